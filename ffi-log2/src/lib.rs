@@ -4,9 +4,19 @@
 //! Work out what needs to be configured inside the DLL to enable the log forwarding.
 //! Create a ffi function that enables the logging in the DLL to be configured (safely).
 //! Creates function in the main that allows creating of the object that is used to configure the DLL funciton.
+//!
+//! The DLL filters records with a runtime filter, a default level plus overrides per target, which can be
+//! changed at any time with [set_level] and [set_target_level]. Each change is passed to the host through
+//! the `set_level` callback of [LogParam], so the `enabled` check of the host passes the records of a
+//! raised level too.
 
 use log::{Level, LevelFilter, Log, Metadata, Record, RecordBuilder};
-use std::{fmt, mem::ManuallyDrop};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    mem::ManuallyDrop,
+    sync::{OnceLock, RwLock},
+};
 
 /// FFI-safe borrowed Rust &str. Can represents `Option<&str>` by setting ptr to null.
 #[repr(C)]
@@ -88,15 +98,25 @@ pub enum ExternCLevel {
 }
 
 impl From<Level> for ExternCLevel {
-    fn from(_myvalue: Level) -> Self {
-        // TODO: Make correct transorm of enums
-        ExternCLevel::Info
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => ExternCLevel::Error,
+            Level::Warn => ExternCLevel::Warn,
+            Level::Info => ExternCLevel::Info,
+            Level::Debug => ExternCLevel::Debug,
+            Level::Trace => ExternCLevel::Trace,
+        }
     }
 }
-impl Into<Level> for ExternCLevel {
-    fn into(self) -> Level {
-        // TODO: Make correct transorm of enums
-        Level::Info
+impl From<ExternCLevel> for Level {
+    fn from(level: ExternCLevel) -> Self {
+        match level {
+            ExternCLevel::Error => Level::Error,
+            ExternCLevel::Warn => Level::Warn,
+            ExternCLevel::Info => Level::Info,
+            ExternCLevel::Debug => Level::Debug,
+            ExternCLevel::Trace => Level::Trace,
+        }
     }
 }
 impl Clone for ExternCLevel {
@@ -125,15 +145,27 @@ pub enum ExternCLevelFilter {
 }
 
 impl From<LevelFilter> for ExternCLevelFilter {
-    fn from(_myvalue: LevelFilter) -> Self {
-        ExternCLevelFilter::Info
-        // TODO: Make correct transorm of enums
+    fn from(level: LevelFilter) -> Self {
+        match level {
+            LevelFilter::Off => ExternCLevelFilter::Off,
+            LevelFilter::Error => ExternCLevelFilter::Error,
+            LevelFilter::Warn => ExternCLevelFilter::Warn,
+            LevelFilter::Info => ExternCLevelFilter::Info,
+            LevelFilter::Debug => ExternCLevelFilter::Debug,
+            LevelFilter::Trace => ExternCLevelFilter::Trace,
+        }
     }
 }
-impl Into<LevelFilter> for ExternCLevelFilter {
-    fn into(self) -> LevelFilter {
-        LevelFilter::Info
-        // TODO: Make correct transorm of enums
+impl From<ExternCLevelFilter> for LevelFilter {
+    fn from(level: ExternCLevelFilter) -> Self {
+        match level {
+            ExternCLevelFilter::Off => LevelFilter::Off,
+            ExternCLevelFilter::Error => LevelFilter::Error,
+            ExternCLevelFilter::Warn => LevelFilter::Warn,
+            ExternCLevelFilter::Info => LevelFilter::Info,
+            ExternCLevelFilter::Debug => LevelFilter::Debug,
+            ExternCLevelFilter::Trace => LevelFilter::Trace,
+        }
     }
 }
 impl Clone for ExternCLevelFilter {
@@ -156,7 +188,7 @@ impl ExternCMetadata {
     /// # Safety
     ///
     /// convert to metadata for use in log functions. Convert from FFI to Metadata
-    pub unsafe fn as_metadata(&self) -> Metadata {
+    pub unsafe fn as_metadata(&self) -> Metadata<'_> {
         let level = self.level;
         let target = self.target.to_str();
         Metadata::builder()
@@ -251,7 +283,7 @@ impl ExternCRecord {
     /// # Safety
     ///
     /// Return the record build for the externCRecord
-    pub unsafe fn as_record_builder(&self) -> RecordBuilder {
+    pub unsafe fn as_record_builder(&self) -> RecordBuilder<'_> {
         let mut builder = Record::builder();
         builder
             // .args(self.message.to_str())
@@ -282,6 +314,9 @@ pub struct LogParam {
     pub flush: extern "C" fn(),
     /// value for the log level
     pub level: ExternCLevelFilter,
+    /// Called with each change of the runtime filter of the DLL: the target, null for the default level,
+    /// the level and whether the override of the target is removed instead. May be null
+    pub set_level: Option<extern "C" fn(RustStr, ExternCLevelFilter, bool)>,
 }
 
 impl fmt::Debug for LogParam {
@@ -295,38 +330,131 @@ impl fmt::Debug for LogParam {
 
 struct DLog;
 
-static mut LOGPARAM: Option<LogParam> = None;
+static LOGPARAM: OnceLock<LogParam> = OnceLock::new();
+
+/// Current levels of the runtime filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLevels {
+    /// Level for targets without an override
+    pub default: LevelFilter,
+    /// Overrides by target. A target also applies to the targets nested below it, eg `hams` to `hams::access`
+    pub targets: BTreeMap<String, LevelFilter>,
+}
+
+impl LogLevels {
+    /// Level applying to a target, from the longest matching override or the default
+    pub fn level(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| {
+                target
+                    .strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// Most verbose level of any target
+    fn max(&self) -> LevelFilter {
+        self.targets.values().copied().fold(self.default, Ord::max)
+    }
+}
+
+static LEVELS: RwLock<LogLevels> = RwLock::new(LogLevels {
+    default: LevelFilter::Info,
+    targets: BTreeMap::new(),
+});
+
+/// Apply a change to the runtime filter and raise or lower the log max level to match
+fn update_levels(update: impl FnOnce(&mut LogLevels)) {
+    let mut levels = LEVELS.write().unwrap_or_else(|e| e.into_inner());
+    update(&mut levels);
+    // Only the DLL owns the max level. A host linking this crate keeps control of its own
+    if LOGPARAM.get().is_some() {
+        log::set_max_level(levels.max());
+    }
+}
+
+/// Pass a change of the runtime filter of the DLL to the host
+fn forward_level(target: Option<&str>, level: Option<LevelFilter>) {
+    if let Some(set_level) = LOGPARAM.get().and_then(|param| param.set_level) {
+        set_level(
+            RustStr::from(target),
+            level.unwrap_or(LevelFilter::Off).into(),
+            level.is_none(),
+        );
+    }
+}
+
+/// Set the level for targets without an override
+pub fn set_level(level: LevelFilter) {
+    update_levels(|levels| levels.default = level);
+    forward_level(None, Some(level));
+}
+
+/// Set the level for a target and the targets nested below it. None removes the override
+pub fn set_target_level(target: &str, level: Option<LevelFilter>) {
+    update_levels(|levels| match level {
+        Some(level) => {
+            levels.targets.insert(target.to_string(), level);
+        }
+        None => {
+            levels.targets.remove(target);
+        }
+    });
+    forward_level(Some(target), level);
+}
+
+/// Current levels of the runtime filter
+pub fn levels() -> LogLevels {
+    LEVELS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn level_enabled(metadata: &Metadata) -> bool {
+    metadata.level()
+        <= LEVELS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .level(metadata.target())
+}
 
 /** init the DLL logging by passing in the references to the implemntation of the logging
+ *
+ * The level in the param is the initial default level of the runtime filter.
  */
 pub fn logger_init(param: LogParam) {
-    let level = param.level;
-    unsafe {
-        if LOGPARAM.is_some() {
-            eprint!("log should only init once");
-            return;
-        }
-        LOGPARAM.replace(param);
+    let level = param.level.into();
+    if LOGPARAM.set(param).is_err() {
+        eprint!("log should only init once");
+        return;
     }
-    if let Err(err) = log::set_logger(&LOGGER).map(|_| log::set_max_level(level.into())) {
+    if let Err(err) = log::set_logger(&LOGGER) {
         eprint!("set logger failed:{}", err);
+        return;
     }
+    // The host already filters at its own level, so only later changes are forwarded
+    update_levels(|levels| levels.default = level);
 }
 
 fn param() -> &'static LogParam {
-    unsafe { LOGPARAM.as_ref() }.unwrap()
+    LOGPARAM.get().expect("logger_init has been called")
 }
 
 /** Log implementation is the definition of the Interfaces used by the log library
  * This struct maps the Logging library API to the FFI provided objects for actual logging.
+ *
+ * Records must pass the runtime filter here and then the enabled check of the host.
  */
 impl Log for DLog {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        let metadata = ExternCMetadata::from(metadata);
-        (param().enabled)(metadata)
+        level_enabled(metadata) && (param().enabled)(ExternCMetadata::from(metadata))
     }
 
     fn log(&self, record: &Record) {
+        if !level_enabled(record.metadata()) {
+            return;
+        }
         let record = ExternCRecord::from(record);
         (param().log)(&record)
     }
@@ -338,13 +466,41 @@ impl Log for DLog {
 
 static LOGGER: DLog = DLog;
 
+/// Levels the DLL raised at runtime, which the host passes whatever its own filter. Targets not raised
+/// are left to the host logger
+static FORWARDED: RwLock<LogLevels> = RwLock::new(LogLevels {
+    default: LevelFilter::Off,
+    targets: BTreeMap::new(),
+});
+
 /// Create a CAPI function for the enabled function. This is used by the Dylib Log
 ///
 /// This function is used in the main to create a CAPI function that can be transported
 /// via LogParam to the SO logger initialisation funtions.
 extern "C" fn enabled(meta: ExternCMetadata) -> bool {
     let metadata = unsafe { meta.as_metadata() };
-    log::logger().enabled(&metadata)
+    let forwarded = FORWARDED
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .level(metadata.target());
+    metadata.level() <= forwarded || log::logger().enabled(&metadata)
+}
+
+/// Create a CAPI function for the set_level function. This is used by the Dylib Log
+///
+/// Records the levels changed in the DLL so that the enabled check of the host follows them.
+extern "C" fn set_level_forwarded(target: RustStr, level: ExternCLevelFilter, remove: bool) {
+    let target = unsafe { target.to_opt_str() };
+    let mut forwarded = FORWARDED.write().unwrap_or_else(|e| e.into_inner());
+    match (target, remove) {
+        (None, _) => forwarded.default = level.into(),
+        (Some(target), false) => {
+            forwarded.targets.insert(target.to_string(), level.into());
+        }
+        (Some(target), true) => {
+            forwarded.targets.remove(target);
+        }
+    }
 }
 
 /// Create a CAPI function for the log function. This is used by the Dylib Log
@@ -354,12 +510,8 @@ extern "C" fn enabled(meta: ExternCMetadata) -> bool {
 extern "C" fn log(ext_record: &ExternCRecord) {
     let mut record_builder = unsafe { ext_record.as_record_builder() };
 
-    match format_args!("{}", unsafe { ext_record.message.to_str() }) {
-        args => {
-            let record = record_builder.args(args).build();
-            log::logger().log(&record);
-        }
-    }
+    let message = unsafe { ext_record.message.to_str() };
+    log::logger().log(&record_builder.args(format_args!("{message}")).build());
 }
 
 /// Create a CAPI function for the flush function. This is used by the Dylib Log
@@ -378,5 +530,70 @@ pub fn log_param() -> LogParam {
         log,
         flush,
         level: log::max_level().into(),
+        set_level: Some(set_level_forwarded),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_conversion() {
+        for level in LevelFilter::iter() {
+            assert_eq!(LevelFilter::from(ExternCLevelFilter::from(level)), level);
+        }
+        for level in Level::iter() {
+            assert_eq!(Level::from(ExternCLevel::from(level)), level);
+        }
+    }
+
+    #[test]
+    fn test_target_levels() {
+        let levels = LogLevels {
+            default: LevelFilter::Warn,
+            targets: BTreeMap::from([
+                ("hams".to_string(), LevelFilter::Info),
+                ("hams::access".to_string(), LevelFilter::Debug),
+                ("hams::access::probe".to_string(), LevelFilter::Off),
+            ]),
+        };
+        assert_eq!(levels.level("other"), LevelFilter::Warn);
+        assert_eq!(levels.level("hamster"), LevelFilter::Warn);
+        assert_eq!(levels.level("hams"), LevelFilter::Info);
+        assert_eq!(levels.level("hams::check"), LevelFilter::Info);
+        assert_eq!(levels.level("hams::access"), LevelFilter::Debug);
+        assert_eq!(levels.level("hams::access::probe"), LevelFilter::Off);
+        assert_eq!(levels.max(), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_forwarded_levels() {
+        let metadata = |level, target| ExternCMetadata {
+            level,
+            target: RustStr::from(target),
+        };
+        // No logger is set in the tests, so the host passes nothing of its own
+        assert!(!enabled(metadata(ExternCLevel::Debug, "forwarded")));
+
+        set_level_forwarded("forwarded".into(), ExternCLevelFilter::Debug, false);
+        assert!(enabled(metadata(ExternCLevel::Debug, "forwarded::inner")));
+        assert!(!enabled(metadata(ExternCLevel::Trace, "forwarded")));
+        assert!(!enabled(metadata(ExternCLevel::Error, "other")));
+
+        set_level_forwarded("forwarded".into(), ExternCLevelFilter::Off, true);
+        assert!(!enabled(metadata(ExternCLevel::Debug, "forwarded")));
+    }
+
+    #[test]
+    fn test_set_levels() {
+        set_level(LevelFilter::Error);
+        set_target_level("sample", Some(LevelFilter::Trace));
+        let current = levels();
+        assert_eq!(current.default, LevelFilter::Error);
+        assert_eq!(current.level("sample::inner"), LevelFilter::Trace);
+
+        set_target_level("sample", None);
+        assert!(levels().targets.is_empty());
     }
 }
@@ -6,20 +6,42 @@ use ffi_helpers::error_handling;
 use libc::{c_char, c_int};
 use thiserror::Error;
 
-/// FFI Enum for error handling mapping to C return codes
+/// Return codes of the C API. Success is 1 and NULL pointers are 0, as returned by the checks of
+/// `ffi_helpers`, and every error is negative. The message of the error is read with
+/// `hams_last_error_message`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FFIEnum {
     /// No Error
     Success = 1,
     /// Null error
     NullError = 0,
-    /// Unknown error
+    /// Unknown error, including a panic
     UnknownError = -1,
     /// CString error
     CStringError = -2,
     /// AlreadyRunning error
     AlreadyRunning = -3,
-    // NotRunning error
+    /// NotRunning error
     NotRunning = -4,
+    /// An item such as a probe or collector with the same name is already registered
+    AlreadyExists = -5,
+    /// A named item such as a probe, check or collector does not exist
+    NotFound = -6,
+    /// An argument such as a name, label or configuration is not valid
+    InvalidArgument = -7,
+    /// A check such as preflight or shutdown, or a probe, did not pass
+    CheckFailed = -8,
+    /// A callback of the host failed or gave no valid output
+    CallbackFailed = -9,
+    /// The service was cancelled
+    Cancelled = -10,
+    /// An IO error, or an error starting, signalling or joining the service thread
+    ThreadError = -11,
+    /// A lock was poisoned by a panic while it was held
+    Poisoned = -12,
+    /// The caller is not authenticated
+    Unauthorized = -13,
 }
 
 /// Allow conversion from i32 to FFIEnum (C return codes to FFIEnum)
@@ -34,6 +56,15 @@ impl TryFrom<i32> for FFIEnum {
             x if x == FFIEnum::CStringError as i32 => Ok(FFIEnum::CStringError),
             x if x == FFIEnum::AlreadyRunning as i32 => Ok(FFIEnum::AlreadyRunning),
             x if x == FFIEnum::NotRunning as i32 => Ok(FFIEnum::NotRunning),
+            x if x == FFIEnum::AlreadyExists as i32 => Ok(FFIEnum::AlreadyExists),
+            x if x == FFIEnum::NotFound as i32 => Ok(FFIEnum::NotFound),
+            x if x == FFIEnum::InvalidArgument as i32 => Ok(FFIEnum::InvalidArgument),
+            x if x == FFIEnum::CheckFailed as i32 => Ok(FFIEnum::CheckFailed),
+            x if x == FFIEnum::CallbackFailed as i32 => Ok(FFIEnum::CallbackFailed),
+            x if x == FFIEnum::Cancelled as i32 => Ok(FFIEnum::Cancelled),
+            x if x == FFIEnum::ThreadError as i32 => Ok(FFIEnum::ThreadError),
+            x if x == FFIEnum::Poisoned as i32 => Ok(FFIEnum::Poisoned),
+            x if x == FFIEnum::Unauthorized as i32 => Ok(FFIEnum::Unauthorized),
             x if x >= 0 => Err(HamsError::NotError(x)),
             _ => Err(HamsError::Unknown),
         }
@@ -42,10 +73,37 @@ impl TryFrom<i32> for FFIEnum {
 
 impl<T: AsRef<HamsError>> From<T> for FFIEnum {
     fn from(err: T) -> Self {
+        // Deliberately without a wildcard so a new error must be given a code
         match err.as_ref() {
-            HamsError::CStringToString(_) => FFIEnum::CStringError,
-            HamsError::NulError(_) => FFIEnum::NullError,
-            _ => FFIEnum::UnknownError,
+            HamsError::CStringToString(_) | HamsError::Utf8Error(_) | HamsError::NulError(_) => {
+                FFIEnum::CStringError
+            }
+            HamsError::AlreadyRunning => FFIEnum::AlreadyRunning,
+            HamsError::NotRunning => FFIEnum::NotRunning,
+            HamsError::AlreadyExists(_) => FFIEnum::AlreadyExists,
+            HamsError::NotFound(_) => FFIEnum::NotFound,
+            HamsError::InvalidArgument(_)
+            | HamsError::JsonError(_)
+            | HamsError::TryFromIntError(_)
+            | HamsError::TlsError(_) => FFIEnum::InvalidArgument,
+            HamsError::ProbeNotGood(_) | HamsError::PreflightCheck | HamsError::ShutdownCheck => {
+                FFIEnum::CheckFailed
+            }
+            HamsError::CallbackError | HamsError::CollectorFailed(_) => FFIEnum::CallbackFailed,
+            HamsError::Cancelled => FFIEnum::Cancelled,
+            HamsError::IoError(_)
+            | HamsError::JoinError(_)
+            | HamsError::JoinError2
+            | HamsError::SendError(_)
+            | HamsError::NoThread => FFIEnum::ThreadError,
+            HamsError::PoisonError => FFIEnum::Poisoned,
+            HamsError::Unauthorized => FFIEnum::Unauthorized,
+            HamsError::NotError(_)
+            | HamsError::FFIErrorBufferNotBigEnough
+            | HamsError::SystemTimeError(_)
+            | HamsError::FFIError(_)
+            | HamsError::Message(_)
+            | HamsError::Unknown => FFIEnum::UnknownError,
         }
     }
 }
@@ -67,6 +125,15 @@ pub enum HamsError {
     /// Probe is not good
     #[error("Probe is not good")]
     ProbeNotGood(String),
+    /// A named item such as a probe does not exist
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// A named item such as a collector is already registered
+    #[error("Already exists: {0}")]
+    AlreadyExists(String),
+    /// An argument such as a metric name or label is not valid
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
     /// Error when service is not running
     #[error("Service is not running")]
     NotRunning,
@@ -85,6 +152,9 @@ pub enum HamsError {
     /// Error when running callback
     #[error("Error calling callback")]
     CallbackError,
+    /// A collector gave no valid output for a scrape of the metrics
+    #[error("Collector {0} failed")]
+    CollectorFailed(String),
 
     /// Error when trying to read FFI error from buffer
     #[error("FFI error buffer wasn't big enough!")]
@@ -124,6 +194,15 @@ pub enum HamsError {
     /// A Nul was found on FFI pointer
     #[error("NulError from FFI pointer")]
     NulError(#[from] NulError),
+    /// Error when serialising or deserialising JSON
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    /// Caller is not authenticated for the route
+    #[error("Authentication required")]
+    Unauthorized,
+    /// Error loading or using the TLS configuration
+    #[error("TLS error: {0}")]
+    TlsError(String),
     /// An error with unknown source
     #[error("Unknown error")]
     Unknown,
@@ -153,6 +232,33 @@ impl HamsError {
     }
 }
 
+/// The code of the last error, which stays set for `hams_last_error_message`. Errors that are not a
+/// HamsError, such as a panic, are unknown
+pub(crate) fn last_error_code() -> i32 {
+    match error_handling::take_last_error() {
+        Some(err) => {
+            let code = err
+                .downcast_ref::<HamsError>()
+                .map_or(FFIEnum::UnknownError, FFIEnum::from);
+            error_handling::update_last_error(err);
+            code as i32
+        }
+        None => FFIEnum::UnknownError as i32,
+    }
+}
+
+/// Like `ffi_helpers::catch_panic!` for functions returning a code, except an error returns the
+/// FFIEnum of the error rather than 0, which is kept for NULL pointers
+macro_rules! catch_code {
+    ($($tokens:tt)*) => {{
+        match ffi_helpers::catch_panic(|| { $($tokens)* }) {
+            Ok(value) => value,
+            Err(()) => return $crate::error::last_error_code(),
+        }
+    }};
+}
+pub(crate) use catch_code;
+
 /// Convert FFI error messages to Result
 ///
 /// When functions set error_msg during FFI calls the calling function can then use this
@@ -192,7 +298,7 @@ mod tests {
     use ffi_helpers::{catch_panic, error_handling::clear_last_error};
     use libc::c_int;
 
-    use super::{ffi_error_to_result, HamsError};
+    use super::{ffi_error_to_result, last_error_code, FFIEnum, HamsError};
 
     #[no_mangle]
     unsafe extern "C" fn set_last_error() -> c_int {
@@ -212,6 +318,47 @@ mod tests {
         )
     }
 
+    #[no_mangle]
+    unsafe extern "C" fn some_coded_operation() -> c_int {
+        catch_code!(Err(HamsError::NotFound("probe".to_string()))?)
+    }
+
+    #[test]
+    fn test_codes() {
+        for code in -13..=1 {
+            assert_eq!(FFIEnum::try_from(code).unwrap() as i32, code);
+        }
+        assert!(matches!(FFIEnum::try_from(2), Err(HamsError::NotError(2))));
+        assert!(matches!(FFIEnum::try_from(-14), Err(HamsError::Unknown)));
+
+        assert_eq!(
+            FFIEnum::from(HamsError::AlreadyExists("probe".to_string())),
+            FFIEnum::AlreadyExists
+        );
+        assert_eq!(
+            FFIEnum::from(&HamsError::InvalidArgument("name".to_string())),
+            FFIEnum::InvalidArgument
+        );
+        assert_eq!(FFIEnum::from(HamsError::PoisonError), FFIEnum::Poisoned);
+    }
+
+    #[test]
+    fn test_catch_code() {
+        clear_last_error();
+        assert_eq!(last_error_code(), FFIEnum::UnknownError as i32);
+
+        assert_eq!(unsafe { some_coded_operation() }, FFIEnum::NotFound as i32);
+        // The code is read without clearing the message
+        assert_eq!(last_error_code(), FFIEnum::NotFound as i32);
+        assert_eq!(
+            ffi_error_to_result().unwrap_err().to_string(),
+            "FFI Error: Not found: probe"
+        );
+
+        unsafe { some_fallible_operation() };
+        assert_eq!(last_error_code(), FFIEnum::UnknownError as i32);
+    }
+
     #[test]
     fn test_read_last_error_with_handler() {
         clear_last_error();
@@ -1,32 +1,129 @@
-use std::{collections::HashSet, sync::Arc, time::SystemTime};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
-use futures::future::join_all;
+use futures::{
+    future::{join_all, BoxFuture, Shared},
+    FutureExt,
+};
+use log::{info, warn};
 
 use serde::Serialize;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
+use utoipa::ToSchema;
 
-use crate::probe::{AsyncHealthProbe, HealthProbeResult};
+use crate::{
+    error::HamsError,
+    hams::{
+        config::{CheckConfig, LimitPolicy, ProbeConfig},
+        events::{status, EventKind, Events, HamsEvent, UNKNOWN},
+        fault::{delay, Fault, FaultKind, FaultReply, MAX_LATENCY},
+        info::rfc3339,
+        maintenance::Maintenance,
+    },
+    metrics::health::HealthMetrics,
+    probe::{manual::Manual, AsyncHealthProbe, FFIProbe, HealthProbeResult},
+};
 
 /// Reply structure to return from a health check
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct HealthCheckResult {
+    /// Name of the check (alive or ready)
     pub(crate) name: String,
+    /// True when every selected probe passed
     pub(crate) valid: bool,
+    /// Why the check failed regardless of its probes, eg maintenance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<String>,
+    /// Result of each probe, only present on verbose requests
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) details: Option<Vec<HealthProbeResult>>,
+    /// True when the result is made of the latest result of each probe because too many checks were running
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) stale: bool,
+}
+
+impl HealthCheckResult {
+    /// Names of the probes that did not pass
+    pub(crate) fn failing(&self) -> impl Iterator<Item = &str> {
+        self.details
+            .iter()
+            .flatten()
+            .filter(|probe| !probe.valid)
+            .map(|probe| probe.name.as_str())
+    }
+
+    /// Keep or drop the probe details from the result
+    pub(crate) fn with_details(mut self, verbose: bool) -> Self {
+        if !verbose {
+            self.details = None;
+        }
+        self
+    }
+}
+
+/// Last result of a probe
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct LastResult {
+    /// Return value of the probe
+    pub(crate) valid: bool,
+    /// When the probe was checked, in RFC 3339
+    pub(crate) time: String,
+    /// Explanation given by the probe
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<String>,
+}
+
+/// A probe as listed by the admin API
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct ProbeDescription {
+    /// Name of the probe
+    pub(crate) name: String,
+    /// Type of the probe, eg manual or kick
+    #[serde(rename = "type")]
+    pub(crate) probe_type: String,
+    /// Configuration of the probe, which depends on its type
+    #[schema(value_type = Object)]
+    pub(crate) config: serde_json::Value,
+    /// Result of the latest check of the probe, if it has been checked
+    pub(crate) last: Option<LastResult>,
+    /// Description of a fault injected into the probe
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) fault: Option<String>,
+}
+
+/// The probes of a check as listed by the admin API
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct CheckProbes {
+    /// Name of the check (alive or ready)
+    pub(crate) name: String,
+    /// Probes of the check sorted by name
+    pub(crate) probes: Vec<ProbeDescription>,
+}
+
+/// Select which probes of a [HealthCheck] take part in a check.
+///
+/// An empty include list selects every probe. Excludes are applied after includes.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ProbeFilter {
+    /// Only check probes with these names
+    pub include: Vec<String>,
+    /// Skip probes with these names
+    pub exclude: Vec<String>,
 }
 
-impl warp::Reply for HealthCheckResult {
-    fn into_response(self) -> warp::reply::Response {
-        warp::reply::with_status(
-            warp::reply::json(&self),
-            if self.valid {
-                warp::http::StatusCode::OK
-            } else {
-                warp::http::StatusCode::NOT_ACCEPTABLE
-            },
-        )
-        .into_response()
+impl ProbeFilter {
+    /// Return true if the probe with this name should be checked
+    pub fn matches(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|include| include == name))
+            && !self.exclude.iter().any(|exclude| exclude == name)
+    }
+
+    /// Every probe name given to the filter
+    fn names(&self) -> impl Iterator<Item = &String> {
+        self.include.iter().chain(&self.exclude)
     }
 }
 
@@ -38,9 +135,33 @@ impl warp::Reply for HealthCheckResult {
 #[derive(Debug, Clone)]
 pub struct HealthCheck {
     pub name: String,
+    /// Response policy used when this check is served over HTTP
+    pub(crate) config: CheckConfig,
     pub(crate) probes: Arc<Mutex<HashSet<Box<dyn AsyncHealthProbe>>>>,
+    /// Last result of each probe by name
+    last: Arc<std::sync::Mutex<HashMap<String, LastResult>>>,
+    /// Maintenance which fails the check regardless of its probes
+    maintenance: Arc<std::sync::Mutex<Option<Maintenance>>>,
+    /// Faults injected into probes by probe name
+    faults: Arc<std::sync::Mutex<HashMap<String, Fault>>>,
+    /// Status of the latest check of every probe, None before the first
+    status: Arc<std::sync::Mutex<Option<bool>>>,
+    /// Where transitions of the check and its probes are published
+    events: Events,
+    /// Where the results of the check and its probes are exported as metrics
+    metrics: HealthMetrics,
+    /// Where evaluations of the check are recorded as spans, when exported
+    #[cfg(feature = "otel")]
+    spans: Option<super::otel::Spans>,
+    /// Evaluations running now by the probes they select, shared by concurrent requests
+    inflight: Arc<std::sync::Mutex<HashMap<ProbeFilter, Evaluation>>>,
+    /// Permits for evaluations when the number running at once is limited
+    permits: Option<Arc<Semaphore>>,
 }
 
+/// An evaluation of a check which every request for the same probes waits on
+type Evaluation = Shared<BoxFuture<'static, HealthCheckResult>>;
+
 // TODO: This does not look right to add Send to HealthCheck
 unsafe impl Send for HealthCheck {}
 
@@ -49,8 +170,57 @@ impl HealthCheck {
     pub fn new<S: Into<String>>(name: S) -> Self {
         Self {
             name: name.into(),
+            config: CheckConfig::default(),
             probes: Arc::new(Mutex::new(HashSet::new())),
+            last: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            maintenance: Arc::new(std::sync::Mutex::new(None)),
+            faults: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            status: Arc::new(std::sync::Mutex::new(None)),
+            events: Events::default(),
+            metrics: HealthMetrics::default(),
+            #[cfg(feature = "otel")]
+            spans: None,
+            inflight: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            permits: None,
+        }
+    }
+
+    /// Set the response policy for this HealthCheck
+    pub fn with_config(mut self, config: CheckConfig) -> Self {
+        self.permits = config
+            .max_concurrent
+            .map(|max_concurrent| Arc::new(Semaphore::new(max_concurrent)));
+        // Not yet shared, so the lock is free and this can be called from within the runtime
+        if let Ok(mut probes) = self.probes.try_lock() {
+            for probe in &config.probes {
+                match probe {
+                    ProbeConfig::Manual { name, valid } => {
+                        probes.insert(FFIProbe::from(Manual::new(name.clone(), *valid)).into())
+                    }
+                };
+            }
         }
+        self.config = config;
+        self
+    }
+
+    /// Publish transitions to events shared with the rest of HaMS
+    pub(crate) fn with_events(mut self, events: Events) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Export results to metrics shared with the rest of HaMS
+    pub(crate) fn with_metrics(mut self, metrics: HealthMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Record evaluations as spans shared with the rest of HaMS
+    #[cfg(feature = "otel")]
+    pub(crate) fn with_spans(mut self, spans: super::otel::Spans) -> Self {
+        self.spans = Some(spans);
+        self
     }
 
     /// Insert a probe into the HealthCheck
@@ -65,62 +235,492 @@ impl HealthCheck {
 
     /// Remove a probe from the HealthCheck
     pub(crate) fn remove(&self, probe: &Box<dyn AsyncHealthProbe>) -> bool {
+        self.forget(probe.as_ref());
         self.probes.blocking_lock().remove(probe)
     }
 
     /// Remove a probe from the HealthCheck using an async safe lock
     pub(crate) async fn remove_async(&self, probe: &Box<dyn AsyncHealthProbe>) -> bool {
+        self.forget(probe.as_ref());
         self.probes.lock().await.remove(probe)
     }
 
-    /// Check the health of the HealthCheck
-    pub async fn check(&self, time: SystemTime) -> HealthCheckResult {
-        let checks = self.probes.lock().await;
+    /// Set or clear the maintenance of the check
+    pub(crate) fn set_maintenance(
+        &self,
+        maintenance: Option<Maintenance>,
+    ) -> Result<(), HamsError> {
+        *self
+            .maintenance
+            .lock()
+            .map_err(|_e| HamsError::PoisonError)? = maintenance;
+        Ok(())
+    }
 
-        // TODO: The use of std Mutex (MutexGuard cannot be sent over an async bondary)
-        // Can we code this so that the MutexGuard is not sent over the async boundary? OR do we need to use the tokio::Mutex
-        // Downside of that is that we need to use mutex:: blocking_lock() where executing on the synchronous
-        // NOTE: Did attempt to clone the AsyncHealthProbes to use outside the mutex BUT that does not work as the AsyncHealthProbe is dyn so cannot be Sized as it is erased.
+    /// Maintenance of the check at the given time. Expired maintenance is cleared
+    pub(crate) fn maintenance(&self, time: SystemTime) -> Option<Maintenance> {
+        let mut maintenance = self.maintenance.lock().ok()?;
+        if maintenance.as_ref().is_some_and(|m| m.expired(time)) {
+            if let Some(expired) = maintenance.take() {
+                info!("Maintenance of {} expired: {}", self.name, expired.reason);
+            }
+        }
+        maintenance.clone()
+    }
 
-        // TODOL Consider to buffer this so that we run multiple checks in parallel
-        let valid = checks
+    /// Inject a fault into the named probe, or revert the probe to its real check with None
+    pub(crate) async fn set_fault(
+        &self,
+        name: &str,
+        fault: Option<Fault>,
+    ) -> Result<(), HamsError> {
+        if find(&*self.probes.lock().await, name).is_none() {
+            return Err(self.not_found(name));
+        }
+        let mut faults = self.faults.lock().map_err(|_e| HamsError::PoisonError)?;
+        match fault {
+            Some(fault) => {
+                info!("Fault on {name} of {}: {}", self.name, fault.describe());
+                faults.insert(name.to_string(), fault);
+            }
+            None => {
+                if faults.remove(name).is_some() {
+                    info!("Fault on {name} of {} reverted", self.name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Active fault of a probe at the given time. Expired faults are reverted
+    fn fault(&self, name: &str, time: SystemTime) -> Option<Fault> {
+        let mut faults = self.faults.lock().ok()?;
+        if faults.get(name).is_some_and(|fault| fault.expired(time)) {
+            faults.remove(name);
+            info!("Fault on {name} of {} expired", self.name);
+        }
+        faults.get(name).cloned()
+    }
+
+    /// Active faults of the check at the given time
+    pub(crate) fn faults(&self, time: SystemTime) -> Vec<FaultReply> {
+        let Ok(mut faults) = self.faults.lock() else {
+            return Vec::new();
+        };
+        faults.retain(|_, fault| !fault.expired(time));
+        let mut replies: Vec<_> = faults
             .iter()
-            .map(|probe| async { probe.check(time).await.unwrap_or(false) });
+            .map(|(probe, fault)| FaultReply {
+                check: self.name.clone(),
+                probe: probe.clone(),
+                fault: fault.kind.clone(),
+                until: rfc3339(fault.until),
+            })
+            .collect();
+        replies.sort_by(|a, b| a.probe.cmp(&b.probe));
+        replies
+    }
+
+    /// Check a probe, applying any fault injected into it. Also returns the latency injected, which the
+    /// caller waits out once it has released the probes so the delay cannot hold up changes to them
+    async fn run_probe(
+        &self,
+        name: String,
+        probe: &dyn AsyncHealthProbe,
+        time: SystemTime,
+    ) -> (HealthProbeResult, Duration) {
+        let fault = self.fault(&name, time);
+        let started = Instant::now();
+        let mut latency = Duration::ZERO;
+        let (valid, message) = match fault.as_ref().map(|fault| &fault.kind) {
+            Some(FaultKind::Fail) | Some(FaultKind::Error { .. }) => (false, None),
+            Some(FaultKind::Pass) => (true, None),
+            Some(FaultKind::Latency { latency_ms }) => {
+                latency = Duration::from_millis(*latency_ms).min(MAX_LATENCY);
+                self.timed_check(probe, time).await
+            }
+            None => self.timed_check(probe, time).await,
+        };
+        self.metrics.probe_duration(
+            &self.name,
+            &name,
+            (started.elapsed() + latency).as_secs_f64(),
+        );
+        let result = HealthProbeResult {
+            name,
+            valid,
+            fault: fault.map(|fault| fault.describe()),
+            message,
+        };
+        (result, latency)
+    }
+
+    /// Check a probe, giving up after the timeout of the check. Returns the result with the explanation
+    /// of the probe. Without a runtime the probe is checked on the calling thread and cannot time out
+    async fn timed_check(
+        &self,
+        probe: &dyn AsyncHealthProbe,
+        time: SystemTime,
+    ) -> (bool, Option<String>) {
+        if tokio::runtime::Handle::try_current().is_err() {
+            return (probe.check(time).await.unwrap_or(false), probe.message());
+        }
+        match tokio::time::timeout(self.config.timeout, probe.check(time)).await {
+            Ok(valid) => (valid.unwrap_or(false), probe.message()),
+            Err(_) => (
+                false,
+                Some(format!("timed out after {:?}", self.config.timeout)),
+            ),
+        }
+    }
+
+    /// Drop the last result and any fault of a probe
+    fn forget(&self, probe: &dyn AsyncHealthProbe) {
+        let Ok(name) = probe.name() else {
+            return;
+        };
+        if let Ok(mut last) = self.last.lock() {
+            last.remove(&name);
+        }
+        if let Ok(mut faults) = self.faults.lock() {
+            faults.remove(&name);
+        }
+        self.metrics.forget_probe(&self.name, &name);
+    }
+
+    /// Keep the results of a check as the last result of each probe and publish the probes that changed
+    fn record(&self, results: &[HealthProbeResult], time: SystemTime) {
+        let Ok(mut last) = self.last.lock() else {
+            return;
+        };
+        let checked = rfc3339(time);
+        for result in results {
+            let previous = last.insert(
+                result.name.clone(),
+                LastResult {
+                    valid: result.valid,
+                    time: checked.clone(),
+                    message: result.message.clone(),
+                },
+            );
+            let unchanged = previous
+                .as_ref()
+                .is_some_and(|previous| previous.valid == result.valid);
+            self.metrics.probe(
+                &self.name,
+                &result.name,
+                result.valid,
+                previous.is_some() && !unchanged,
+            );
+            if unchanged {
+                continue;
+            }
+            let old = previous.map_or(UNKNOWN, |previous| status(previous.valid));
+            let mut event = HamsEvent::new(EventKind::Probe, time, old, status(result.valid));
+            event.check = Some(self.name.clone());
+            event.probe = Some(result.name.clone());
+            event.reason = result.fault.clone();
+            self.events.send(event);
+        }
+    }
 
-        let valids = join_all(valid).await;
-        let valid = valids.into_iter().all(|check| check);
+    /// Keep the status of a check of every probe and publish it if it changed
+    fn record_status(&self, result: &HealthCheckResult, time: SystemTime) {
+        let Ok(mut status_guard) = self.status.lock() else {
+            return;
+        };
+        let previous = status_guard.replace(result.valid);
+        self.metrics.check(&self.name, result.valid);
+        if previous == Some(result.valid) {
+            return;
+        }
+        let old = previous.map_or(UNKNOWN, status);
+        let mut event = HamsEvent::new(EventKind::Check, time, old, status(result.valid));
+        event.check = Some(self.name.clone());
+        event.reason = result.reason.clone().or_else(|| {
+            let failing: Vec<_> = result.failing().collect();
+            (!failing.is_empty()).then(|| format!("failing: {}", failing.join(", ")))
+        });
+        self.events.send(event);
+    }
+
+    /// Describe a probe with its last result
+    fn describe_probe(&self, probe: &dyn AsyncHealthProbe) -> ProbeDescription {
+        let name = probe.name().unwrap_or("Unknown".to_string());
+        let mut config = probe
+            .describe()
+            .unwrap_or_else(|e| serde_json::json!({ "error": e.to_string() }));
+        let probe_type = config
+            .as_object_mut()
+            .and_then(|config| config.remove("type"))
+            .and_then(|probe_type| probe_type.as_str().map(str::to_string))
+            .unwrap_or("unknown".to_string());
+        let last = self
+            .last
+            .lock()
+            .ok()
+            .and_then(|last| last.get(&name).cloned());
+        let fault = self
+            .fault(&name, SystemTime::now())
+            .map(|fault| fault.describe());
+
+        ProbeDescription {
+            name,
+            probe_type,
+            config,
+            last,
+            fault,
+        }
+    }
+
+    /// Describe every probe of the HealthCheck
+    pub(crate) async fn describe(&self) -> CheckProbes {
+        let mut probes: Vec<_> = self
+            .probes
+            .lock()
+            .await
+            .iter()
+            .map(|probe| self.describe_probe(probe.as_ref()))
+            .collect();
+        probes.sort_by(|a, b| a.name.cmp(&b.name));
 
-        HealthCheckResult {
+        CheckProbes {
             name: self.name.clone(),
-            valid,
-            details: None,
+            probes,
         }
     }
 
+    /// Force the result of the named probe, which is only supported by manual probes
+    pub(crate) async fn set_valid(
+        &self,
+        name: &str,
+        valid: bool,
+    ) -> Result<ProbeDescription, HamsError> {
+        let probes = self.probes.lock().await;
+        let probe = find(&probes, name).ok_or_else(|| self.not_found(name))?;
+        if !probe.set_valid(valid)? {
+            return Err(HamsError::InvalidArgument(format!(
+                "Probe {name} cannot be enabled or disabled"
+            )));
+        }
+        Ok(self.describe_probe(probe))
+    }
+
+    /// Check only the named probe
+    pub(crate) async fn check_probe(
+        &self,
+        name: &str,
+        time: SystemTime,
+    ) -> Result<HealthProbeResult, HamsError> {
+        let probes = self.probes.lock().await;
+        let probe = find(&probes, name).ok_or_else(|| self.not_found(name))?;
+        let (result, latency) = self.run_probe(name.to_string(), probe, time).await;
+        drop(probes);
+        delay(latency).await;
+        self.record(std::slice::from_ref(&result), time);
+        Ok(result)
+    }
+
+    fn not_found(&self, name: &str) -> HamsError {
+        HamsError::NotFound(format!("probe {name} in {}", self.name))
+    }
+
+    /// Check the health of the HealthCheck
+    pub async fn check(&self, time: SystemTime) -> HealthCheckResult {
+        self.check_with(time, &ProbeFilter::default(), false).await
+    }
+
     /// Check the health of the HealthCheck and return a vector of results of type [HealthProbeResult]
     pub async fn check_verbose(&self, time: SystemTime) -> HealthCheckResult {
+        self.check_with(time, &ProbeFilter::default(), true).await
+    }
+
+    /// Check the health of the probes selected by the [ProbeFilter].
+    ///
+    /// The probe details are always collected so the caller can report which probes failed, they are only
+    /// retained on the result when verbose is requested. Concurrent checks of the same probes share one
+    /// evaluation, which uses the time of the first.
+    pub async fn check_with(
+        &self,
+        time: SystemTime,
+        filter: &ProbeFilter,
+        verbose: bool,
+    ) -> HealthCheckResult {
+        let result = match self.evaluation(time, filter, false) {
+            Some(evaluation) => evaluation.await,
+            None => self.evaluate(time, filter.clone()).await,
+        };
+        result.with_details(verbose)
+    }
+
+    /// Reject a filter naming a probe the check does not have. Otherwise a mistyped name selects no
+    /// probes and the check passes
+    pub(crate) async fn validate_filter(&self, filter: &ProbeFilter) -> Result<(), HamsError> {
+        if filter.names().next().is_none() {
+            return Ok(());
+        }
+        let probes = self.probes.lock().await;
+        match filter.names().find(|name| find(&probes, name).is_none()) {
+            Some(name) => Err(self.not_found(name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check the health of the probes selected by the [ProbeFilter] within the concurrency limit.
+    ///
+    /// When the limit is reached the reply follows the configured [LimitPolicy]. None when the request is
+    /// rejected.
+    pub(crate) async fn try_check_with(
+        &self,
+        time: SystemTime,
+        filter: &ProbeFilter,
+        verbose: bool,
+    ) -> Option<HealthCheckResult> {
+        let result = match self.evaluation(time, filter, true) {
+            Some(evaluation) => evaluation.await,
+            None => {
+                warn!(
+                    "Check {} is running {} evaluations, the limit",
+                    self.name,
+                    self.config.max_concurrent.unwrap_or_default()
+                );
+                match self.config.on_limit {
+                    LimitPolicy::Reject => return None,
+                    LimitPolicy::Stale => self.stale(time, filter)?,
+                }
+            }
+        };
+        Some(result.with_details(verbose))
+    }
+
+    /// The evaluation running for the probes selected by the filter, or a new one. None when a new one
+    /// is needed but the limit is respected and reached
+    fn evaluation(
+        &self,
+        time: SystemTime,
+        filter: &ProbeFilter,
+        limited: bool,
+    ) -> Option<Evaluation> {
+        let mut inflight = self.inflight.lock().ok()?;
+        if let Some(evaluation) = inflight.get(filter) {
+            return Some(evaluation.clone());
+        }
+
+        let permit = match (&self.permits, limited) {
+            (Some(permits), true) => Some(permits.clone().try_acquire_owned().ok()?),
+            _ => None,
+        };
+        let check = self.clone();
+        let key = filter.clone();
+        let evaluation = async move {
+            let result = check.evaluate(time, key.clone()).await;
+            if let Ok(mut inflight) = check.inflight.lock() {
+                inflight.remove(&key);
+            }
+            drop(permit);
+            result
+        }
+        .boxed()
+        .shared();
+        inflight.insert(filter.clone(), evaluation.clone());
+        Some(evaluation)
+    }
+
+    /// The latest result of each selected probe as a check result, None if none has been checked yet.
+    ///
+    /// The probes are not locked as a running evaluation holds them, so probes that have never been
+    /// checked are left out.
+    fn stale(&self, time: SystemTime, filter: &ProbeFilter) -> Option<HealthCheckResult> {
+        let mut details: Vec<_> = self
+            .last
+            .lock()
+            .ok()?
+            .iter()
+            .filter(|(name, _)| filter.matches(name))
+            .map(|(name, last)| HealthProbeResult {
+                name: name.clone(),
+                valid: last.valid,
+                fault: None,
+                message: last.message.clone(),
+            })
+            .collect();
+        if details.is_empty() {
+            return None;
+        }
+        details.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let reason = self
+            .maintenance(time)
+            .map(|maintenance| format!("maintenance: {}", maintenance.reason));
+        Some(HealthCheckResult {
+            name: self.name.clone(),
+            valid: reason.is_none() && details.iter().all(|probe| probe.valid),
+            reason,
+            details: Some(details),
+            stale: true,
+        })
+    }
+
+    /// Run the probes selected by the filter and record the results
+    async fn evaluate(&self, time: SystemTime, filter: ProbeFilter) -> HealthCheckResult {
+        let filter = &filter;
         let my_probes = self.probes.lock().await;
 
+        // TODO: The use of std Mutex (MutexGuard cannot be sent over an async bondary)
+        // Can we code this so that the MutexGuard is not sent over the async boundary? OR do we need to use the tokio::Mutex
+        // Downside of that is that we need to use mutex:: blocking_lock() where executing on the synchronous
+        // NOTE: Did attempt to clone the AsyncHealthProbes to use outside the mutex BUT that does not work as the AsyncHealthProbe is dyn so cannot be Sized as it is erased.
         let checks: Vec<_> = my_probes
             .iter()
-            .map(|probe| async {
-                HealthProbeResult {
-                    name: probe.name().unwrap_or("Unknown".to_string()),
-                    valid: probe.check(time).await.unwrap_or(false),
-                }
+            .filter_map(|probe| {
+                let name = probe.name().unwrap_or("Unknown".to_string());
+                filter.matches(&name).then_some((name, probe))
+            })
+            .map(|(name, probe)| async move {
+                let started = SystemTime::now();
+                let (result, latency) = self.run_probe(name, probe.as_ref(), time).await;
+                (result, latency, (started, SystemTime::now() + latency))
             })
             .collect();
 
-        let checks = futures::future::join_all(checks).await;
+        #[cfg(feature = "otel")]
+        let started = SystemTime::now();
+        let mut latency = Duration::ZERO;
+        #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+        let (checks, times): (Vec<_>, Vec<_>) = join_all(checks)
+            .await
+            .into_iter()
+            .map(|(result, probe_latency, times)| {
+                latency = latency.max(probe_latency);
+                (result, times)
+            })
+            .unzip();
+        drop(my_probes);
+        delay(latency).await;
+        self.record(&checks, time);
 
-        // let checks: Vec<HealthProbeResult> = vec!();
+        let reason = self
+            .maintenance(time)
+            .map(|maintenance| format!("maintenance: {}", maintenance.reason));
 
-        HealthCheckResult {
+        let result = HealthCheckResult {
             name: self.name.clone(),
-            valid: checks.iter().all(|check| check.valid),
+            valid: reason.is_none() && checks.iter().all(|check| check.valid),
+            reason,
             details: Some(checks),
+            stale: false,
+        };
+        // A filtered check says nothing about the status of the whole check
+        if *filter == ProbeFilter::default() {
+            self.record_status(&result, time);
         }
+        #[cfg(feature = "otel")]
+        if let Some(spans) = &self.spans {
+            spans.check(&result, started, &times);
+        }
+        result
     }
+
     pub(super) fn len(&self) -> usize {
         self.probes.blocking_lock().len()
     }
@@ -129,6 +729,17 @@ impl HealthCheck {
     }
 }
 
+/// Find a probe by name
+fn find<'a>(
+    probes: &'a HashSet<Box<dyn AsyncHealthProbe>>,
+    name: &str,
+) -> Option<&'a dyn AsyncHealthProbe> {
+    probes
+        .iter()
+        .find(|probe| probe.name().is_ok_and(|probe_name| probe_name == name))
+        .map(|probe| probe.as_ref())
+}
+
 // #[cfg(test)]
 // pub(crate) async fn blocking_probe_remove<T: HealthProbe + Clone + 'static>(
 //     check: &HealthCheck,
@@ -144,10 +755,15 @@ impl HealthCheck {
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
 
     use super::*;
-    use crate::probe::{kick::Kick, manual::Manual, FFIProbe};
+    use crate::probe::kick::Kick;
 
     /// Test insert on a health check using manual
     #[test]
@@ -354,4 +970,339 @@ mod tests {
         assert!(names.contains(&"test_probe0".to_string()));
         assert!(names.contains(&"test_probe1".to_string()));
     }
+
+    /// Test check_with only checks the probes selected by the filter
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_health_check_check_with_filter() {
+        let check = HealthCheck::new("test");
+        let manual0 = Manual::new("test_probe0", true);
+        let manual1 = Manual::new("test_probe1", false);
+
+        check
+            .insert_async(FFIProbe::from(manual0.clone()).into())
+            .await;
+        check
+            .insert_async(FFIProbe::from(manual1.clone()).into())
+            .await;
+
+        let replies = check
+            .check_with(SystemTime::now(), &ProbeFilter::default(), false)
+            .await;
+        assert!(!replies.valid);
+        assert!(replies.details.is_none());
+
+        let include = ProbeFilter {
+            include: vec!["test_probe0".to_string()],
+            exclude: vec![],
+        };
+        let replies = check.check_with(SystemTime::now(), &include, true).await;
+        assert!(replies.valid);
+        assert_eq!(replies.details.unwrap().len(), 1);
+
+        let exclude = ProbeFilter {
+            include: vec![],
+            exclude: vec!["test_probe0".to_string()],
+        };
+        let replies = check.check_with(SystemTime::now(), &exclude, true).await;
+        assert!(!replies.valid);
+        assert_eq!(replies.failing().collect::<Vec<_>>(), vec!["test_probe1"]);
+
+        assert!(check.validate_filter(&include).await.is_ok());
+        assert!(check.validate_filter(&ProbeFilter::default()).await.is_ok());
+        let typo = ProbeFilter {
+            include: vec![],
+            exclude: vec!["test_probe0".to_string(), "test_prob1".to_string()],
+        };
+        assert!(matches!(
+            check.validate_filter(&typo).await,
+            Err(HamsError::NotFound(_))
+        ));
+    }
+
+    /// The admin API lists, sets and checks single probes and keeps their last result
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_health_check_admin() {
+        let check = HealthCheck::new("test");
+        let manual = Manual::new("test_manual", true);
+        let kick = Kick::new("test_kick", Duration::from_secs(10));
+        check
+            .insert_async(FFIProbe::from(manual.clone()).into())
+            .await;
+        check.insert_async(FFIProbe::from(kick).into()).await;
+
+        let described = check.describe().await;
+        assert_eq!(described.name, "test");
+        assert_eq!(described.probes.len(), 2);
+        assert_eq!(described.probes[0].name, "test_kick");
+        assert_eq!(described.probes[0].probe_type, "kick");
+        assert_eq!(described.probes[0].config["margin_secs"], 10);
+        assert_eq!(described.probes[1].probe_type, "manual");
+        assert!(described.probes[1].last.is_none());
+
+        let probe = check.set_valid("test_manual", false).await.unwrap();
+        assert_eq!(probe.config["valid"], false);
+        assert!(matches!(
+            check.set_valid("test_kick", false).await,
+            Err(HamsError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            check.set_valid("missing", false).await,
+            Err(HamsError::NotFound(_))
+        ));
+
+        let result = check
+            .check_probe("test_manual", SystemTime::now())
+            .await
+            .unwrap();
+        assert!(!result.valid);
+        let described = check.describe().await;
+        assert!(!described.probes[1].last.as_ref().unwrap().valid);
+        assert!(described.probes[0].last.is_none());
+
+        check.check(SystemTime::now()).await;
+        let described = check.describe().await;
+        assert!(described.probes[0].last.as_ref().unwrap().valid);
+
+        check.remove_async(&(FFIProbe::from(manual).into())).await;
+        assert!(check.last.lock().unwrap().get("test_manual").is_none());
+    }
+
+    /// Maintenance fails the check with a reason until cleared or expired
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_health_check_maintenance() {
+        let check = HealthCheck::new("ready");
+        check
+            .insert_async(FFIProbe::from(Manual::new("test_probe0", true)).into())
+            .await;
+
+        check
+            .set_maintenance(Some(Maintenance::new("upgrade", None).unwrap()))
+            .unwrap();
+        let result = check.check_verbose(SystemTime::now()).await;
+        assert!(!result.valid);
+        assert_eq!(result.reason.as_deref(), Some("maintenance: upgrade"));
+        assert_eq!(result.failing().count(), 0);
+
+        check.set_maintenance(None).unwrap();
+        let result = check.check(SystemTime::now()).await;
+        assert!(result.valid);
+        assert!(result.reason.is_none());
+
+        check
+            .set_maintenance(Some(
+                Maintenance::new("upgrade", Some(Duration::from_secs(60))).unwrap(),
+            ))
+            .unwrap();
+        assert!(!check.check(SystemTime::now()).await.valid);
+        let later = SystemTime::now() + Duration::from_secs(61);
+        assert!(check.check(later).await.valid);
+        assert!(check.maintenance(SystemTime::now()).is_none());
+    }
+
+    /// Probe that counts its checks and takes a while to answer
+    #[derive(Debug, Default)]
+    struct Slow {
+        checks: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl AsyncHealthProbe for Slow {
+        fn name(&self) -> Result<String, HamsError> {
+            Ok("slow".to_string())
+        }
+
+        async fn check(&self, _time: SystemTime) -> Result<bool, HamsError> {
+            self.checks.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(true)
+        }
+    }
+
+    /// Concurrent checks of the same probes share one evaluation
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_health_check_coalesce() {
+        let check = HealthCheck::new("ready");
+        let slow = Slow::default();
+        let checks = slow.checks.clone();
+        check.insert_async(Box::new(slow)).await;
+
+        let time = SystemTime::now();
+        let results = join_all((0..10).map(|_| check.check(time))).await;
+        assert!(results.iter().all(|result| result.valid));
+        assert_eq!(checks.load(Ordering::SeqCst), 1);
+
+        let filter = ProbeFilter {
+            include: vec!["slow".to_string()],
+            exclude: vec![],
+        };
+        let (all, filtered) = tokio::join!(
+            check.check_verbose(time),
+            check.check_with(time, &filter, true)
+        );
+        assert_eq!(all.details.unwrap().len(), 1);
+        assert_eq!(filtered.details.unwrap().len(), 1);
+        assert_eq!(checks.load(Ordering::SeqCst), 3);
+        assert!(check.inflight.lock().unwrap().is_empty());
+    }
+
+    /// A probe slower than the timeout fails with a message saying so
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_health_check_timeout() {
+        let check = HealthCheck::new("ready").with_config(CheckConfig {
+            timeout: Duration::from_millis(10),
+            ..Default::default()
+        });
+        check.insert_async(Box::new(Slow::default())).await;
+
+        let result = check.check_verbose(SystemTime::now()).await;
+        assert!(!result.valid);
+        let details = result.details.unwrap();
+        assert_eq!(details[0].message.as_deref(), Some("timed out after 10ms"));
+        let last = check.describe().await.probes.remove(0).last.unwrap();
+        assert_eq!(last.message.as_deref(), Some("timed out after 10ms"));
+    }
+
+    /// Evaluations over the limit are rejected or served from the latest results
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_health_check_limit() {
+        let all = ProbeFilter::default();
+        let filter = ProbeFilter {
+            include: vec!["slow".to_string()],
+            exclude: vec![],
+        };
+        let time = SystemTime::now();
+        let config = CheckConfig {
+            max_concurrent: Some(1),
+            ..Default::default()
+        };
+
+        let check = HealthCheck::new("ready").with_config(config.clone());
+        check.insert_async(Box::new(Slow::default())).await;
+        let (first, second, joined) = tokio::join!(
+            check.try_check_with(time, &all, false),
+            check.try_check_with(time, &filter, false),
+            check.try_check_with(time, &all, false),
+        );
+        assert!(first.is_some_and(|result| !result.stale));
+        assert!(second.is_none());
+        assert!(joined.is_some());
+
+        let check = HealthCheck::new("ready").with_config(CheckConfig {
+            on_limit: LimitPolicy::Stale,
+            ..config
+        });
+        check.insert_async(Box::new(Slow::default())).await;
+        let (first, second) = tokio::join!(
+            check.try_check_with(time, &all, false),
+            check.try_check_with(time, &filter, false),
+        );
+        assert!(first.is_some());
+        assert!(second.is_none(), "Nothing checked yet to serve");
+
+        let (first, second) = tokio::join!(
+            check.try_check_with(time, &all, false),
+            check.try_check_with(time, &filter, true),
+        );
+        assert!(first.is_some_and(|result| !result.stale));
+        let second = second.unwrap();
+        assert!(second.stale && second.valid);
+        assert_eq!(second.details.unwrap()[0].name, "slow");
+    }
+
+    /// Faults replace or delay probe results until they expire and are labelled on the result
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_health_check_fault() {
+        let check = HealthCheck::new("alive");
+        check
+            .insert_async(FFIProbe::from(Manual::new("test_probe0", true)).into())
+            .await;
+        check
+            .insert_async(FFIProbe::from(Manual::new("test_probe1", false)).into())
+            .await;
+
+        let ttl = Duration::from_secs(60);
+        check
+            .set_fault(
+                "test_probe0",
+                Some(Fault::new(FaultKind::Fail, ttl).unwrap()),
+            )
+            .await
+            .unwrap();
+        check
+            .set_fault(
+                "test_probe1",
+                Some(Fault::new(FaultKind::Pass, ttl).unwrap()),
+            )
+            .await
+            .unwrap();
+        let result = check.check_verbose(SystemTime::now()).await;
+        assert!(!result.valid);
+        assert_eq!(result.failing().collect::<Vec<_>>(), vec!["test_probe0"]);
+        let details = result.details.unwrap();
+        let probe0 = details.iter().find(|probe| probe.name == "test_probe0");
+        assert!(probe0
+            .unwrap()
+            .fault
+            .as_deref()
+            .unwrap()
+            .starts_with("injected fail until "));
+        assert_eq!(check.faults(SystemTime::now()).len(), 2);
+        assert!(check.describe().await.probes[0].fault.is_some());
+
+        let later = SystemTime::now() + Duration::from_secs(61);
+        let result = check.check_probe("test_probe0", later).await.unwrap();
+        assert!(result.valid);
+        assert!(result.fault.is_none());
+        assert_eq!(check.faults(later).len(), 0);
+
+        check
+            .set_fault(
+                "test_probe0",
+                Some(Fault::new(FaultKind::Latency { latency_ms: 20 }, ttl).unwrap()),
+            )
+            .await
+            .unwrap();
+        let start = std::time::Instant::now();
+        let result = check
+            .check_probe("test_probe0", SystemTime::now())
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert!(result.valid);
+        assert!(result.fault.is_some());
+
+        // The probes can be changed while a latency is waited out
+        check
+            .set_fault(
+                "test_probe0",
+                Some(Fault::new(FaultKind::Latency { latency_ms: 500 }, ttl).unwrap()),
+            )
+            .await
+            .unwrap();
+        let slow = tokio::spawn({
+            let check = check.clone();
+            async move { check.check(SystemTime::now()).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let start = std::time::Instant::now();
+        check.set_valid("test_probe0", true).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(250));
+        slow.await.unwrap();
+
+        check.set_fault("test_probe0", None).await.unwrap();
+        assert!(check.faults(SystemTime::now()).is_empty());
+        assert!(matches!(
+            check
+                .set_fault("missing", Some(Fault::new(FaultKind::Fail, ttl).unwrap()))
+                .await,
+            Err(HamsError::NotFound(_))
+        ));
+    }
 }
@@ -0,0 +1,182 @@
+//! Health state transitions streamed on `/hams/events`
+//!
+//! Checks and probes report a transition when a check observes a status different from the last one, so
+//! transitions are seen as often as the checks run, eg every kubelet poll. The lifecycle reports every
+//! state change of HaMS. Subscribers that fall behind lose the oldest transitions rather than slowing
+//! the checks down.
+
+use std::time::SystemTime;
+
+use futures::Stream;
+use log::{debug, warn};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+use utoipa::ToSchema;
+
+/// Transitions kept for subscribers that have not yet received them
+const CAPACITY: usize = 256;
+
+/// Status of a check or probe before it has been checked
+pub(crate) const UNKNOWN: &str = "unknown";
+
+/// What changed status
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EventKind {
+    /// The alive or ready check
+    Check,
+    /// A probe of a check
+    Probe,
+    /// The lifecycle state of HaMS
+    Lifecycle,
+}
+
+impl EventKind {
+    /// Name of the SSE event
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Check => "check",
+            EventKind::Probe => "probe",
+            EventKind::Lifecycle => "lifecycle",
+        }
+    }
+}
+
+/// A change of status of a check, a probe or the lifecycle of HaMS
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct HamsEvent {
+    /// What changed status
+    pub(crate) kind: EventKind,
+    /// Name of the check, or of the check the probe belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) check: Option<String>,
+    /// Name of the probe
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) probe: Option<String>,
+    /// When the change was observed, in RFC 3339 with milliseconds
+    pub(crate) timestamp: String,
+    /// Status before the change. pass, fail or unknown for checks and probes, the state for the lifecycle
+    pub(crate) old: String,
+    /// Status after the change
+    pub(crate) new: String,
+    /// Why the status changed, eg maintenance, the failing probes or an injected fault
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<String>,
+}
+
+impl HamsEvent {
+    /// A transition observed at the given time
+    pub(crate) fn new<O: Into<String>, N: Into<String>>(
+        kind: EventKind,
+        time: SystemTime,
+        old: O,
+        new: N,
+    ) -> Self {
+        HamsEvent {
+            kind,
+            check: None,
+            probe: None,
+            timestamp: humantime::format_rfc3339_millis(time).to_string(),
+            old: old.into(),
+            new: new.into(),
+            reason: None,
+        }
+    }
+}
+
+/// Status of a check or probe result
+pub(crate) fn status(valid: bool) -> &'static str {
+    if valid {
+        "pass"
+    } else {
+        "fail"
+    }
+}
+
+/// Broadcast of transitions to every open event stream
+#[derive(Debug, Clone)]
+pub(crate) struct Events {
+    sender: broadcast::Sender<HamsEvent>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    /// Publish a transition. Nothing is kept when no stream is open
+    pub(crate) fn send(&self, event: HamsEvent) {
+        debug!("{} {} -> {}", event.kind.as_str(), event.old, event.new);
+        let _ = self.sender.send(event);
+    }
+
+    /// Number of open event streams
+    pub(crate) fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Transitions published from now until the token is cancelled
+    pub(crate) fn stream(&self, ct: CancellationToken) -> impl Stream<Item = HamsEvent> + Send {
+        futures::stream::unfold(
+            (self.sender.subscribe(), ct),
+            |(mut receiver, ct)| async move {
+                loop {
+                    // Deliver the transitions already published, such as stopping, before ending
+                    let received = tokio::select! {
+                        biased;
+                        received = receiver.recv() => received,
+                        _ = ct.cancelled() => return None,
+                    };
+                    match received {
+                        Ok(event) => return Some((event, (receiver, ct))),
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Event stream fell behind and lost {skipped} events")
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream() {
+        let events = Events::default();
+        events.send(HamsEvent::new(
+            EventKind::Lifecycle,
+            SystemTime::now(),
+            "stopped",
+            "starting",
+        ));
+
+        let ct = CancellationToken::new();
+        let mut stream = Box::pin(events.stream(ct.clone()));
+        assert_eq!(events.subscribers(), 1);
+
+        let mut event = HamsEvent::new(EventKind::Probe, SystemTime::UNIX_EPOCH, UNKNOWN, "pass");
+        event.check = Some("ready".to_string());
+        event.probe = Some("database".to_string());
+        events.send(event.clone());
+        assert_eq!(stream.next().await, Some(event.clone()));
+
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["kind"], "probe");
+        assert_eq!(value["timestamp"], "1970-01-01T00:00:00.000Z");
+        assert!(value.get("reason").is_none());
+
+        ct.cancel();
+        assert_eq!(stream.next().await, None);
+    }
+}
@@ -0,0 +1,183 @@
+//! Faults injected into probes to rehearse outages
+//!
+//! A fault replaces or delays the result of a single probe until its TTL passes, after which the probe
+//! reverts to its real check. Faulted results carry a description of the fault in verbose output so an
+//! injected failure cannot be mistaken for a real one.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{error::HamsError, hams::info::rfc3339};
+
+/// What a fault does to a probe
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub(crate) enum FaultKind {
+    /// The probe fails without being checked
+    Fail,
+    /// The probe passes without being checked
+    Pass,
+    /// The result of the probe is delayed. The probes of the check can still be changed meanwhile
+    Latency {
+        /// Delay of the result in milliseconds, at most a minute
+        latency_ms: u64,
+    },
+    /// The probe fails as if its check returned an error
+    Error {
+        /// Error reported with the result
+        message: String,
+    },
+}
+
+/// Longest delay a latency fault may add to a probe
+pub(crate) const MAX_LATENCY: Duration = Duration::from_secs(60);
+
+/// Longest time a fault may be injected for
+pub(crate) const MAX_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A fault injected into a probe until it expires
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Fault {
+    pub(crate) kind: FaultKind,
+    pub(crate) until: SystemTime,
+}
+
+impl Fault {
+    pub(crate) fn new(kind: FaultKind, ttl: Duration) -> Result<Self, HamsError> {
+        let until = SystemTime::now().checked_add(ttl).ok_or_else(|| {
+            HamsError::InvalidArgument(format!("ttl of {}s is too long", ttl.as_secs()))
+        })?;
+        Ok(Fault { kind, until })
+    }
+
+    /// Whether the fault has reverted by the given time
+    pub(crate) fn expired(&self, time: SystemTime) -> bool {
+        time >= self.until
+    }
+
+    /// Description of the fault shown on faulted results
+    pub(crate) fn describe(&self) -> String {
+        let kind = match &self.kind {
+            FaultKind::Fail => "fail".to_string(),
+            FaultKind::Pass => "pass".to_string(),
+            FaultKind::Latency { latency_ms } => format!("latency {latency_ms}ms"),
+            FaultKind::Error { message } => format!("error: {message}"),
+        };
+        format!("injected {kind} until {}", rfc3339(self.until))
+    }
+}
+
+/// Request to inject a fault into a probe
+#[derive(Debug, Clone, PartialEq, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct FaultRequest {
+    /// What the fault does
+    pub(crate) fault: FaultKind,
+    /// Seconds after which the probe reverts to its real check, at most a day
+    pub(crate) ttl_secs: u64,
+}
+
+impl FaultRequest {
+    pub(crate) fn into_fault(self) -> Result<Fault, HamsError> {
+        if self.ttl_secs == 0 {
+            return Err(HamsError::InvalidArgument(
+                "ttl_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.ttl_secs > MAX_TTL.as_secs() {
+            return Err(HamsError::InvalidArgument(format!(
+                "ttl_secs must be at most {}",
+                MAX_TTL.as_secs()
+            )));
+        }
+        if let FaultKind::Latency { latency_ms } = self.fault {
+            if Duration::from_millis(latency_ms) > MAX_LATENCY {
+                return Err(HamsError::InvalidArgument(format!(
+                    "latency_ms must be at most {}",
+                    MAX_LATENCY.as_millis()
+                )));
+            }
+        }
+        Fault::new(self.fault, Duration::from_secs(self.ttl_secs))
+    }
+}
+
+/// An active fault as listed by the admin API
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct FaultReply {
+    /// Name of the check (alive or ready)
+    pub(crate) check: String,
+    /// Name of the probe
+    pub(crate) probe: String,
+    /// What the fault does
+    pub(crate) fault: FaultKind,
+    /// When the probe reverts to its real check, in RFC 3339
+    pub(crate) until: String,
+}
+
+/// Wait without blocking the runtime, or block the thread when called outside a runtime over FFI
+pub(crate) async fn delay(duration: Duration) {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::time::sleep(duration).await;
+    } else {
+        std::thread::sleep(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fault_request() {
+        let request: FaultRequest = serde_json::from_value(serde_json::json!({
+            "fault": {"type": "latency", "latency_ms": 250},
+            "ttl_secs": 60
+        }))
+        .unwrap();
+        assert_eq!(request.fault, FaultKind::Latency { latency_ms: 250 });
+
+        let fault = request.into_fault().unwrap();
+        assert!(!fault.expired(SystemTime::now()));
+        assert!(fault.expired(SystemTime::now() + Duration::from_secs(61)));
+        assert!(fault
+            .describe()
+            .starts_with("injected latency 250ms until "));
+
+        let request: FaultRequest = serde_json::from_value(serde_json::json!({
+            "fault": {"type": "error", "message": "connection refused"},
+            "ttl_secs": 0
+        }))
+        .unwrap();
+        assert!(request.into_fault().is_err());
+
+        let request: FaultRequest = serde_json::from_value(serde_json::json!({
+            "fault": {"type": "latency", "latency_ms": u64::MAX},
+            "ttl_secs": 60
+        }))
+        .unwrap();
+        assert!(matches!(
+            request.into_fault(),
+            Err(HamsError::InvalidArgument(_))
+        ));
+
+        let request: FaultRequest = serde_json::from_value(serde_json::json!({
+            "fault": {"type": "fail"},
+            "ttl_secs": u64::MAX
+        }))
+        .unwrap();
+        assert!(matches!(
+            request.into_fault(),
+            Err(HamsError::InvalidArgument(_))
+        ));
+        assert!(Fault::new(FaultKind::Fail, Duration::MAX).is_err());
+
+        assert!(serde_json::from_value::<FaultRequest>(serde_json::json!({
+            "fault": {"type": "explode"},
+            "ttl_secs": 60
+        }))
+        .is_err());
+    }
+}
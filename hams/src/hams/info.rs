@@ -0,0 +1,157 @@
+//! Build and runtime details of the application reported on `/hams/info`
+
+use std::{
+    collections::BTreeMap,
+    ffi::CStr,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Version of the application until it is set
+pub(crate) const UNDEFINED_VERSION: &str = "UNDEFINED";
+
+/// Build details of the application set through the Rust, C or hamsrs API
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct AppInfo {
+    /// Version of the application
+    pub(crate) version: String,
+    /// Git commit the application was built from
+    pub(crate) git_sha: Option<String>,
+    /// When the application was built
+    pub(crate) build_time: Option<String>,
+    /// Free form labels such as region or deployment
+    pub(crate) labels: BTreeMap<String, String>,
+}
+
+impl Default for AppInfo {
+    fn default() -> Self {
+        AppInfo {
+            version: UNDEFINED_VERSION.to_string(),
+            git_sha: None,
+            build_time: None,
+            labels: BTreeMap::new(),
+        }
+    }
+}
+
+/// Lifecycle state of HaMS
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HamsState {
+    /// Not started or stopped
+    Stopped,
+    /// Started and waiting for the webservice
+    Starting,
+    /// Serving the webservice
+    Running,
+    /// Shutdown requested and callbacks running
+    Stopping,
+}
+
+impl HamsState {
+    /// Name of the state as serialised
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            HamsState::Stopped => "stopped",
+            HamsState::Starting => "starting",
+            HamsState::Running => "running",
+            HamsState::Stopping => "stopping",
+        }
+    }
+}
+
+/// Reply structure for the info response
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct InfoReply {
+    /// Name of the application
+    pub(crate) name: String,
+    /// Build details of the application
+    #[serde(flatten)]
+    pub(crate) app: AppInfo,
+    /// Name of the HaMS package
+    pub(crate) hams_name: String,
+    /// Version of HaMS
+    pub(crate) hams_version: String,
+    /// When HaMS was created, normally at application start, in RFC 3339
+    pub(crate) start_time: String,
+    /// Seconds since start_time
+    pub(crate) uptime_seconds: f64,
+    /// Process id
+    pub(crate) pid: u32,
+    /// Hostname, which is the pod name on Kubernetes
+    pub(crate) hostname: String,
+    /// Lifecycle state of HaMS
+    pub(crate) state: HamsState,
+    /// Configuration with secrets redacted
+    #[schema(value_type = Object)]
+    pub(crate) config: serde_json::Value,
+}
+
+/// Format a time in RFC 3339 with seconds precision. Times past the year 9999, which RFC 3339 cannot
+/// represent, fall back to seconds since the Unix epoch
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    use std::fmt::Write;
+
+    let mut text = String::new();
+    match write!(text, "{}", humantime::format_rfc3339_seconds(time)) {
+        Ok(()) => text,
+        Err(_) => time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_secs()
+            .to_string(),
+    }
+}
+
+/// Seconds elapsed since a time, zero if the clock went backwards
+pub(crate) fn uptime(since: SystemTime) -> f64 {
+    SystemTime::now()
+        .duration_since(since)
+        .unwrap_or(Duration::ZERO)
+        .as_secs_f64()
+}
+
+/// Hostname of the machine or an empty string if it cannot be read
+pub(crate) fn hostname() -> String {
+    let mut buffer = [0 as libc::c_char; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr(), buffer.len()) };
+    if result != 0 {
+        return String::new();
+    }
+    // Terminate in case the name was truncated
+    buffer[buffer.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buffer.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hostname() {
+        assert!(!hostname().is_empty());
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let far = SystemTime::UNIX_EPOCH + Duration::from_secs(300_000_000_000);
+        assert_eq!(rfc3339(far), "300000000000");
+        assert!(uptime(SystemTime::now() - Duration::from_secs(2)) >= 2.0);
+        assert_eq!(uptime(SystemTime::now() + Duration::from_secs(60)), 0.0);
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut app = AppInfo::default();
+        app.labels.insert("region".to_string(), "eu".to_string());
+        let value = serde_json::to_value(&app).unwrap();
+        assert_eq!(value["version"], UNDEFINED_VERSION);
+        assert_eq!(value["labels"]["region"], "eu");
+        assert_eq!(serde_json::to_value(HamsState::Running).unwrap(), "running");
+    }
+}
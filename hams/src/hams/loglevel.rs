@@ -0,0 +1,110 @@
+//! Runtime log levels of HaMS served on `/hams/loglevel`
+//!
+//! The levels are the runtime filter of ffi-log2 which HaMS applies before forwarding a record to the
+//! host. Each change is also passed to the host through the `set_level` callback of its `LogParam`, and
+//! the forwarded `enabled` check of a host using ffi-log2 then passes the records of a raised level.
+
+use std::{collections::BTreeMap, str::FromStr};
+
+use log::{info, LevelFilter};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::HamsError;
+
+/// Current log levels
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct LogLevelReply {
+    /// Level for targets without an override
+    #[schema(value_type = String, example = "INFO")]
+    pub(crate) default: LevelFilter,
+    /// Overrides by target, which also apply to the targets nested below them
+    #[schema(value_type = BTreeMap<String, String>)]
+    pub(crate) targets: BTreeMap<String, LevelFilter>,
+}
+
+impl From<ffi_log2::LogLevels> for LogLevelReply {
+    fn from(levels: ffi_log2::LogLevels) -> Self {
+        LogLevelReply {
+            default: levels.default,
+            targets: levels.targets,
+        }
+    }
+}
+
+/// Change of a log level
+#[derive(Debug, Clone, PartialEq, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LogLevelUpdate {
+    /// Target to change, eg `hams::access`. The default level when not given
+    pub(crate) target: Option<String>,
+    /// One of off, error, warn, info, debug or trace. Removes the override of the target when not given
+    #[schema(value_type = Option<String>, example = "debug")]
+    pub(crate) level: Option<LevelFilter>,
+}
+
+impl LogLevelUpdate {
+    /// Parse a change from the strings passed over FFI
+    pub(crate) fn parse(level: Option<&str>, target: Option<&str>) -> Result<Self, HamsError> {
+        let level = level
+            .map(|level| {
+                LevelFilter::from_str(level)
+                    .map_err(|_e| HamsError::InvalidArgument(format!("Invalid log level: {level}")))
+            })
+            .transpose()?;
+        Ok(LogLevelUpdate {
+            target: target.map(str::to_string),
+            level,
+        })
+    }
+
+    /// Apply the change to the runtime filter
+    pub(crate) fn apply(&self) -> Result<LogLevelReply, HamsError> {
+        match (&self.target, self.level) {
+            (None, None) => {
+                return Err(HamsError::InvalidArgument(
+                    "A level is required to change the default level".to_string(),
+                ))
+            }
+            (None, Some(level)) => {
+                ffi_log2::set_level(level);
+                info!("Default log level set to {level}");
+            }
+            (Some(target), level) => {
+                ffi_log2::set_target_level(target, level);
+                match level {
+                    Some(level) => info!("Log level of {target} set to {level}"),
+                    None => info!("Log level override of {target} removed"),
+                }
+            }
+        }
+        Ok(levels())
+    }
+}
+
+/// Current log levels
+pub(crate) fn levels() -> LogLevelReply {
+    ffi_log2::levels().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            LogLevelUpdate::parse(Some("Debug"), Some("hams::access")).unwrap(),
+            LogLevelUpdate {
+                target: Some("hams::access".to_string()),
+                level: Some(LevelFilter::Debug),
+            }
+        );
+        assert_eq!(
+            LogLevelUpdate::parse(None, Some("hams")).unwrap().level,
+            None
+        );
+        assert!(LogLevelUpdate::parse(Some("loud"), None).is_err());
+        assert!(LogLevelUpdate::parse(None, None).unwrap().apply().is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Maintenance mode which takes the service out of rotation with a reason
+//!
+//! While set, the ready check fails with the reason regardless of its probes. The alive check is never
+//! affected so the orchestrator does not restart a service under maintenance.
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{error::HamsError, hams::info::rfc3339};
+
+/// Longest expiry maintenance may be set with
+pub(crate) const MAX_EXPIRY: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Maintenance set on a check
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Maintenance {
+    /// Why the service is under maintenance
+    pub(crate) reason: String,
+    /// When maintenance was set
+    pub(crate) since: SystemTime,
+    /// When maintenance ends by itself
+    pub(crate) until: Option<SystemTime>,
+}
+
+impl Maintenance {
+    /// Maintenance from now, ending after the expiry if given. Fails when the expiry is longer than
+    /// MAX_EXPIRY
+    pub(crate) fn new<S: Into<String>>(
+        reason: S,
+        expiry: Option<Duration>,
+    ) -> Result<Self, HamsError> {
+        let since = SystemTime::now();
+        let until = expiry
+            .map(|expiry| {
+                since
+                    .checked_add(expiry)
+                    .filter(|_| expiry <= MAX_EXPIRY)
+                    .ok_or_else(|| {
+                        HamsError::InvalidArgument(format!(
+                            "expiry must be at most {}s",
+                            MAX_EXPIRY.as_secs()
+                        ))
+                    })
+            })
+            .transpose()?;
+        Ok(Maintenance {
+            reason: reason.into(),
+            since,
+            until,
+        })
+    }
+
+    /// Whether maintenance has ended by the given time
+    pub(crate) fn expired(&self, time: SystemTime) -> bool {
+        self.until.is_some_and(|until| time >= until)
+    }
+}
+
+/// Maintenance state of the service
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub(crate) struct MaintenanceReply {
+    /// True while the service is under maintenance
+    pub(crate) active: bool,
+    /// Why the service is under maintenance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) reason: Option<String>,
+    /// When maintenance was set, in RFC 3339
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) since: Option<String>,
+    /// When maintenance ends by itself, in RFC 3339
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) until: Option<String>,
+}
+
+impl From<Option<Maintenance>> for MaintenanceReply {
+    fn from(maintenance: Option<Maintenance>) -> Self {
+        MaintenanceReply {
+            active: maintenance.is_some(),
+            reason: maintenance.as_ref().map(|m| m.reason.clone()),
+            since: maintenance.as_ref().map(|m| rfc3339(m.since)),
+            until: maintenance.and_then(|m| m.until).map(rfc3339),
+        }
+    }
+}
+
+/// Request to set maintenance
+#[derive(Debug, Clone, PartialEq, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MaintenanceRequest {
+    /// Why the service is under maintenance, reported by the ready check
+    pub(crate) reason: String,
+    /// Seconds after which maintenance ends by itself, at most a year. Maintenance lasts until cleared
+    /// when not given
+    pub(crate) expiry_secs: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maintenance() {
+        let maintenance = Maintenance::new("upgrade", Some(Duration::from_secs(60))).unwrap();
+        assert!(!maintenance.expired(SystemTime::now()));
+        assert!(maintenance.expired(SystemTime::now() + Duration::from_secs(61)));
+        let year = Duration::from_secs(365 * 24 * 60 * 60);
+        assert!(!Maintenance::new("upgrade", None)
+            .unwrap()
+            .expired(SystemTime::now() + year));
+        assert!(matches!(
+            Maintenance::new("upgrade", Some(Duration::from_secs(u64::MAX))),
+            Err(HamsError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Maintenance::new("upgrade", Some(Duration::from_secs(300_000_000_000))),
+            Err(HamsError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            Maintenance::new("upgrade", Some(MAX_EXPIRY + Duration::from_secs(1))),
+            Err(HamsError::InvalidArgument(_))
+        ));
+        assert!(Maintenance::new("upgrade", Some(MAX_EXPIRY)).is_ok());
+
+        let reply = MaintenanceReply::from(Some(maintenance));
+        assert!(reply.active);
+        assert_eq!(reply.reason.as_deref(), Some("upgrade"));
+        assert!(reply.until.is_some());
+
+        let reply = serde_json::to_value(MaintenanceReply::from(None)).unwrap();
+        assert_eq!(reply, serde_json::json!({"active": false}));
+    }
+}
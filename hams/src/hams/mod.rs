@@ -1,24 +1,47 @@
 mod check;
 pub mod config;
+pub(crate) mod events;
+pub(crate) mod fault;
+pub(crate) mod info;
+pub(crate) mod loglevel;
+pub(crate) mod maintenance;
+#[cfg(feature = "otel")]
+pub(crate) mod otel;
+pub(crate) mod push;
+pub(crate) mod statsd;
 mod webservice;
 
 use std::{
     net::SocketAddr,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, RwLock},
+    task::Poll,
     thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
 };
 
 use crate::{
-    error::HamsError, hams::check::HealthCheck, probe::AsyncHealthProbe, tokio_tools::run_in_tokio,
+    error::HamsError,
+    hams::check::{CheckProbes, HealthCheck},
+    metrics::{
+        collector::Collectors, health::HealthMetrics, openmetrics, process::ProcessMetrics,
+        Counter, Format, Gauge, Histogram, Registry, RESERVED_PREFIX,
+    },
+    probe::AsyncHealthProbe,
+    tokio_tools::run_in_tokio,
 };
 
-use config::HamsConfig;
+use config::{AccessLogConfig, HamsConfig, StopSignal, TlsConfig};
+use events::{EventKind, Events, HamsEvent};
+use fault::{Fault, FaultReply};
+use info::{AppInfo, HamsState};
 use libc::c_void;
 use log::info;
+use maintenance::Maintenance;
 use tokio::signal::unix::signal;
 
 use tokio::signal::unix::SignalKind;
 use tokio_util::sync::CancellationToken;
+use webservice::{access::RequestMetrics, auth::Auth};
 
 #[derive(Debug)]
 pub(crate) struct HamsCallback {
@@ -31,6 +54,9 @@ pub(crate) struct HamsCallback {
 /// Send impl.
 unsafe impl Send for HamsCallback {}
 
+/// Name of the collector registered by [Hams::register_prometheus]
+pub(crate) const DEFAULT_COLLECTOR: &str = "default";
+
 #[derive(Debug, Clone)]
 pub struct PrometheusCallback {
     pub my_cb: extern "C" fn(ptr: *const c_void) -> *mut libc::c_char,
@@ -44,8 +70,8 @@ unsafe impl Send for PrometheusCallback {}
 pub struct Hams {
     /// Name of the application this HaMS is for
     pub(crate) name: String,
-    /// Provide the version of the application
-    pub(crate) version: String,
+    /// Version, build details and labels of the application
+    pub(crate) app_info: Arc<RwLock<AppInfo>>,
     /// Provide the version of the release of HaMS
     pub(crate) hams_version: String,
     /// Provide the name of the package
@@ -53,6 +79,31 @@ pub struct Hams {
 
     /// Provide the address on which to serve the HaMS readyness and liveness
     address: SocketAddr,
+    /// Access log policy for the webservice
+    pub(crate) access_log: AccessLogConfig,
+    /// Counters and latencies of requests served by the webservice
+    pub(crate) request_metrics: Arc<RequestMetrics>,
+    /// Metrics registered by the application
+    pub(crate) registry: Arc<Registry>,
+    /// Metrics of the checks, probes and build exported by HaMS
+    pub(crate) health_metrics: HealthMetrics,
+    /// Metrics of the process, when configured
+    pub(crate) process_metrics: Option<ProcessMetrics>,
+    /// Serve over TLS when configured
+    tls: Option<TlsConfig>,
+    /// Access policies and credentials of the webservice
+    pub(crate) auth: Arc<Auth>,
+    /// Configuration this HaMS was created with
+    pub(crate) config: Arc<HamsConfig>,
+    /// When this HaMS was created
+    pub(crate) created: SystemTime,
+    /// Lifecycle state of the service
+    pub(crate) state: Arc<Mutex<HamsState>>,
+    /// Transitions of the checks, probes and lifecycle published on the event stream
+    pub(crate) events: Events,
+    /// Spans of the checks waiting to be exported over OTLP
+    #[cfg(feature = "otel")]
+    pub(crate) spans: otel::Spans,
 
     // preflights run successfully before the service starts
     pub preflights: HealthCheck,
@@ -70,8 +121,8 @@ pub struct Hams {
     /// joinhandle to wait when shutting down service
     thread_jh: Arc<Mutex<Option<JoinHandle<Result<(), HamsError>>>>>,
 
-    /// Callback to be called on prometheus
-    pub(crate) prometheus_cb: Arc<Mutex<Option<PrometheusCallback>>>,
+    /// Named collectors called on every scrape of the metrics
+    pub(crate) collectors: Arc<Collectors>,
     // /// Tokio runtime
     // pub(crate) rt: Arc<Mutex<Option<tokio::runtime::Runtime>>>,
 }
@@ -86,9 +137,32 @@ impl Hams {
         // Create a new CancellationToken and then cancel it so it cannot be used further but needs to be replaced
         let ct = CancellationToken::new();
         ct.cancel();
+        let events = Events::default();
+        let health_metrics = HealthMetrics::default();
+        let alive = HealthCheck::new("alive")
+            .with_config(config.alive.clone())
+            .with_events(events.clone())
+            .with_metrics(health_metrics.clone());
+        let ready = HealthCheck::new("ready")
+            .with_config(config.ready.clone())
+            .with_events(events.clone())
+            .with_metrics(health_metrics.clone());
+        #[cfg(feature = "otel")]
+        let spans = otel::Spans::default();
+        #[cfg(feature = "otel")]
+        let (alive, ready) = match config.otel {
+            Some(_) => (
+                alive.with_spans(spans.clone()),
+                ready.with_spans(spans.clone()),
+            ),
+            None => (alive, ready),
+        };
         Hams {
+            config: Arc::new(config.clone()),
+            created: SystemTime::now(),
+            state: Arc::new(Mutex::new(HamsState::Stopped)),
             name: config.name,
-            version: "UNDEFINED".to_owned(),
+            app_info: Arc::new(RwLock::new(AppInfo::default())),
             hams_version: env!("CARGO_PKG_VERSION").to_string(),
             hams_name: env!("CARGO_PKG_NAME").to_string(),
 
@@ -96,19 +170,115 @@ impl Hams {
 
             cancellation_token: ct,
             address: config.address,
+            access_log: config.access_log,
+            request_metrics: Arc::new(RequestMetrics::default()),
+            registry: Arc::new(Registry::default()),
+            process_metrics: config.metrics.process.then(ProcessMetrics::default),
+            auth: Arc::new(Auth::new(config.auth, config.tls.as_ref())),
+            tls: config.tls,
 
             preflights: HealthCheck::new("preflights"),
             shutdowns: HealthCheck::new("shutdowns"),
 
-            alive: HealthCheck::new("alive"),
-            ready: HealthCheck::new("ready"),
+            alive,
+            ready,
+            events,
+            #[cfg(feature = "otel")]
+            spans,
+            health_metrics,
             shutdown_cb: Arc::new(Mutex::new(None)),
-            // prometheus_cb: None,
-            prometheus_cb: Arc::new(Mutex::new(None)),
+            collectors: Arc::new(Collectors::new(&config.metrics)),
             // rt: Arc::new(Mutex::new(None)),
         }
     }
 
+    /// Set the version of the application reported by HaMS
+    pub fn set_version(&self, version: &str) -> Result<(), HamsError> {
+        self.app_info.write()?.version = version.to_string();
+        Ok(())
+    }
+
+    /// Set the git commit and build time of the application reported by HaMS
+    pub fn set_build_info(
+        &self,
+        git_sha: Option<&str>,
+        build_time: Option<&str>,
+    ) -> Result<(), HamsError> {
+        let mut app_info = self.app_info.write()?;
+        app_info.git_sha = git_sha.map(str::to_string);
+        app_info.build_time = build_time.map(str::to_string);
+        Ok(())
+    }
+
+    /// Set a label reported by HaMS. A None value removes the label
+    pub fn set_label(&self, key: &str, value: Option<&str>) -> Result<(), HamsError> {
+        let mut app_info = self.app_info.write()?;
+        match value {
+            Some(value) => app_info.labels.insert(key.to_string(), value.to_string()),
+            None => app_info.labels.remove(key),
+        };
+        Ok(())
+    }
+
+    /// Take the service out of rotation. Ready fails with the reason, regardless of its probes, until
+    /// maintenance is cleared or the expiry has passed. Alive is unaffected.
+    pub fn set_maintenance(&self, reason: &str, expiry: Option<Duration>) -> Result<(), HamsError> {
+        info!("Maintenance of {} set: {reason}", self.name);
+        self.ready
+            .set_maintenance(Some(Maintenance::new(reason, expiry)?))
+    }
+
+    /// End maintenance and return ready to its probes
+    pub fn clear_maintenance(&self) -> Result<(), HamsError> {
+        info!("Maintenance of {} cleared", self.name);
+        self.ready.set_maintenance(None)
+    }
+
+    /// Current maintenance, if any
+    pub(crate) fn maintenance(&self) -> Option<Maintenance> {
+        self.ready.maintenance(SystemTime::now())
+    }
+
+    /// Register a counter reported on the metrics endpoint
+    pub fn counter(&self, name: &str, help: &str, labels: &[&str]) -> Result<Counter, HamsError> {
+        self.registry.counter(name, help, labels)
+    }
+
+    /// Register a gauge reported on the metrics endpoint
+    pub fn gauge(&self, name: &str, help: &str, labels: &[&str]) -> Result<Gauge, HamsError> {
+        self.registry.gauge(name, help, labels)
+    }
+
+    /// Register a histogram reported on the metrics endpoint. The default buckets are used when not given
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        buckets: Option<&[f64]>,
+    ) -> Result<Histogram, HamsError> {
+        self.registry.histogram(name, help, labels, buckets)
+    }
+
+    /// Current lifecycle state
+    pub fn state(&self) -> Result<HamsState, HamsError> {
+        Ok(*self.state.lock()?)
+    }
+
+    fn set_state(&self, state: HamsState) -> Result<(), HamsError> {
+        info!("HaMS {} is {:?}", self.name, state);
+        let old = std::mem::replace(&mut *self.state.lock()?, state);
+        if old != state {
+            self.events.send(HamsEvent::new(
+                EventKind::Lifecycle,
+                SystemTime::now(),
+                old.as_str(),
+                state.as_str(),
+            ));
+        }
+        Ok(())
+    }
+
     pub fn register_shutdown(
         &self,
         cb: unsafe extern "C" fn(*mut c_void),
@@ -127,30 +297,50 @@ impl Hams {
         Ok(())
     }
 
+    /// Register the default collector, replacing any registered before.
+    /// Libraries sharing a HaMS should use [Hams::register_collector] with their own name instead
     pub fn register_prometheus(
         &mut self,
         my_cb: extern "C" fn(ptr: *const c_void) -> *mut libc::c_char,
         my_cb_free: extern "C" fn(*mut libc::c_char),
         state: *const c_void,
     ) -> Result<(), HamsError> {
-        println!("Add prometheus to {}", self.name);
-
-        // self.prometheus_cb = Some(PrometheusCallback { my_cb, my_cb_free, state });
-        *self.prometheus_cb.lock()? = Some(PrometheusCallback {
-            my_cb,
-            my_cb_free,
-            state,
-        });
-        Ok(())
+        self.deregister_prometheus()?;
+        self.register_collector(DEFAULT_COLLECTOR, my_cb, my_cb_free, state)
     }
 
-    /// Deregister Prometheus
+    /// Deregister the default collector
     pub fn deregister_prometheus(&mut self) -> Result<(), HamsError> {
-        println!("Remove prometheus from {}", self.name);
+        match self.deregister_collector(DEFAULT_COLLECTOR) {
+            Err(HamsError::NotFound(_)) => Ok(()),
+            result => result,
+        }
+    }
 
-        // self.prometheus_cb = None;
-        *self.prometheus_cb.lock()? = None;
-        Ok(())
+    /// Register a named collector called on every scrape of the metrics. Collectors with other names are
+    /// kept, a collector already registered with the name is an error
+    pub fn register_collector(
+        &self,
+        name: &str,
+        my_cb: extern "C" fn(ptr: *const c_void) -> *mut libc::c_char,
+        my_cb_free: extern "C" fn(*mut libc::c_char),
+        state: *const c_void,
+    ) -> Result<(), HamsError> {
+        info!("Add collector {name} to {}", self.name);
+        self.collectors.register(
+            name,
+            PrometheusCallback {
+                my_cb,
+                my_cb_free,
+                state,
+            },
+        )
+    }
+
+    /// Deregister a named collector
+    pub fn deregister_collector(&self, name: &str) -> Result<(), HamsError> {
+        info!("Remove collector {name} from {}", self.name);
+        self.collectors.deregister(name)
     }
 
     pub fn start(&mut self) -> Result<(), HamsError> {
@@ -160,6 +350,7 @@ impl Hams {
             return Err(HamsError::AlreadyRunning);
         }
         self.cancellation_token = CancellationToken::new();
+        self.set_state(HamsState::Starting)?;
 
         // Create a clone of self to be owned by the thread
         let mut self_thread = self.clone();
@@ -223,6 +414,86 @@ impl Hams {
         self.ready.remove(probe)
     }
 
+    /// The check with the given name, alive or ready
+    pub(crate) fn check_group(&self, name: &str) -> Result<&HealthCheck, HamsError> {
+        [&self.alive, &self.ready]
+            .into_iter()
+            .find(|check| check.name == name)
+            .ok_or_else(|| HamsError::NotFound(format!("check {name}")))
+    }
+
+    /// Describe the probes of every check
+    pub(crate) async fn probe_list(&self) -> Vec<CheckProbes> {
+        vec![self.alive.describe().await, self.ready.describe().await]
+    }
+
+    /// Inject a fault into a probe of a check, or revert the probe to its real check with None
+    pub(crate) async fn set_fault(
+        &self,
+        check: &str,
+        probe: &str,
+        fault: Option<Fault>,
+    ) -> Result<(), HamsError> {
+        self.check_group(check)?.set_fault(probe, fault).await
+    }
+
+    /// Active faults of every check
+    pub(crate) fn faults(&self) -> Vec<FaultReply> {
+        let time = SystemTime::now();
+        let mut faults = self.alive.faults(time);
+        faults.extend(self.ready.faults(time));
+        faults
+    }
+
+    /// Render the metrics from the registered collectors followed by the metrics of the application, of
+    /// HaMS itself and, when configured, of the process
+    pub(crate) async fn render_metrics(&self, format: Format) -> Result<String, HamsError> {
+        let native = |name: &str| {
+            name.starts_with(RESERVED_PREFIX)
+                || self.registry.contains(name)
+                || (self.process_metrics.is_some() && name.starts_with("process_"))
+        };
+        let metrics = self.collectors.collect(format, native).await?;
+        let health_metrics = {
+            let app_info = self.app_info.read()?;
+            self.health_metrics
+                .render(&self.name, &app_info, &self.hams_version, format)
+        };
+        let request_metrics = match format {
+            Format::Prometheus => self.request_metrics.render(),
+            Format::OpenMetrics => openmetrics::from_prometheus(&self.request_metrics.render()),
+        };
+        let process_metrics = self
+            .process_metrics
+            .as_ref()
+            .map(|process| process.render(format))
+            .unwrap_or_default();
+        Ok(metrics
+            + &self.registry.render(format)
+            + &health_metrics
+            + &process_metrics
+            + &self.collectors.render(format)
+            + &request_metrics)
+    }
+
+    /// The registries of the application, of HaMS itself and, when configured, of the process, with the
+    /// build details and process metrics read now
+    pub(crate) fn native_registries(&self) -> Result<Vec<&Registry>, HamsError> {
+        let mut registries = vec![self.registry.as_ref()];
+        {
+            let app_info = self.app_info.read()?;
+            registries.push(
+                self.health_metrics
+                    .refresh(&self.name, &app_info, &self.hams_version),
+            );
+        }
+        if let Some(process) = self.process_metrics.as_ref().and_then(|p| p.refresh()) {
+            registries.push(process);
+        }
+        registries.push(self.collectors.registry());
+        Ok(registries)
+    }
+
     async fn start_async(&mut self, ct: CancellationToken) -> Result<(), HamsError> {
         info!("Starting ASYNC");
 
@@ -230,16 +501,62 @@ impl Hams {
         // for each service get a channel to allow us to shut it down
         // and when spawning save the handle to allow us to wait on it finishing.
 
-        let hams_webservice = webservice(self.clone(), ct.clone()).await;
+        let hams_webservice = match webservice(self.clone(), ct.clone()).await {
+            Ok(hams_webservice) => hams_webservice,
+            Err(e) => {
+                self.set_state(HamsState::Stopped)?;
+                return Err(e);
+            }
+        };
+        self.set_state(HamsState::Running)?;
+
+        // Stopped after the shutdown callback so the final push has the final values
+        let push_stop = CancellationToken::new();
+        let push = self
+            .config
+            .metrics
+            .push
+            .clone()
+            .map(|config| tokio::spawn(push::run(self.clone(), config, push_stop.clone())));
+        let statsd = self
+            .config
+            .metrics
+            .statsd
+            .clone()
+            .map(|config| tokio::spawn(statsd::run(self.clone(), config, push_stop.clone())));
+        #[cfg(feature = "otel")]
+        let otel = self
+            .config
+            .otel
+            .clone()
+            .map(|config| tokio::spawn(otel::run(self.clone(), config, push_stop.clone())));
 
         let my_shutdown_cb = self.shutdown_cb.clone();
 
         info!("Starting Tokio spawn");
 
-        let mut sig_terminate = signal(SignalKind::terminate())?;
-        let mut sig_quit = signal(SignalKind::quit())?;
-        let mut sig_hup = signal(SignalKind::hangup())?;
-        info!("registered signal handlers: TERM, QUIT, HUP");
+        let mut signals = self
+            .config
+            .signals
+            .iter()
+            .map(|stop| Ok((*stop, signal(stop_signal_kind(*stop))?)))
+            .collect::<Result<Vec<_>, std::io::Error>>()?;
+        info!(
+            "registered signal handlers: {:?}",
+            signals
+                .iter()
+                .map(|(stop, _)| stop.name())
+                .collect::<Vec<_>>()
+        );
+        // Never ready without signals, leaving them to the application
+        let stop_signal = std::future::poll_fn(|cx| {
+            for (stop, signal) in signals.iter_mut() {
+                if signal.poll_recv(cx).is_ready() {
+                    return Poll::Ready(*stop);
+                }
+            }
+            Poll::Pending
+        });
 
         info!("Waiting on signal handlers");
         tokio::select! {
@@ -250,24 +567,28 @@ impl Hams {
             _ = ct.cancelled() => {
                 info!("Cancellation Token cancelled");
             },
-            _ = tokio::signal::ctrl_c() => {
-                info!("Received ctrl-c signal: {:?}", my_shutdown_cb);
-            },
-            _ = sig_terminate.recv() => {
-                info!("Received SIGTERM");
-            },
-            _ = sig_quit.recv() => {
-                info!("Received SIGQUIT");
-            },
-            _ = sig_hup.recv() => {
-                info!("Received SIGHUP");
+            stop = stop_signal => {
+                info!("Received {}", stop.name());
             },
         };
         info!("Signal handlers completed");
         // Send ct.cancel() in case we exited the select based on a signal
         ct.cancel();
+        self.set_state(HamsState::Stopping)?;
 
         Hams::call_shutdown_callback(my_shutdown_cb.lock()?.as_ref())?;
+        push_stop.cancel();
+        if let Some(push) = push {
+            push.await?;
+        }
+        if let Some(statsd) = statsd {
+            statsd.await?;
+        }
+        #[cfg(feature = "otel")]
+        if let Some(otel) = otel {
+            otel.await?;
+        }
+        self.set_state(HamsState::Stopped)?;
 
         info!("start_async is now complete for HaMS {}", self.name);
         Ok(())
@@ -295,16 +616,44 @@ impl Hams {
 /**
 Start the port listening and exposing the service on it
 */
-pub async fn webservice<'a>(hams: Hams, ct: CancellationToken) -> tokio::task::JoinHandle<()> {
-    use webservice::hams_service;
+pub async fn webservice<'a>(
+    hams: Hams,
+    ct: CancellationToken,
+) -> Result<tokio::task::JoinHandle<()>, HamsError> {
+    use webservice::{hams_service, tls};
 
     let api = hams_service(hams.clone());
 
-    let (_addr, server) =
-        warp::serve(api).bind_with_graceful_shutdown(hams.address, ct.cancelled_owned());
+    match &hams.tls {
+        Some(tls_config) => {
+            let server_config = tls::server_config(tls_config)?;
+            let (address, server) =
+                tls::serve(warp::service(api), hams.address, server_config, ct).await?;
+
+            info!(
+                "Serving HaMS ({}) with TLS on address {}",
+                hams.name, address
+            );
+            Ok(tokio::task::spawn(server))
+        }
+        None => {
+            let (_addr, server) =
+                warp::serve(api).bind_with_graceful_shutdown(hams.address, ct.cancelled_owned());
+
+            info!("Serving HaMS ({}) on address {}", hams.name, hams.address);
+            Ok(tokio::task::spawn(server))
+        }
+    }
+}
 
-    info!("Serving HaMS ({}) on address {}", hams.name, hams.address);
-    tokio::task::spawn(server)
+/// Kind of the signal to listen for
+fn stop_signal_kind(stop: StopSignal) -> SignalKind {
+    match stop {
+        StopSignal::Interrupt => SignalKind::interrupt(),
+        StopSignal::Terminate => SignalKind::terminate(),
+        StopSignal::Quit => SignalKind::quit(),
+        StopSignal::Hangup => SignalKind::hangup(),
+    }
 }
 
 #[cfg(test)]
@@ -317,14 +666,14 @@ mod tests {
 
     /// Create a hams then assign the prometheus callback
     /// Check that callback is responding correctly
-    #[test]
-    fn test_prometheus_callback() {
+    #[tokio::test]
+    async fn test_prometheus_callback() {
         let mut hams = Hams::new(HamsConfig::default());
 
         extern "C" fn prometheus(ptr: *const c_void) -> *mut libc::c_char {
             let state = unsafe { &*(ptr as *const String) };
 
-            let prometheus = format!("test {state}");
+            let prometheus = format!("test{{state=\"{state}\"}} 1");
             let c_str_prometheus = std::ffi::CString::new(prometheus).unwrap();
 
             c_str_prometheus.into_raw()
@@ -338,10 +687,11 @@ mod tests {
             }
         }
 
+        let state = "splat".to_string();
         let prometheus_cb = PrometheusCallback {
             my_cb: prometheus,
             my_cb_free: prometheus_free,
-            state: &"splat".to_string() as *const String as *const c_void,
+            state: &state as *const String as *const c_void,
         };
 
         hams.register_prometheus(
@@ -350,16 +700,41 @@ mod tests {
             prometheus_cb.state,
         )
         .expect("Registered prometheus");
-
-        let prometheus_cb = hams.prometheus_cb.lock().unwrap();
-        let prometheus_cb = prometheus_cb.as_ref().unwrap();
-
-        let ptr = (prometheus_cb.my_cb)(prometheus_cb.state);
-        let c_str = unsafe { std::ffi::CStr::from_ptr(ptr) };
-        let str_slice = c_str.to_str().unwrap();
-        assert_eq!(str_slice, "test splat");
-
-        (prometheus_cb.my_cb_free)(ptr);
+        hams.register_prometheus(
+            prometheus_cb.my_cb,
+            prometheus_cb.my_cb_free,
+            prometheus_cb.state,
+        )
+        .expect("Replaced prometheus");
+        let library = "library".to_string();
+        hams.register_collector(
+            "library",
+            prometheus_cb.my_cb,
+            prometheus_cb.my_cb_free,
+            &library as *const String as *const c_void,
+        )
+        .expect("Registered collector");
+
+        assert_eq!(
+            hams.collectors
+                .collect(Format::Prometheus, |_| false)
+                .await
+                .unwrap(),
+            "test{state=\"splat\"} 1\ntest{state=\"library\"} 1\n"
+        );
+
+        hams.deregister_prometheus()
+            .expect("Deregistered prometheus");
+        hams.deregister_prometheus()
+            .expect("Deregistering twice is not an error");
+        assert_eq!(
+            hams.collectors
+                .collect(Format::Prometheus, |_| false)
+                .await
+                .unwrap(),
+            "test{state=\"library\"} 1\n"
+        );
+        assert!(hams.deregister_collector("default").is_err());
     }
 
     /// Create a hams then start and stop it
@@ -369,7 +744,31 @@ mod tests {
         let mut hams = Hams::new(HamsConfig::default());
         hams.start().expect("Started");
         thread::sleep(Duration::from_secs(1));
+        assert_eq!(hams.state().unwrap(), HamsState::Running);
         hams.stop().expect("Stopped");
+        assert_eq!(hams.state().unwrap(), HamsState::Stopped);
+    }
+
+    /// Application details set after creation are shared with clones such as the service thread
+    #[test]
+    fn test_app_info() {
+        let hams = Hams::new(HamsConfig::default());
+        let service = hams.clone();
+        assert_eq!(service.app_info.read().unwrap().version, "UNDEFINED");
+
+        hams.set_version("1.2.3").unwrap();
+        hams.set_build_info(Some("abc123"), None).unwrap();
+        hams.set_label("region", Some("eu")).unwrap();
+        hams.set_label("zone", Some("a")).unwrap();
+        hams.set_label("zone", None).unwrap();
+
+        let app_info = service.app_info.read().unwrap();
+        assert_eq!(app_info.version, "1.2.3");
+        assert_eq!(app_info.git_sha.as_deref(), Some("abc123"));
+        assert_eq!(app_info.build_time, None);
+        assert_eq!(app_info.labels.len(), 1);
+        assert_eq!(app_info.labels["region"], "eu");
+        assert_eq!(service.state().unwrap(), HamsState::Stopped);
     }
 
     /// Test add and remove alive and ready checks
@@ -0,0 +1,418 @@
+//! Export of the native metrics and of check spans to an OpenTelemetry collector over OTLP/HTTP
+//!
+//! Each evaluation of a check is recorded as a span with a child span for every probe it ran, carrying
+//! the result of the probe. Spans are kept in a bounded buffer and sent with the metrics every interval
+//! and once more during shutdown, as the OTLP/HTTP JSON encoding to `/v1/traces` and `/v1/metrics` of
+//! the collector. The resource of both is the name and version of the application set on [Hams].
+
+use std::{
+    collections::{hash_map::RandomState, VecDeque},
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+
+use log::{debug, info, warn};
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde_json::{json, Value as Json};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use super::{check::HealthCheckResult, config::OtelConfig, Hams};
+use crate::{
+    error::HamsError,
+    metrics::otlp::{self, attributes, nanos},
+};
+
+/// Most spans kept between exports. The oldest are dropped when the collector cannot keep up
+const MAX_SPANS: usize = 2048;
+
+/// Name of the instrumentation scope of the spans and metrics
+const SCOPE: &str = "hams";
+
+/// OTLP status code of a span that succeeded
+const STATUS_OK: u8 = 1;
+/// OTLP status code of a span that failed
+const STATUS_ERROR: u8 = 2;
+/// OTLP kind of a span within the application
+const KIND_INTERNAL: u8 = 1;
+
+/// A finished span waiting to be exported
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: String,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<Json>,
+    ok: bool,
+}
+
+impl Span {
+    fn to_json(&self) -> Json {
+        let mut span = json!({
+            "traceId": self.trace_id,
+            "spanId": self.span_id,
+            "name": self.name,
+            "kind": KIND_INTERNAL,
+            "startTimeUnixNano": nanos(self.start),
+            "endTimeUnixNano": nanos(self.end),
+            "attributes": self.attributes,
+            "status": {"code": if self.ok { STATUS_OK } else { STATUS_ERROR }},
+        });
+        if let Some(parent) = &self.parent_span_id {
+            span["parentSpanId"] = json!(parent);
+        }
+        span
+    }
+}
+
+/// Spans of the checks waiting to be exported, shared by the checks and the exporter
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Spans(Arc<Mutex<VecDeque<Span>>>);
+
+impl Spans {
+    /// Record a check as a span with a child span per probe, whose start and end times are in the order
+    /// of the probes in the result
+    pub(crate) fn check(
+        &self,
+        result: &HealthCheckResult,
+        start: SystemTime,
+        probes: &[(SystemTime, SystemTime)],
+    ) {
+        let trace_id = format!("{:016x}{:016x}", random_id(), random_id());
+        let span_id = format!("{:016x}", random_id());
+        let mut check_attributes = attributes([("hams.check", result.name.as_str())]);
+        check_attributes
+            .push(json!({"key": "hams.check.valid", "value": {"boolValue": result.valid}}));
+        if let Some(reason) = &result.reason {
+            check_attributes.extend(attributes([("hams.check.reason", reason.as_str())]));
+        }
+
+        let mut spans = vec![Span {
+            trace_id: trace_id.clone(),
+            span_id: span_id.clone(),
+            parent_span_id: None,
+            name: format!("check {}", result.name),
+            start,
+            end: SystemTime::now(),
+            attributes: check_attributes,
+            ok: result.valid,
+        }];
+        let details = result.details.as_deref().unwrap_or_default();
+        for (probe, (start, end)) in details.iter().zip(probes) {
+            let mut probe_attributes = attributes([
+                ("hams.check", result.name.as_str()),
+                ("hams.probe", probe.name.as_str()),
+            ]);
+            probe_attributes
+                .push(json!({"key": "hams.probe.valid", "value": {"boolValue": probe.valid}}));
+            if let Some(fault) = &probe.fault {
+                probe_attributes.extend(attributes([("hams.probe.fault", fault.as_str())]));
+            }
+            spans.push(Span {
+                trace_id: trace_id.clone(),
+                span_id: format!("{:016x}", random_id()),
+                parent_span_id: Some(span_id.clone()),
+                name: format!("probe {}", probe.name),
+                start: *start,
+                end: *end,
+                attributes: probe_attributes,
+                ok: probe.valid,
+            });
+        }
+
+        let Ok(mut buffer) = self.0.lock() else {
+            return;
+        };
+        buffer.extend(spans);
+        let excess = buffer.len().saturating_sub(MAX_SPANS);
+        buffer.drain(..excess);
+    }
+
+    /// Take the spans waiting to be exported
+    fn take(&self) -> Vec<Span> {
+        self.0
+            .lock()
+            .map(|mut buffer| buffer.drain(..).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A random, non-zero id, as trace and span ids must be
+fn random_id() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        let id = hasher.finish();
+        if id != 0 {
+            return id;
+        }
+    }
+}
+
+/// Sends the metrics and spans of a HaMS to an OpenTelemetry collector
+#[derive(Debug, Clone)]
+pub(crate) struct Exporter {
+    client: Client,
+    config: OtelConfig,
+}
+
+impl Exporter {
+    pub(crate) fn new(config: OtelConfig) -> Result<Self, HamsError> {
+        let client = Client::builder()
+            .connect_timeout(config.timeout)
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| HamsError::Message(format!("OTLP client: {e}")))?;
+        Ok(Exporter { client, config })
+    }
+
+    /// Send the current metrics and the spans recorded since the last export
+    pub(crate) async fn export(&self, hams: &Hams) -> Result<(), HamsError> {
+        let resource = resource(hams)?;
+        let scope = json!({"name": SCOPE, "version": hams.hams_version});
+
+        let spans: Vec<Json> = hams.spans.take().iter().map(Span::to_json).collect();
+        let traces = if spans.is_empty() {
+            Ok(())
+        } else {
+            let traces = json!({"resourceSpans": [{
+                "resource": resource,
+                "scopeSpans": [{"scope": scope, "spans": spans}],
+            }]});
+            self.send("/v1/traces", traces).await
+        };
+
+        // Metrics are sent even when the spans could not be
+        let metrics = json!({"resourceMetrics": [{
+            "resource": resource,
+            "scopeMetrics": [{"scope": scope, "metrics": native_metrics(hams)?}],
+        }]});
+        let metrics = self.send("/v1/metrics", metrics).await;
+        traces.and(metrics)
+    }
+
+    async fn send(&self, path: &str, body: Json) -> Result<(), HamsError> {
+        let url = format!("{}{path}", self.config.endpoint.trim_end_matches('/'));
+        let mut request = self
+            .client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_string());
+        for (name, value) in &self.config.headers {
+            request = request.header(name, value.resolve()?);
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| HamsError::Message(format!("OTLP export to {url} failed: {e}")))?;
+        if !response.status().is_success() {
+            return Err(HamsError::Message(format!(
+                "OTLP export to {url} replied {}",
+                response.status()
+            )));
+        }
+        debug!("OTLP export to {url}");
+        Ok(())
+    }
+}
+
+/// The resource of the application as OTLP
+fn resource(hams: &Hams) -> Result<Json, HamsError> {
+    let app_info = hams.app_info.read()?;
+    Ok(json!({"attributes": attributes([
+        ("service.name", hams.name.as_str()),
+        ("service.version", app_info.version.as_str()),
+    ])}))
+}
+
+/// The metrics of the application, of HaMS itself and, when configured, of the process as OTLP
+fn native_metrics(hams: &Hams) -> Result<Vec<Json>, HamsError> {
+    let time = SystemTime::now();
+    Ok(hams
+        .native_registries()?
+        .into_iter()
+        .flat_map(|registry| otlp::metrics(registry, time))
+        .collect())
+}
+
+/// Export every interval until stopped, then a final time. Failed exports are logged and the spans
+/// they held are dropped
+pub(crate) async fn run(hams: Hams, config: OtelConfig, stop: CancellationToken) {
+    info!("Exporting OTLP of {} to {}", hams.name, config.endpoint);
+    let exporter = match Exporter::new(config.clone()) {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            warn!("{e}");
+            return;
+        }
+    };
+
+    let mut interval = tokio::time::interval(config.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = exporter.export(&hams).await {
+                    warn!("{e}");
+                }
+            },
+            _ = stop.cancelled() => break,
+        }
+    }
+
+    if let Err(e) = exporter.export(&hams).await {
+        warn!("Final OTLP export: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use warp::Filter;
+
+    use super::*;
+    use crate::{
+        hams::config::HamsConfig,
+        probe::{manual::Manual, FFIProbe},
+    };
+
+    /// Bodies received by the stand-in collector by path
+    type Received = Arc<Mutex<Vec<(String, Json)>>>;
+
+    /// Serve a stand-in OpenTelemetry collector on a free port, returning its URL
+    fn collector(received: Received) -> String {
+        let route = warp::post()
+            .and(warp::path::full())
+            .and(warp::body::json())
+            .map(move |path: warp::path::FullPath, body: Json| {
+                received
+                    .lock()
+                    .unwrap()
+                    .push((path.as_str().to_string(), body));
+                warp::reply()
+            });
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{address}")
+    }
+
+    #[test]
+    fn test_random_id() {
+        assert_ne!(random_id(), random_id());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_export() {
+        let received = Received::default();
+        let config = HamsConfig {
+            name: "orders".to_string(),
+            otel: Some(OtelConfig {
+                endpoint: collector(received.clone()),
+                interval: Duration::from_secs(60),
+                headers: Default::default(),
+                timeout: Duration::from_secs(5),
+            }),
+            ..Default::default()
+        };
+        let hams = Hams::new(config.clone());
+        hams.set_version("1.2.3").unwrap();
+        hams.registry
+            .counter("jobs_total", "Jobs", &[])
+            .unwrap()
+            .inc(&[])
+            .unwrap();
+        hams.ready
+            .insert_async(FFIProbe::from(Manual::new("database", false)).into())
+            .await;
+        hams.ready
+            .insert_async(FFIProbe::from(Manual::new("cache", true)).into())
+            .await;
+        hams.ready.check(SystemTime::now()).await;
+
+        let stop = CancellationToken::new();
+        stop.cancel();
+        run(hams.clone(), config.otel.unwrap(), stop).await;
+
+        let received = received.lock().unwrap();
+        let traces = &received
+            .iter()
+            .find(|(path, _)| path == "/v1/traces")
+            .expect("Spans exported")
+            .1;
+        let resource = &traces["resourceSpans"][0]["resource"]["attributes"];
+        assert_eq!(resource[0]["value"]["stringValue"], "orders");
+        assert_eq!(resource[1]["value"]["stringValue"], "1.2.3");
+        let spans = traces["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0]["name"], "check ready");
+        assert_eq!(spans[0]["status"]["code"], STATUS_ERROR);
+        for probe in &spans[1..] {
+            assert_eq!(probe["parentSpanId"], spans[0]["spanId"]);
+            assert_eq!(probe["traceId"], spans[0]["traceId"]);
+        }
+        let database = spans
+            .iter()
+            .find(|s| s["name"] == "probe database")
+            .unwrap();
+        assert_eq!(database["status"]["code"], STATUS_ERROR);
+        let cache = spans.iter().find(|s| s["name"] == "probe cache").unwrap();
+        assert_eq!(cache["status"]["code"], STATUS_OK);
+
+        let metrics = &received
+            .iter()
+            .find(|(path, _)| path == "/v1/metrics")
+            .expect("Metrics exported")
+            .1;
+        let names: Vec<_> = metrics["resourceMetrics"][0]["scopeMetrics"][0]["metrics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|metric| metric["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"jobs_total"));
+        assert!(names.contains(&"hams_probe_up"));
+        assert!(hams.spans.take().is_empty(), "Exported spans are not kept");
+    }
+
+    /// The final export gives up on a collector that never replies
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_export_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        // Accept connections and hold them open without replying
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+        let config = OtelConfig {
+            endpoint,
+            interval: Duration::from_secs(60),
+            headers: Default::default(),
+            timeout: Duration::from_millis(100),
+        };
+
+        let stop = CancellationToken::new();
+        stop.cancel();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            run(Hams::new(HamsConfig::default()), config, stop),
+        )
+        .await
+        .expect("Exports should time out");
+    }
+}
@@ -0,0 +1,257 @@
+//! Pushing of the metrics to a Prometheus Pushgateway
+//!
+//! Short-lived jobs can finish before Prometheus scrapes them, so HaMS can push the same metrics it
+//! serves on `/hams/metrics` to a Pushgateway instead. The metrics replace those of the grouping at
+//! `/metrics/job/<job>/instance/<instance>` on each push. Label values that are not safe in a URL path
+//! are sent base64 encoded as the Pushgateway allows.
+
+use base64::{engine::general_purpose::URL_SAFE, Engine};
+use log::{debug, info, warn};
+use reqwest::{header::CONTENT_TYPE, Client, Method};
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use super::{config::PushConfig, info::hostname, Hams};
+use crate::{error::HamsError, metrics::Format};
+
+/// Pushes the metrics of a HaMS to the grouping of its job and instance
+#[derive(Debug, Clone)]
+pub(crate) struct Pusher {
+    client: Client,
+    /// URL of the grouping
+    url: String,
+}
+
+impl Pusher {
+    pub(crate) fn new(config: &PushConfig, name: &str) -> Result<Self, HamsError> {
+        let job = config.job.as_deref().unwrap_or(name);
+        let instance = config.instance.clone().unwrap_or_else(hostname);
+        let url = format!(
+            "{}/metrics/{}/{}",
+            config.url.trim_end_matches('/'),
+            segment("job", job),
+            segment("instance", &instance)
+        );
+        let client = Client::builder()
+            .connect_timeout(config.timeout)
+            .timeout(config.timeout)
+            .build()
+            .map_err(|e| HamsError::Message(format!("Pushgateway client: {e}")))?;
+        Ok(Pusher { client, url })
+    }
+
+    /// Replace the metrics of the grouping with the metrics of the HaMS
+    pub(crate) async fn push(&self, hams: &Hams) -> Result<(), HamsError> {
+        let metrics = hams.render_metrics(Format::Prometheus).await?;
+        self.send(Method::PUT, metrics).await
+    }
+
+    /// Delete the grouping and its metrics
+    pub(crate) async fn delete(&self) -> Result<(), HamsError> {
+        self.send(Method::DELETE, String::new()).await
+    }
+
+    async fn send(&self, method: Method, body: String) -> Result<(), HamsError> {
+        let response = self
+            .client
+            .request(method.clone(), &self.url)
+            .header(CONTENT_TYPE, Format::Prometheus.content_type())
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| HamsError::Message(format!("Pushgateway {method} failed: {e}")))?;
+        if !response.status().is_success() {
+            return Err(HamsError::Message(format!(
+                "Pushgateway {method} to {} replied {}",
+                self.url,
+                response.status()
+            )));
+        }
+        debug!("Pushgateway {method} to {}", self.url);
+        Ok(())
+    }
+}
+
+/// Push the metrics every interval until stopped, then push a final time and delete the grouping if
+/// configured. Failed pushes are logged and retried on the next interval
+pub(crate) async fn run(hams: Hams, config: PushConfig, stop: CancellationToken) {
+    let pusher = match Pusher::new(&config, &hams.name) {
+        Ok(pusher) => pusher,
+        Err(e) => {
+            warn!("{e}");
+            return;
+        }
+    };
+    info!("Pushing metrics of {} to {}", hams.name, pusher.url);
+
+    let mut interval = tokio::time::interval(config.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = pusher.push(&hams).await {
+                    warn!("{e}");
+                }
+            },
+            _ = stop.cancelled() => break,
+        }
+    }
+
+    if let Err(e) = pusher.push(&hams).await {
+        warn!("Final push: {e}");
+    }
+    if config.delete_on_exit {
+        if let Err(e) = pusher.delete().await {
+            warn!("{e}");
+        }
+    }
+}
+
+/// A grouping label and value as path segments, base64 encoded when the value is not URL safe
+fn segment(label: &str, value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~');
+    if value.is_empty() {
+        // An empty value can only be given encoded
+        format!("{label}@base64/=")
+    } else if value.chars().all(safe) {
+        format!("{label}/{value}")
+    } else {
+        format!("{label}@base64/{}", URL_SAFE.encode(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use warp::Filter;
+
+    use super::*;
+    use crate::hams::config::HamsConfig;
+
+    /// Requests received by the stand-in Pushgateway as method, path and body
+    type Received = Arc<Mutex<Vec<(String, String, String)>>>;
+
+    /// Serve a stand-in Pushgateway on a free port, returning its URL
+    fn pushgateway(received: Received) -> String {
+        let route = warp::method()
+            .and(warp::path::full())
+            .and(warp::body::bytes())
+            .map(
+                move |method: warp::http::Method,
+                      path: warp::path::FullPath,
+                      body: warp::hyper::body::Bytes| {
+                    received.lock().unwrap().push((
+                        method.to_string(),
+                        path.as_str().to_string(),
+                        String::from_utf8_lossy(&body).into_owned(),
+                    ));
+                    warp::reply()
+                },
+            );
+        let (address, server) = warp::serve(route).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{address}/")
+    }
+
+    fn push_config(url: &str) -> PushConfig {
+        PushConfig {
+            url: url.to_string(),
+            job: Some("batch".to_string()),
+            instance: Some("pod-1".to_string()),
+            interval: Duration::from_millis(20),
+            delete_on_exit: true,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_segment() {
+        assert_eq!(segment("job", "batch-1.a"), "job/batch-1.a");
+        assert_eq!(segment("job", "a/b"), "job@base64/YS9i");
+        assert_eq!(segment("instance", ""), "instance@base64/=");
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_push() {
+        let received = Received::default();
+        let url = pushgateway(received.clone());
+        let hams = Hams::new(HamsConfig::default());
+        hams.registry
+            .counter("jobs_total", "Jobs", &[])
+            .unwrap()
+            .inc(&[])
+            .unwrap();
+
+        let stop = CancellationToken::new();
+        let task = tokio::spawn(run(hams.clone(), push_config(&url), stop.clone()));
+        tokio::time::sleep(Duration::from_millis(70)).await;
+        stop.cancel();
+        task.await.unwrap();
+
+        let received = received.lock().unwrap();
+        let (last, pushes) = received.split_last().unwrap();
+        assert!(pushes.len() >= 2, "Periodic and final pushes");
+        for (method, path, body) in pushes {
+            assert_eq!(method, "PUT");
+            assert_eq!(path, "/metrics/job/batch/instance/pod-1");
+            assert!(body.contains("jobs_total 1\n"));
+        }
+        assert_eq!(last.0, "DELETE");
+        assert_eq!(last.1, "/metrics/job/batch/instance/pod-1");
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_push_keep_on_exit() {
+        let received = Received::default();
+        let url = pushgateway(received.clone());
+        let hams = Hams::new(HamsConfig::default());
+        let config = PushConfig {
+            job: None,
+            delete_on_exit: false,
+            interval: Duration::from_secs(60),
+            ..push_config(&url)
+        };
+
+        let stop = CancellationToken::new();
+        stop.cancel();
+        run(hams, config, stop).await;
+
+        let received = received.lock().unwrap();
+        assert!(received.iter().all(|(method, _, _)| method == "PUT"));
+        assert_eq!(received[0].1, "/metrics/job/NO_NAME/instance/pod-1");
+    }
+
+    /// The final push and delete give up on a Pushgateway that never replies
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_push_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        // Accept connections and hold them open without replying
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+        let config = PushConfig {
+            timeout: Duration::from_millis(100),
+            ..push_config(&url)
+        };
+
+        let stop = CancellationToken::new();
+        stop.cancel();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            run(Hams::new(HamsConfig::default()), config, stop),
+        )
+        .await
+        .expect("Pushes should time out");
+    }
+}
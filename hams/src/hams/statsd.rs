@@ -0,0 +1,181 @@
+//! Emitting of the native metrics to a StatsD or DogStatsD agent
+//!
+//! Some hosts only run a local StatsD agent, so HaMS can send the metrics of the application and of
+//! HaMS itself to it over UDP instead of being scraped. The address is resolved on each emit so a
+//! changed agent is picked up. StatsD over UDP is lossy by design and a failed send is only logged.
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use log::{debug, info, warn};
+use tokio::{net::UdpSocket, time::MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use super::{config::StatsdConfig, Hams};
+use crate::{error::HamsError, metrics::statsd::Encoder};
+
+/// Sends the metrics of a HaMS to a StatsD agent
+#[derive(Debug)]
+pub(crate) struct Emitter {
+    address: String,
+    packet_size: usize,
+    encoder: Encoder,
+    socket: Option<UdpSocket>,
+}
+
+impl Emitter {
+    pub(crate) fn new(config: &StatsdConfig) -> Self {
+        Emitter {
+            address: config.address.clone(),
+            packet_size: config.packet_size,
+            encoder: Encoder::new(config.flavor, config.prefix.as_deref()),
+            socket: None,
+        }
+    }
+
+    /// Send the metrics of the HaMS in as few datagrams as fit them
+    pub(crate) async fn emit(&mut self, hams: &Hams) -> Result<(), HamsError> {
+        // Resolved before encoding, so the increase of the counters is not lost when the agent is unknown
+        let agent = tokio::net::lookup_host(&self.address)
+            .await?
+            .next()
+            .ok_or_else(|| HamsError::Message(format!("No address for {}", self.address)))?;
+        let socket = match self.socket.take() {
+            Some(socket) if socket.local_addr()?.is_ipv4() == agent.is_ipv4() => socket,
+            _ => {
+                let local: SocketAddr = if agent.is_ipv4() {
+                    (Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    (Ipv6Addr::UNSPECIFIED, 0).into()
+                };
+                UdpSocket::bind(local).await?
+            }
+        };
+        let socket = self.socket.insert(socket);
+
+        let mut lines = Vec::new();
+        for registry in hams.native_registries()? {
+            self.encoder.encode(registry, &mut lines);
+        }
+        let packets = packets(&lines, self.packet_size);
+        for packet in &packets {
+            socket.send_to(packet.as_bytes(), agent).await?;
+        }
+        debug!(
+            "Sent {} metrics in {} datagrams to {agent}",
+            lines.len(),
+            packets.len()
+        );
+        Ok(())
+    }
+}
+
+/// Emit the metrics every interval until stopped, then emit a final time
+pub(crate) async fn run(hams: Hams, config: StatsdConfig, stop: CancellationToken) {
+    let mut emitter = Emitter::new(&config);
+    info!(
+        "Emitting metrics of {} to StatsD at {}",
+        hams.name, config.address
+    );
+
+    let mut interval = tokio::time::interval(config.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = emitter.emit(&hams).await {
+                    warn!("StatsD emit failed: {e}");
+                }
+            },
+            _ = stop.cancelled() => break,
+        }
+    }
+
+    if let Err(e) = emitter.emit(&hams).await {
+        warn!("Final StatsD emit failed: {e}");
+    }
+}
+
+/// Join the lines into datagrams of at most `size` bytes. A line longer than that is sent on its own
+fn packets(lines: &[String], size: usize) -> Vec<String> {
+    let mut packets: Vec<String> = Vec::new();
+    for line in lines {
+        match packets.last_mut() {
+            Some(packet) if packet.len() + 1 + line.len() <= size => {
+                packet.push('\n');
+                packet.push_str(line);
+            }
+            _ => packets.push(line.clone()),
+        }
+    }
+    packets
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::hams::config::{HamsConfig, StatsdFlavor};
+
+    #[test]
+    fn test_packets() {
+        let lines: Vec<String> = ["a:1|c", "b:2|c", "c:3|c", "a_very_long_name:4|g"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(
+            packets(&lines, 12),
+            ["a:1|c\nb:2|c", "c:3|c", "a_very_long_name:4|g"]
+        );
+        assert!(packets(&[], 12).is_empty());
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_run() {
+        let agent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let hams = Hams::new(HamsConfig::default());
+        let jobs = hams
+            .registry
+            .counter("jobs_total", "Jobs", &["queue"])
+            .unwrap();
+        jobs.inc(&["fast"]).unwrap();
+        let config = StatsdConfig {
+            address: agent.local_addr().unwrap().to_string(),
+            flavor: StatsdFlavor::Dogstatsd,
+            prefix: None,
+            interval: Duration::from_millis(20),
+            packet_size: 512,
+        };
+
+        let stop = CancellationToken::new();
+        let task = tokio::spawn(run(hams.clone(), config, stop.clone()));
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        jobs.inc_by(&["fast"], 2.0).unwrap();
+        stop.cancel();
+        task.await.unwrap();
+
+        let mut received = Vec::new();
+        let mut buffer = [0; 512];
+        while let Ok(Ok(length)) =
+            tokio::time::timeout(Duration::from_millis(100), agent.recv(&mut buffer)).await
+        {
+            received.extend(
+                String::from_utf8_lossy(&buffer[..length])
+                    .lines()
+                    .map(str::to_string),
+            );
+        }
+        let jobs: Vec<&String> = received
+            .iter()
+            .filter(|line| line.starts_with("jobs_total:"))
+            .collect();
+        assert_eq!(
+            jobs,
+            ["jobs_total:1|c|#queue:fast", "jobs_total:2|c|#queue:fast"]
+        );
+        assert!(received
+            .iter()
+            .any(|line| line.starts_with("hams_build_info:1|g|#name:NO_NAME,")));
+    }
+}
@@ -0,0 +1,322 @@
+//! Access logging and request metrics for the HaMS webservice
+//!
+//! Every request is logged through `log` as `key=value` pairs so the line survives forwarding by
+//! ffi-log2, and is counted per route in the metrics served on `/hams/metrics`. Routes are labeled by
+//! their path under the default prefix whatever the prefix is configured to, so dashboards and log
+//! filters do not depend on it.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::{log, log_enabled, Level};
+use warp::{http::Method, log::Info};
+
+use crate::hams::config::{AccessLogConfig, DEFAULT_PREFIX};
+
+tokio::task_local! {
+    /// Remote address of a connection accepted outside of warp, which then cannot report it, set
+    /// around each request served on the connection
+    pub(super) static PEER: SocketAddr;
+}
+
+/// Remote address of the request for the access log, or `-` when it is not known
+fn peer(remote_addr: Option<SocketAddr>) -> String {
+    remote_addr
+        .or_else(|| PEER.try_with(|peer| *peer).ok())
+        .map_or_else(|| "-".to_string(), |addr| addr.to_string())
+}
+
+/// Routes served by HaMS. Anything else is counted as `other` to keep the label set bounded
+pub(super) const ROUTES: [&str; 12] = [
+    "/hams/alive",
+    "/hams/ready",
+    "/hams/version",
+    "/hams/metrics",
+    "/hams/shutdown",
+    "/hams/info",
+    "/hams/loglevel",
+    "/hams/admin/probes",
+    "/hams/maintenance",
+    "/hams/admin/faults",
+    "/hams/events",
+    "/hams/openapi.json",
+];
+
+/// Routes with parameters in the path, labeled by their prefix
+pub(super) const PARAM_ROUTES: [(&str, &str); 2] = [
+    (
+        "/hams/admin/probes/",
+        "/hams/admin/probes/{check}/{probe}/{action}",
+    ),
+    ("/hams/admin/faults/", "/hams/admin/faults/{check}/{probe}"),
+];
+
+/// Routes polled by kubelet which are logged with the probe level and target
+const PROBE_ROUTES: [&str; 2] = ["/hams/alive", "/hams/ready"];
+
+/// Upper bounds in seconds of the request duration histogram buckets
+const BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// Map a request path under the configured prefix to the route label used in metrics
+fn route_label(prefix: &str, path: &str) -> &'static str {
+    let path = path.strip_suffix('/').unwrap_or(path);
+    let Some(path) = path.strip_prefix(prefix) else {
+        return "other";
+    };
+    let relative = |route: &'static str| &route[DEFAULT_PREFIX.len()..];
+    ROUTES
+        .iter()
+        .find(|route| relative(route) == path)
+        .copied()
+        .or_else(|| {
+            PARAM_ROUTES
+                .iter()
+                .find(|(start, _)| path.starts_with(relative(start)))
+                .map(|(_, route)| *route)
+        })
+        .unwrap_or("other")
+}
+
+/// Map a request method to the method label used in metrics
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        _ => "other",
+    }
+}
+
+/// Counters and latency histogram for one route
+#[derive(Debug, Default)]
+struct RouteMetrics {
+    /// Requests by method and status
+    requests: BTreeMap<(&'static str, u16), u64>,
+    /// Cumulative count of requests at or below each of [BUCKETS]
+    buckets: [u64; BUCKETS.len()],
+    /// Total duration of all requests in seconds
+    sum: f64,
+    /// Number of requests timed
+    count: u64,
+}
+
+/// Request counters and latency histograms per route
+#[derive(Debug, Default)]
+pub(crate) struct RequestMetrics {
+    routes: Mutex<BTreeMap<&'static str, RouteMetrics>>,
+}
+
+impl RequestMetrics {
+    /// Record a request served on the route
+    pub(crate) fn observe(
+        &self,
+        route: &'static str,
+        method: &Method,
+        status: u16,
+        elapsed: Duration,
+    ) {
+        let Ok(mut routes) = self.routes.lock() else {
+            return;
+        };
+        let route = routes.entry(route).or_default();
+
+        *route
+            .requests
+            .entry((method_label(method), status))
+            .or_default() += 1;
+
+        let seconds = elapsed.as_secs_f64();
+        for (bucket, bound) in route.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        route.sum += seconds;
+        route.count += 1;
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub(crate) fn render(&self) -> String {
+        let Ok(routes) = self.routes.lock() else {
+            return String::new();
+        };
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP hams_http_requests_total HTTP requests served by HaMS"
+        );
+        let _ = writeln!(out, "# TYPE hams_http_requests_total counter");
+        for (route, metrics) in routes.iter() {
+            for ((method, status), count) in metrics.requests.iter() {
+                let _ = writeln!(
+                    out,
+                    "hams_http_requests_total{{route=\"{route}\",method=\"{method}\",status=\"{status}\"}} {count}"
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP hams_http_request_duration_seconds Duration of HTTP requests served by HaMS"
+        );
+        let _ = writeln!(out, "# TYPE hams_http_request_duration_seconds histogram");
+        for (route, metrics) in routes.iter() {
+            for (bucket, bound) in metrics.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "hams_http_request_duration_seconds_bucket{{route=\"{route}\",le=\"{bound}\"}} {bucket}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "hams_http_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}",
+                metrics.count
+            );
+            let _ = writeln!(
+                out,
+                "hams_http_request_duration_seconds_sum{{route=\"{route}\"}} {}",
+                metrics.sum
+            );
+            let _ = writeln!(
+                out,
+                "hams_http_request_duration_seconds_count{{route=\"{route}\"}} {}",
+                metrics.count
+            );
+        }
+        out
+    }
+}
+
+impl AccessLogConfig {
+    /// Level and target to log a request to the given route
+    fn level_target(&self, route: &str) -> (Level, &str) {
+        if PROBE_ROUTES.contains(&route) {
+            (self.probe_level, &self.probe_target)
+        } else {
+            (self.level, &self.target)
+        }
+    }
+}
+
+/// Filter to wrap the HaMS routes which logs and counts every request
+pub(crate) fn access_log(
+    config: AccessLogConfig,
+    prefix: String,
+    metrics: Arc<RequestMetrics>,
+) -> warp::log::Log<impl Fn(Info<'_>) + Clone> {
+    warp::log::custom(move |info: Info<'_>| {
+        let route = route_label(&prefix, info.path());
+        metrics.observe(route, info.method(), info.status().as_u16(), info.elapsed());
+
+        if !config.enabled {
+            return;
+        }
+        let (level, target) = config.level_target(route);
+        if log_enabled!(target: target, level) {
+            log!(
+                target: target,
+                level,
+                "method={} path={} status={} duration_ms={:.3} peer={} user_agent={:?}",
+                info.method(),
+                info.path(),
+                info.status().as_u16(),
+                info.elapsed().as_secs_f64() * 1000.0,
+                peer(info.remote_addr()),
+                info.user_agent().unwrap_or("-"),
+            );
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peer() {
+        let remote: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let accepted: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+        assert_eq!(peer(None), "-");
+        assert_eq!(peer(Some(remote)), "10.0.0.1:4000");
+        PEER.scope(accepted, async {
+            assert_eq!(peer(None), "10.0.0.2:5000");
+            assert_eq!(peer(Some(remote)), "10.0.0.1:4000");
+        })
+        .await;
+    }
+
+    #[test]
+    fn test_route_label() {
+        assert_eq!(route_label(DEFAULT_PREFIX, "/hams/alive"), "/hams/alive");
+        assert_eq!(route_label(DEFAULT_PREFIX, "/hams/ready/"), "/hams/ready");
+        assert_eq!(route_label(DEFAULT_PREFIX, "/hams/splat"), "other");
+        assert_eq!(
+            route_label(DEFAULT_PREFIX, "/hams/admin/probes/ready/db/disable"),
+            "/hams/admin/probes/{check}/{probe}/{action}"
+        );
+        assert_eq!(route_label(DEFAULT_PREFIX, "/"), "other");
+
+        assert_eq!(
+            route_label("/ops/health", "/ops/health/alive"),
+            "/hams/alive"
+        );
+        assert_eq!(
+            route_label("/ops/health", "/ops/health/admin/faults/ready/db"),
+            "/hams/admin/faults/{check}/{probe}"
+        );
+        assert_eq!(route_label("/ops/health", "/hams/alive"), "other");
+        assert_eq!(route_label("/ops/health", "/ops/healthalive"), "other");
+    }
+
+    #[test]
+    fn test_level_target() {
+        let config = AccessLogConfig::default();
+        assert_eq!(
+            config.level_target(route_label(DEFAULT_PREFIX, "/hams/alive")),
+            (Level::Debug, "hams::access::probe")
+        );
+        assert_eq!(
+            config.level_target(route_label(DEFAULT_PREFIX, "/hams/metrics")),
+            (Level::Info, "hams::access")
+        );
+    }
+
+    #[test]
+    fn test_request_metrics() {
+        let metrics = RequestMetrics::default();
+        metrics.observe("/hams/alive", &Method::GET, 200, Duration::from_millis(2));
+        metrics.observe("/hams/alive", &Method::GET, 200, Duration::from_millis(200));
+        metrics.observe("/hams/alive", &Method::HEAD, 503, Duration::from_millis(2));
+        metrics.observe("other", &Method::PATCH, 404, Duration::ZERO);
+
+        let text = metrics.render();
+        assert!(text.contains(
+            "hams_http_requests_total{route=\"/hams/alive\",method=\"GET\",status=\"200\"} 2\n"
+        ));
+        assert!(text.contains(
+            "hams_http_requests_total{route=\"/hams/alive\",method=\"HEAD\",status=\"503\"} 1\n"
+        ));
+        assert!(text.contains(
+            "hams_http_requests_total{route=\"other\",method=\"other\",status=\"404\"} 1\n"
+        ));
+        assert!(text.contains(
+            "hams_http_request_duration_seconds_bucket{route=\"/hams/alive\",le=\"0.0025\"} 2\n"
+        ));
+        assert!(text.contains(
+            "hams_http_request_duration_seconds_bucket{route=\"/hams/alive\",le=\"+Inf\"} 3\n"
+        ));
+        assert!(
+            text.contains("hams_http_request_duration_seconds_count{route=\"/hams/alive\"} 3\n")
+        );
+    }
+}
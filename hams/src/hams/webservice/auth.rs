@@ -0,0 +1,385 @@
+//! Authentication of the HaMS webservice routes
+//!
+//! Routes are grouped by sensitivity and each group has an [Access] policy from [AuthConfig]. Callers are
+//! identified from the `Authorization` header or the client certificate attached by [super::tls].
+
+use std::{fmt, sync::Arc};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use log::warn;
+use warp::{reject::Rejection, Filter};
+use x509_parser::parse_x509_certificate;
+
+use crate::{
+    error::HamsError,
+    hams::config::{Access, AuthConfig, Secret, TlsConfig},
+};
+
+/// Challenge sent with 401 Unauthorized
+pub(crate) const CHALLENGE: &str = r#"Basic realm="hams", Bearer realm="hams""#;
+
+/// Groups of routes sharing an access policy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RouteGroup {
+    /// alive and ready
+    Checks,
+    /// alive and ready with probe details
+    Verbose,
+    /// metrics
+    Metrics,
+    /// shutdown
+    Shutdown,
+    /// info
+    Info,
+    /// Runtime administration such as the log level
+    Admin,
+}
+
+/// Client certificate verified during the TLS handshake
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ClientIdentity {
+    /// Common name from the certificate subject
+    pub(crate) common_name: Option<String>,
+}
+
+/// The caller of a request
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Caller {
+    /// How the caller authenticated, None for anonymous callers
+    pub(crate) identity: Option<String>,
+}
+
+impl Caller {
+    pub(crate) fn is_authenticated(&self) -> bool {
+        self.identity.is_some()
+    }
+}
+
+/// What a verbose check request gets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum VerbosePolicy {
+    /// Reply with probe details
+    Allow,
+    /// Reply without probe details
+    Redact,
+    /// Reject with 401 Unauthorized
+    Deny,
+}
+
+/// Access policies and credentials of the webservice.
+///
+/// Secrets are read once when created, so a rotated secret takes effect when HaMS is created again
+#[derive(Clone, Default)]
+pub(crate) struct Auth {
+    config: AuthConfig,
+    /// Bearer tokens read from their secrets
+    tokens: Vec<String>,
+    /// Username and password read from its secret of each basic credential
+    basic: Vec<(String, String)>,
+    /// Client certificates are verified against a client CA
+    mtls: bool,
+}
+
+impl Auth {
+    pub(crate) fn new(config: AuthConfig, tls: Option<&TlsConfig>) -> Self {
+        let tokens = config
+            .bearer_tokens
+            .iter()
+            .filter_map(read_secret)
+            .collect();
+        let basic = config
+            .basic
+            .iter()
+            .filter_map(|basic| Some((basic.username.clone(), read_secret(&basic.password)?)))
+            .collect();
+        Auth {
+            config,
+            tokens,
+            basic,
+            mtls: tls.is_some_and(|tls| tls.client_ca.is_some()),
+        }
+    }
+
+    fn has_credentials(&self) -> bool {
+        !self.config.bearer_tokens.is_empty() || !self.config.basic.is_empty() || self.mtls
+    }
+
+    /// Access policy of a group. Without configured credentials everything but shutdown and admin
+    /// defaults to open. Those default to authenticated, which nobody can be, so they stay closed until
+    /// credentials are configured or the operator opens them explicitly.
+    pub(crate) fn access(&self, group: RouteGroup) -> Access {
+        let (configured, default) = match group {
+            RouteGroup::Checks => (self.config.checks, Access::Open),
+            RouteGroup::Verbose => (self.config.verbose, Access::Redact),
+            RouteGroup::Metrics => (self.config.metrics, Access::Authenticated),
+            RouteGroup::Shutdown => (self.config.shutdown, Access::Authenticated),
+            RouteGroup::Info => (self.config.info, Access::Authenticated),
+            RouteGroup::Admin => (self.config.admin, Access::Authenticated),
+        };
+        let sensitive = matches!(group, RouteGroup::Shutdown | RouteGroup::Admin);
+        configured.unwrap_or(if sensitive || self.has_credentials() {
+            default
+        } else {
+            Access::Open
+        })
+    }
+
+    /// Whether the caller may use a group of routes
+    pub(crate) fn allows(&self, group: RouteGroup, caller: &Caller) -> bool {
+        self.access(group) == Access::Open || caller.is_authenticated()
+    }
+
+    /// What the caller gets when asking for probe details
+    pub(crate) fn verbose_policy(&self, caller: &Caller) -> VerbosePolicy {
+        match (self.access(RouteGroup::Verbose), caller.is_authenticated()) {
+            (Access::Open, _) | (_, true) => VerbosePolicy::Allow,
+            (Access::Redact, false) => VerbosePolicy::Redact,
+            (Access::Authenticated, false) => VerbosePolicy::Deny,
+        }
+    }
+
+    /// Identify the caller from the Authorization header or the client certificate
+    pub(crate) fn authenticate(
+        &self,
+        authorization: Option<&str>,
+        client: Option<&ClientIdentity>,
+    ) -> Caller {
+        let identity = authorization
+            .and_then(|authorization| self.authenticate_header(authorization))
+            .or_else(|| client.and_then(|client| self.authenticate_client(client)));
+        Caller { identity }
+    }
+
+    fn authenticate_header(&self, authorization: &str) -> Option<String> {
+        let (scheme, credentials) = authorization.trim().split_once(' ')?;
+        let credentials = credentials.trim();
+
+        if scheme.eq_ignore_ascii_case("bearer") {
+            self.tokens
+                .iter()
+                .any(|token| constant_time_eq(token.as_bytes(), credentials.as_bytes()))
+                .then(|| "bearer".to_string())
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(STANDARD.decode(credentials).ok()?).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+            self.basic
+                .iter()
+                .any(|(expected_username, expected_password)| {
+                    expected_username == username
+                        && constant_time_eq(expected_password.as_bytes(), password.as_bytes())
+                })
+                .then(|| format!("basic:{username}"))
+        } else {
+            None
+        }
+    }
+
+    fn authenticate_client(&self, client: &ClientIdentity) -> Option<String> {
+        let name = client.common_name.as_deref();
+        let accepted = self.mtls
+            && (self.config.client_names.is_empty()
+                || name.is_some_and(|name| self.config.client_names.iter().any(|n| n == name)));
+        accepted.then(|| format!("cert:{}", name.unwrap_or("-")))
+    }
+}
+
+/// Never print the credentials read from secrets
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Auth")
+            .field("config", &self.config)
+            .field("mtls", &self.mtls)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Read a secret, which no credential will match when it cannot be read
+fn read_secret(secret: &Secret) -> Option<String> {
+    secret
+        .resolve()
+        .map_err(|e| warn!("Unable to read secret {secret:?}: {e}"))
+        .ok()
+}
+
+/// Compare a presented credential with a secret without leaking where they differ
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Extract the caller of a request
+pub(crate) fn with_caller(
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = (Caller,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and(warp::ext::optional::<ClientIdentity>())
+        .map(
+            move |authorization: Option<String>, client: Option<ClientIdentity>| {
+                auth.authenticate(authorization.as_deref(), client.as_ref())
+            },
+        )
+}
+
+/// Reject callers not allowed to use the group of routes
+pub(crate) fn require(
+    auth: Arc<Auth>,
+    group: RouteGroup,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    with_caller(auth.clone())
+        .and_then(move |caller: Caller| {
+            let allowed = auth.allows(group, &caller);
+            async move {
+                if allowed {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(HamsError::Unauthorized))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// Extract what the caller gets for a verbose check
+pub(crate) fn with_verbose_policy(
+    auth: Arc<Auth>,
+) -> impl Filter<Extract = (VerbosePolicy,), Error = Rejection> + Clone {
+    with_caller(auth.clone()).map(move |caller: Caller| auth.verbose_policy(&caller))
+}
+
+/// Common name of the subject of a DER encoded X.509 certificate. The last common name is the most specific
+pub(crate) fn common_name(certificate: &[u8]) -> Option<String> {
+    let (_, certificate) = parse_x509_certificate(certificate).ok()?;
+    let name = certificate.subject().iter_common_name().last()?;
+    name.as_str().ok().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tokio_rustls::rustls::pki_types::{pem::PemObject, CertificateDer};
+
+    use super::*;
+    use crate::hams::config::BasicCredential;
+
+    fn auth() -> Auth {
+        Auth::new(
+            AuthConfig {
+                bearer_tokens: vec![Secret::Value("t0ken".to_string())],
+                basic: vec![BasicCredential {
+                    username: "ops".to_string(),
+                    password: Secret::Value("letmein".to_string()), // pragma: allowlist secret
+                }],
+                client_names: vec!["kubelet-test".to_string()],
+                ..Default::default()
+            },
+            Some(&TlsConfig {
+                cert: PathBuf::from("server.pem"),
+                key: PathBuf::from("server.key"),
+                client_ca: Some(PathBuf::from("ca.pem")),
+            }),
+        )
+    }
+
+    #[test]
+    fn test_default_access() {
+        let open = Auth::default();
+        for group in [
+            RouteGroup::Checks,
+            RouteGroup::Verbose,
+            RouteGroup::Metrics,
+            RouteGroup::Info,
+        ] {
+            assert_eq!(open.access(group), Access::Open);
+        }
+
+        // Without credentials shutdown and admin are closed to everyone unless opened explicitly
+        assert_eq!(open.access(RouteGroup::Shutdown), Access::Authenticated);
+        assert_eq!(open.access(RouteGroup::Admin), Access::Authenticated);
+        assert!(!open.allows(RouteGroup::Admin, &Caller::default()));
+        let opened = Auth::new(
+            AuthConfig {
+                admin: Some(Access::Open),
+                ..Default::default()
+            },
+            None,
+        );
+        assert!(opened.allows(RouteGroup::Admin, &Caller::default()));
+        assert!(!opened.allows(RouteGroup::Shutdown, &Caller::default()));
+
+        let auth = auth();
+        assert_eq!(auth.access(RouteGroup::Checks), Access::Open);
+        assert_eq!(auth.access(RouteGroup::Verbose), Access::Redact);
+        assert_eq!(auth.access(RouteGroup::Metrics), Access::Authenticated);
+        assert_eq!(auth.access(RouteGroup::Shutdown), Access::Authenticated);
+        assert_eq!(auth.access(RouteGroup::Info), Access::Authenticated);
+        assert_eq!(auth.access(RouteGroup::Admin), Access::Authenticated);
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let auth = auth();
+
+        let caller = auth.authenticate(Some("Bearer t0ken"), None);
+        assert_eq!(caller.identity.as_deref(), Some("bearer"));
+        assert!(!auth
+            .authenticate(Some("Bearer t0ke"), None)
+            .is_authenticated());
+
+        let basic = format!("Basic {}", STANDARD.encode("ops:letmein"));
+        let caller = auth.authenticate(Some(&basic), None);
+        assert_eq!(caller.identity.as_deref(), Some("basic:ops"));
+        let basic = format!("Basic {}", STANDARD.encode("ops:guess"));
+        assert!(!auth.authenticate(Some(&basic), None).is_authenticated());
+
+        let client = ClientIdentity {
+            common_name: Some("kubelet-test".to_string()),
+        };
+        let caller = auth.authenticate(None, Some(&client));
+        assert_eq!(caller.identity.as_deref(), Some("cert:kubelet-test"));
+        let client = ClientIdentity {
+            common_name: Some("intruder".to_string()),
+        };
+        assert!(!auth.authenticate(None, Some(&client)).is_authenticated());
+
+        assert!(!auth.authenticate(None, None).is_authenticated());
+        assert_eq!(
+            auth.verbose_policy(&Caller::default()),
+            VerbosePolicy::Redact
+        );
+    }
+
+    /// Secrets are read when created rather than on every request
+    #[test]
+    fn test_secret_read_once() {
+        let path = std::env::temp_dir().join(format!("hams-token-{}", std::process::id()));
+        std::fs::write(&path, "t0ken\n").unwrap();
+        let auth = Auth::new(
+            AuthConfig {
+                bearer_tokens: vec![
+                    Secret::File(path.clone()),
+                    Secret::Env("HAMS_TEST_MISSING_TOKEN".to_string()),
+                ],
+                ..Default::default()
+            },
+            None,
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(auth
+            .authenticate(Some("Bearer t0ken"), None)
+            .is_authenticated());
+        assert!(!format!("{auth:?}").contains("t0ken"));
+    }
+
+    #[test]
+    fn test_common_name() {
+        let client = CertificateDer::from_pem_file(
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/certs/client.pem"),
+        )
+        .unwrap();
+        assert_eq!(common_name(&client).as_deref(), Some("kubelet-test"));
+
+        assert_eq!(common_name(&[]), None);
+        assert_eq!(common_name(&client[..40]), None);
+    }
+}
@@ -0,0 +1,1910 @@
+pub(crate) mod access;
+pub(crate) mod auth;
+mod negotiate;
+mod openapi;
+pub(crate) mod tls;
+
+use std::convert::Infallible;
+
+use warp::{
+    body::BodyDeserializeError,
+    filters::BoxedFilter,
+    http::{header::WWW_AUTHENTICATE, HeaderValue, Method},
+    hyper::StatusCode,
+    reject::{InvalidQuery, MethodNotAllowed, Reject, Rejection},
+    reply::{json, Reply},
+    Filter,
+};
+
+use crate::{error::HamsError, hams::Hams};
+
+use super::check::HealthCheck;
+use auth::RouteGroup;
+
+impl Reject for HamsError {}
+
+async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Infallible> {
+    let (code, json_message) = if err.is_not_found() {
+        (StatusCode::NOT_FOUND, json(&"Not Found".to_string()))
+    } else if let Some(e) = err.find::<HamsError>() {
+        match e {
+            HamsError::Message(msg) => (StatusCode::BAD_REQUEST, json(&msg)),
+            HamsError::PoisonError => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"Poison Error".to_string()),
+            ),
+            HamsError::Unknown => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"Unknown Error".to_string()),
+            ),
+            HamsError::NotRunning => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"Not Running".to_string()),
+            ),
+            HamsError::SendError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"Send Error".to_string()),
+            ),
+            HamsError::IoError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"IO Error".to_string()),
+            ),
+            HamsError::AlreadyRunning => todo!(),
+            HamsError::Cancelled => todo!(),
+            HamsError::CallbackError => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"Callback Error".to_string()),
+            ),
+            HamsError::CollectorFailed(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, json(&e.to_string()))
+            }
+            HamsError::JoinError2 => todo!(),
+            HamsError::JoinError(_) => todo!(),
+            HamsError::NoThread => todo!(),
+            HamsError::NulError(_) => todo!(),
+            HamsError::ProbeNotGood(probename) => {
+                (StatusCode::SERVICE_UNAVAILABLE, json(probename))
+            }
+            HamsError::NotFound(_) => (StatusCode::NOT_FOUND, json(&e.to_string())),
+            HamsError::AlreadyExists(_) => (StatusCode::CONFLICT, json(&e.to_string())),
+            HamsError::InvalidArgument(_) => (StatusCode::BAD_REQUEST, json(&e.to_string())),
+            HamsError::PreflightCheck => todo!(),
+            HamsError::ShutdownCheck => todo!(),
+            HamsError::CStringToString(_) => todo!(),
+            HamsError::TryFromIntError(_) => todo!(),
+            HamsError::SystemTimeError(_) => todo!(),
+            HamsError::FFIError(_msg) => todo!(),
+            HamsError::Utf8Error(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"UTF-8 Error".to_string()),
+            ),
+            HamsError::FFIErrorBufferNotBigEnough => todo!(),
+            HamsError::NotError(_) => todo!(),
+            HamsError::JsonError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"JSON Error".to_string()),
+            ),
+            HamsError::Unauthorized => {
+                (StatusCode::UNAUTHORIZED, json(&"Unauthorized".to_string()))
+            }
+            HamsError::TlsError(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json(&"TLS Error".to_string()),
+            ),
+            // Add match arms for the remaining error variants here
+        }
+    } else if err.find::<InvalidQuery>().is_some() {
+        (StatusCode::BAD_REQUEST, json(&"Invalid Query".to_string()))
+    } else if let Some(e) = err.find::<BodyDeserializeError>() {
+        (StatusCode::BAD_REQUEST, json(&e.to_string()))
+    } else if err.find::<MethodNotAllowed>().is_some() {
+        (
+            StatusCode::METHOD_NOT_ALLOWED,
+            json(&"Method Not Allowed".to_string()),
+        )
+    } else {
+        eprintln!("unhandled error: {:?}", err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            json(&"Internal Server Error".to_string()),
+        )
+    };
+
+    let mut response = warp::reply::with_status(json_message, code).into_response();
+    if code == StatusCode::UNAUTHORIZED {
+        response
+            .headers_mut()
+            .insert(WWW_AUTHENTICATE, HeaderValue::from_static(auth::CHALLENGE));
+    }
+    Ok(response)
+}
+
+pub fn hams_service(
+    hams: Hams,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+    let shutdown = warp::path("shutdown")
+        .and(auth::require(hams.auth.clone(), RouteGroup::Shutdown))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::shutdown_handler);
+
+    let alive = warp::path("alive")
+        .and(with_check_request())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Checks))
+        .and(auth::with_verbose_policy(hams.auth.clone()))
+        .and(with_healthcheck(hams.alive.clone()))
+        .and_then(handlers::alive);
+
+    let ready = warp::path("ready")
+        .and(with_check_request())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Checks))
+        .and(auth::with_verbose_policy(hams.auth.clone()))
+        .and(with_healthcheck(hams.ready.clone()))
+        .and_then(handlers::ready);
+
+    let version = warp::path("version")
+        .and(warp::get())
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::version);
+
+    let metrics = warp::path("metrics")
+        .and(warp::get())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Metrics))
+        .and(warp::header::optional::<String>("accept"))
+        .and(warp::header::optional::<String>("accept-encoding"))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::metrics);
+
+    let info = warp::path("info")
+        .and(warp::get())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Info))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::info);
+
+    let loglevel = warp::path("loglevel")
+        .and(warp::get())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and_then(handlers::loglevel);
+
+    let set_loglevel = warp::path("loglevel")
+        .and(warp::put())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(warp::body::json())
+        .and_then(handlers::set_loglevel);
+
+    let probe_list = warp::path!("admin" / "probes")
+        .and(warp::get())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::probe_list);
+
+    let probe_action = warp::path!("admin" / "probes" / String / String / String)
+        .and(warp::post())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::probe_action);
+
+    let maintenance = warp::path("maintenance")
+        .and(warp::get())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::maintenance);
+
+    let set_maintenance = warp::path("maintenance")
+        .and(warp::put())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(warp::body::json())
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::set_maintenance);
+
+    let clear_maintenance = warp::path("maintenance")
+        .and(warp::delete())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::clear_maintenance);
+
+    let fault_list = warp::path!("admin" / "faults")
+        .and(warp::get())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::fault_list);
+
+    let inject_fault = warp::path!("admin" / "faults" / String / String)
+        .and(warp::put())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(warp::body::json())
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::inject_fault);
+
+    let clear_fault = warp::path!("admin" / "faults" / String / String)
+        .and(warp::delete())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Admin))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::clear_fault);
+
+    let events = warp::path("events")
+        .and(warp::get())
+        .and(auth::require(hams.auth.clone(), RouteGroup::Verbose))
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::events);
+
+    let openapi = warp::path("openapi.json")
+        .and(warp::get())
+        .and(with_hams(hams.clone()))
+        .and_then(handlers::openapi);
+
+    let access_log = access::access_log(
+        hams.access_log.clone(),
+        hams.config.prefix.clone(),
+        hams.request_metrics.clone(),
+    );
+
+    with_prefix(&hams.config.prefix)
+        .and(
+            version
+                .or(shutdown)
+                .or(alive)
+                .or(ready)
+                .or(metrics)
+                .or(info)
+                .or(loglevel)
+                .or(set_loglevel)
+                .or(probe_list)
+                .or(probe_action)
+                .or(maintenance)
+                .or(set_maintenance)
+                .or(clear_maintenance)
+                .or(fault_list)
+                .or(inject_fault)
+                .or(clear_fault)
+                .or(events)
+                .or(openapi)
+                .recover(handle_rejection),
+        )
+        .with(access_log)
+}
+
+/// Match the segments of the configured prefix
+fn with_prefix(prefix: &str) -> BoxedFilter<()> {
+    prefix
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .fold(warp::any().boxed(), |filter, segment| {
+            filter.and(warp::path(segment.to_string())).boxed()
+        })
+}
+
+fn with_healthcheck(
+    check: HealthCheck,
+) -> impl Filter<Extract = (HealthCheck,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || check.clone())
+}
+
+/// Extract the method, query and Accept header of a GET or HEAD request to a check endpoint
+fn with_check_request(
+) -> impl Filter<Extract = (Method, Vec<(String, String)>, Option<String>), Error = warp::Rejection>
+       + Clone {
+    warp::get()
+        .or(warp::head())
+        .unify()
+        .and(warp::method())
+        .and(warp::query::<Vec<(String, String)>>())
+        .and(warp::header::optional::<String>("accept"))
+}
+
+fn with_hams(
+    hams: Hams,
+) -> impl Filter<Extract = (Hams,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || hams.clone())
+}
+
+mod handlers {
+    use super::{
+        auth::VerbosePolicy,
+        negotiate::{accepts_gzip, metrics_format, metrics_reply, CheckFormat, CheckQuery},
+        openapi, Hams,
+    };
+    use crate::{
+        error::HamsError,
+        hams::{
+            check::HealthCheck,
+            config::CheckConfig,
+            fault::FaultRequest,
+            info::{hostname, rfc3339, uptime, InfoReply},
+            loglevel::{self, LogLevelUpdate},
+            maintenance::{MaintenanceReply, MaintenanceRequest},
+        },
+        metrics::{openmetrics, Format},
+    };
+    use futures::StreamExt;
+    use log::info;
+    use serde::Serialize;
+    use std::time::{Duration, SystemTime};
+    use utoipa::ToSchema;
+    use warp::{
+        http::{header::RETRY_AFTER, HeaderValue, Method, StatusCode},
+        reject::Rejection,
+        Reply,
+    };
+
+    /// Reply structure for Version response
+    #[derive(Serialize, ToSchema)]
+    pub(super) struct VersionReply {
+        /// Name of the application
+        name: String,
+        /// Version of the application
+        version: String,
+        /// Name of the HaMS package
+        hams_name: String,
+        /// Version of HaMS
+        hams_version: String,
+    }
+
+    /// Name and version of the application and of HaMS
+    #[utoipa::path(
+        get,
+        path = "/hams/version",
+        tag = "hams",
+        responses((status = 200, description = "Version details", body = VersionReply))
+    )]
+    pub async fn version(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        let version = hams
+            .app_info
+            .read()
+            .map_err(|_e| HamsError::PoisonError)?
+            .version
+            .clone();
+        let version_reply = VersionReply {
+            name: hams.name,
+            version,
+            hams_version: hams.hams_version,
+            hams_name: hams.hams_name,
+        };
+        Ok(warp::reply::json(&version_reply))
+    }
+
+    /// Request the service to shut down
+    #[utoipa::path(
+        post,
+        path = "/hams/shutdown",
+        tag = "hams",
+        operation_id = "shutdown",
+        responses(
+            (status = 200, description = "Shutdown requested", body = VersionReply),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn shutdown_handler(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        // TODO: Call shutdown
+        // Hams::tigger_callback(hams.shutdown_cb.clone());
+
+        version(hams).await
+    }
+
+    /// Liveness check. HEAD is also supported and replies with no body
+    #[utoipa::path(
+        get,
+        path = "/hams/alive",
+        tag = "hams",
+        params(
+            ("verbose" = Option<bool>, Query, description = "Include the result of each probe. A bare `?verbose` is true"),
+            ("probe" = Option<Vec<String>>, Query, description = "Only check the named probes. May be repeated or comma separated"),
+            ("exclude" = Option<Vec<String>>, Query, description = "Skip the named probes. May be repeated or comma separated"),
+            ("accept" = Option<String>, Header, description = "application/json, application/health+json or text/plain")
+        ),
+        responses(
+            (status = 200, description = "All selected probes pass", body = HealthCheckResult),
+            (status = 503, description = "A probe failed. The status is configurable per check", body = HealthCheckResult),
+            (status = 400, description = "Invalid query parameter"),
+            (status = 401, description = "Authentication required by the check or verbose access policy"),
+            (status = 404, description = "A probe or exclude names no probe of the check"),
+            (status = 429, description = "Too many checks running. The status is configurable per check")
+        )
+    )]
+    pub async fn alive(
+        method: Method,
+        params: Vec<(String, String)>,
+        accept: Option<String>,
+        verbose_policy: VerbosePolicy,
+        check: HealthCheck,
+    ) -> Result<impl warp::Reply, Rejection> {
+        check_handler(method, params, accept, verbose_policy, check).await
+    }
+
+    /// Readiness check. HEAD is also supported and replies with no body
+    #[utoipa::path(
+        get,
+        path = "/hams/ready",
+        tag = "hams",
+        params(
+            ("verbose" = Option<bool>, Query, description = "Include the result of each probe. A bare `?verbose` is true"),
+            ("probe" = Option<Vec<String>>, Query, description = "Only check the named probes. May be repeated or comma separated"),
+            ("exclude" = Option<Vec<String>>, Query, description = "Skip the named probes. May be repeated or comma separated"),
+            ("accept" = Option<String>, Header, description = "application/json, application/health+json or text/plain")
+        ),
+        responses(
+            (status = 200, description = "All selected probes pass", body = HealthCheckResult),
+            (status = 503, description = "A probe failed. The status is configurable per check", body = HealthCheckResult),
+            (status = 400, description = "Invalid query parameter"),
+            (status = 401, description = "Authentication required by the check or verbose access policy"),
+            (status = 404, description = "A probe or exclude names no probe of the check"),
+            (status = 429, description = "Too many checks running. The status is configurable per check")
+        )
+    )]
+    pub async fn ready(
+        method: Method,
+        params: Vec<(String, String)>,
+        accept: Option<String>,
+        verbose_policy: VerbosePolicy,
+        check: HealthCheck,
+    ) -> Result<impl warp::Reply, Rejection> {
+        check_handler(method, params, accept, verbose_policy, check).await
+    }
+
+    /// Handler for the alive and ready endpoints
+    ///
+    /// The query selects verbosity and probes, the Accept header selects the output format.
+    /// Probe details, including the failing probes listed by plain text, are only given to callers
+    /// allowed by the verbose policy.
+    async fn check_handler(
+        method: Method,
+        params: Vec<(String, String)>,
+        accept: Option<String>,
+        verbose_policy: VerbosePolicy,
+        check: HealthCheck,
+    ) -> Result<impl warp::Reply, Rejection> {
+        let mut query = CheckQuery::try_from(params)?;
+        if query.verbose {
+            match verbose_policy {
+                VerbosePolicy::Allow => {}
+                VerbosePolicy::Redact => query.verbose = false,
+                VerbosePolicy::Deny => return Err(HamsError::Unauthorized.into()),
+            }
+        }
+        let format = CheckFormat::negotiate(accept.as_deref());
+        check.validate_filter(&query.filter).await?;
+        // Plain text lists the failing probes even when not verbose
+        let details = query.verbose
+            || (format == CheckFormat::Text && verbose_policy == VerbosePolicy::Allow);
+
+        let Some(health_check) = check
+            .try_check_with(SystemTime::now(), &query.filter, details)
+            .await
+        else {
+            let mut response = warp::reply::with_status(
+                warp::reply::json(&"Too many checks running".to_string()),
+                StatusCode::from_u16(check.config.limit_status)
+                    .unwrap_or(StatusCode::TOO_MANY_REQUESTS),
+            )
+            .into_response();
+            if let Some(retry_after) = check.config.retry_after {
+                response
+                    .headers_mut()
+                    .insert(RETRY_AFTER, HeaderValue::from(retry_after.as_secs()));
+            }
+            return Ok(response);
+        };
+
+        let mut response = format.reply(
+            &method,
+            check_status(&check.config, health_check.valid),
+            &health_check,
+            query.verbose,
+        )?;
+
+        if let (false, Some(retry_after)) = (health_check.valid, check.config.retry_after) {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after.as_secs()));
+        }
+        Ok(response.into_response())
+    }
+
+    /// The one place that decides the HTTP status of a check reply.
+    ///
+    /// A passing check is 200 OK, a failing check uses the configured failure status which defaults to 503.
+    pub(super) fn check_status(config: &CheckConfig, valid: bool) -> StatusCode {
+        if valid {
+            StatusCode::OK
+        } else {
+            StatusCode::from_u16(config.fail_status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+
+    /// Build, runtime and process details of the application
+    #[utoipa::path(
+        get,
+        path = "/hams/info",
+        tag = "hams",
+        responses(
+            (status = 200, description = "Application details", body = InfoReply),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn info(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        let app = hams
+            .app_info
+            .read()
+            .map_err(|_e| HamsError::PoisonError)?
+            .clone();
+        let state = hams.state()?;
+        let config = serde_json::to_value(hams.config.as_ref()).map_err(HamsError::from)?;
+
+        let info_reply = InfoReply {
+            name: hams.name,
+            app,
+            hams_name: hams.hams_name,
+            hams_version: hams.hams_version,
+            start_time: rfc3339(hams.created),
+            uptime_seconds: uptime(hams.created),
+            pid: std::process::id(),
+            hostname: hostname(),
+            state,
+            config,
+        };
+        Ok(warp::reply::json(&info_reply))
+    }
+
+    /// Log levels of HaMS
+    #[utoipa::path(
+        get,
+        path = "/hams/loglevel",
+        tag = "hams",
+        responses(
+            (status = 200, description = "Current log levels", body = LogLevelReply),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn loglevel() -> Result<impl warp::Reply, Rejection> {
+        Ok(warp::reply::json(&loglevel::levels()))
+    }
+
+    /// Change the default log level or the level of a target without restarting
+    #[utoipa::path(
+        put,
+        path = "/hams/loglevel",
+        tag = "hams",
+        request_body = LogLevelUpdate,
+        responses(
+            (status = 200, description = "Log levels after the change", body = LogLevelReply),
+            (status = 400, description = "Invalid level or missing level for the default"),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn set_loglevel(update: LogLevelUpdate) -> Result<impl warp::Reply, Rejection> {
+        Ok(warp::reply::json(&update.apply()?))
+    }
+
+    /// Probes of every check with their type, configuration and last result
+    #[utoipa::path(
+        get,
+        path = "/hams/admin/probes",
+        tag = "hams",
+        responses(
+            (status = 200, description = "Probes by check", body = [CheckProbes]),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn probe_list(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        Ok(warp::reply::json(&hams.probe_list().await))
+    }
+
+    /// Enable or disable a manual probe, or check a single probe now
+    #[utoipa::path(
+        post,
+        path = "/hams/admin/probes/{check}/{probe}/{action}",
+        tag = "hams",
+        params(
+            ("check" = String, Path, description = "alive or ready"),
+            ("probe" = String, Path, description = "Name of the probe"),
+            ("action" = String, Path, description = "enable, disable or check")
+        ),
+        responses(
+            (status = 200, description = "The probe after enable or disable, or the result of check", body = ProbeDescription),
+            (status = 400, description = "The probe cannot be enabled or disabled"),
+            (status = 401, description = "Authentication required"),
+            (status = 404, description = "Unknown check, probe or action")
+        )
+    )]
+    pub async fn probe_action(
+        check: String,
+        probe: String,
+        action: String,
+        hams: Hams,
+    ) -> Result<impl warp::Reply, Rejection> {
+        let check = hams.check_group(&check)?;
+        let reply = match action.as_str() {
+            "enable" | "disable" => {
+                let valid = action == "enable";
+                let description = check.set_valid(&probe, valid).await?;
+                info!("Probe {probe} of {} set to {valid}", check.name);
+                warp::reply::json(&description)
+            }
+            "check" => warp::reply::json(&check.check_probe(&probe, SystemTime::now()).await?),
+            _ => return Err(HamsError::NotFound(format!("action {action}")).into()),
+        };
+        Ok(reply)
+    }
+
+    /// Maintenance state of the service
+    #[utoipa::path(
+        get,
+        path = "/hams/maintenance",
+        tag = "hams",
+        responses(
+            (status = 200, description = "Maintenance state", body = MaintenanceReply),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn maintenance(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        Ok(warp::reply::json(&MaintenanceReply::from(
+            hams.maintenance(),
+        )))
+    }
+
+    /// Set maintenance. Ready fails with the reason until maintenance is cleared or expires, alive is unaffected
+    #[utoipa::path(
+        put,
+        path = "/hams/maintenance",
+        tag = "hams",
+        request_body = MaintenanceRequest,
+        responses(
+            (status = 200, description = "Maintenance state after the change", body = MaintenanceReply),
+            (status = 400, description = "Invalid request"),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn set_maintenance(
+        request: MaintenanceRequest,
+        hams: Hams,
+    ) -> Result<impl warp::Reply, Rejection> {
+        hams.set_maintenance(
+            &request.reason,
+            request.expiry_secs.map(Duration::from_secs),
+        )?;
+        maintenance(hams).await
+    }
+
+    /// Clear maintenance
+    #[utoipa::path(
+        delete,
+        path = "/hams/maintenance",
+        tag = "hams",
+        responses(
+            (status = 200, description = "Maintenance state after the change", body = MaintenanceReply),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn clear_maintenance(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        hams.clear_maintenance()?;
+        maintenance(hams).await
+    }
+
+    /// Faults injected into probes which have not yet reverted
+    #[utoipa::path(
+        get,
+        path = "/hams/admin/faults",
+        tag = "hams",
+        responses(
+            (status = 200, description = "Active faults", body = [FaultReply]),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn fault_list(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        Ok(warp::reply::json(&hams.faults()))
+    }
+
+    /// Inject a fault into a probe. The probe reverts to its real check after the TTL
+    #[utoipa::path(
+        put,
+        path = "/hams/admin/faults/{check}/{probe}",
+        tag = "hams",
+        params(
+            ("check" = String, Path, description = "alive or ready"),
+            ("probe" = String, Path, description = "Name of the probe")
+        ),
+        request_body = FaultRequest,
+        responses(
+            (status = 200, description = "Active faults after the change", body = [FaultReply]),
+            (status = 400, description = "Invalid fault or TTL"),
+            (status = 401, description = "Authentication required"),
+            (status = 404, description = "Unknown check or probe")
+        )
+    )]
+    pub async fn inject_fault(
+        check: String,
+        probe: String,
+        request: FaultRequest,
+        hams: Hams,
+    ) -> Result<impl warp::Reply, Rejection> {
+        hams.set_fault(&check, &probe, Some(request.into_fault()?))
+            .await?;
+        fault_list(hams).await
+    }
+
+    /// Revert a probe to its real check before its fault expires
+    #[utoipa::path(
+        delete,
+        path = "/hams/admin/faults/{check}/{probe}",
+        tag = "hams",
+        params(
+            ("check" = String, Path, description = "alive or ready"),
+            ("probe" = String, Path, description = "Name of the probe")
+        ),
+        responses(
+            (status = 200, description = "Active faults after the change", body = [FaultReply]),
+            (status = 401, description = "Authentication required"),
+            (status = 404, description = "Unknown check or probe")
+        )
+    )]
+    pub async fn clear_fault(
+        check: String,
+        probe: String,
+        hams: Hams,
+    ) -> Result<impl warp::Reply, Rejection> {
+        hams.set_fault(&check, &probe, None).await?;
+        fault_list(hams).await
+    }
+
+    /// Server-Sent Events stream of status transitions of the checks, probes and lifecycle. Each event is
+    /// named after its kind and its data is the transition as JSON. The stream ends when HaMS stops
+    #[utoipa::path(
+        get,
+        path = "/hams/events",
+        tag = "hams",
+        responses(
+            (status = 200, description = "Stream of transitions", body = HamsEvent, content_type = "text/event-stream"),
+            (status = 401, description = "Authentication required by the verbose access policy")
+        )
+    )]
+    pub async fn events(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        let stream = hams
+            .events
+            .stream(hams.cancellation_token.clone())
+            .map(|event| {
+                warp::sse::Event::default()
+                    .event(event.kind.as_str())
+                    .json_data(&event)
+            });
+        info!(
+            "Event stream opened, {} open streams",
+            hams.events.subscribers()
+        );
+        Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+    }
+
+    /// This document
+    #[utoipa::path(
+        get,
+        path = "/hams/openapi.json",
+        tag = "hams",
+        responses((status = 200, description = "OpenAPI 3 document", body = Object))
+    )]
+    pub async fn openapi(hams: Hams) -> Result<impl warp::Reply, Rejection> {
+        Ok(warp::reply::json(&openapi::document(&hams.config.prefix)))
+    }
+
+    /// Prometheus metrics, in OpenMetrics when accepted and gzip compressed when allowed
+    #[utoipa::path(
+        get,
+        path = "/hams/metrics",
+        tag = "hams",
+        params(
+            ("accept" = Option<String>, Header, description = "application/openmetrics-text or text/plain"),
+            ("accept-encoding" = Option<String>, Header, description = "gzip to compress the reply")
+        ),
+        responses(
+            (status = 200, description = "Metrics in the Prometheus text format or OpenMetrics", body = String, content_type = ["text/plain", "application/openmetrics-text"]),
+            (status = 401, description = "Authentication required")
+        )
+    )]
+    pub async fn metrics(
+        accept: Option<String>,
+        accept_encoding: Option<String>,
+        hams: Hams,
+    ) -> Result<impl warp::Reply, Rejection> {
+        let format = metrics_format(accept.as_deref());
+        let mut metrics = hams.render_metrics(format).await?;
+        if format == Format::OpenMetrics {
+            metrics.push_str(openmetrics::EOF);
+        }
+
+        Ok(metrics_reply(
+            metrics,
+            format,
+            accepts_gzip(accept_encoding.as_deref()),
+        )?)
+    }
+
+    #[cfg(test)]
+    mod tests {
+
+        use crate::{
+            hams::{
+                config::{Access, AuthConfig, BasicCredential, HamsConfig, Secret},
+                fault::{Fault, FaultKind},
+                info::HamsState,
+                webservice::hams_service,
+            },
+            probe::{manual::Manual, FFIProbe},
+        };
+        use std::time::Duration;
+        use tokio_util::sync::CancellationToken;
+
+        use super::*;
+        use warp::http::StatusCode;
+
+        /// Configuration with shutdown and admin opened, as they are closed without credentials
+        fn open_config() -> HamsConfig {
+            let mut config = HamsConfig::default();
+            config.auth.shutdown = Some(Access::Open);
+            config.auth.admin = Some(Access::Open);
+            config
+        }
+
+        /// Test metrics
+        /// This test will fail as the metrics handler works with empty reply when nothing is registered
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_metrics() {
+            let hams = Hams::new(HamsConfig::default());
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/metrics")
+                .reply(&api)
+                .await;
+
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        /// A collector that returns no output fails the scrape rather than the server
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_metrics_collector_failed() {
+            extern "C" fn failing(_ptr: *const libc::c_void) -> *mut libc::c_char {
+                std::ptr::null_mut()
+            }
+            extern "C" fn collector_free(_ptr: *mut libc::c_char) {}
+
+            let hams = Hams::new(HamsConfig::default());
+            hams.register_collector("broken", failing, collector_free, std::ptr::null())
+                .unwrap();
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/metrics")
+                .reply(&api)
+                .await;
+
+            assert_eq!(reply.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert!(String::from_utf8_lossy(reply.body()).contains("broken"));
+        }
+
+        /// Requests are counted per route and served with the metrics
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_request_metrics() {
+            let hams = Hams::new(HamsConfig::default());
+            let api = hams_service(hams);
+
+            for _ in 0..2 {
+                warp::test::request().path("/hams/alive").reply(&api).await;
+            }
+            warp::test::request().path("/hams/splat").reply(&api).await;
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/metrics")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            let body = std::str::from_utf8(reply.body()).unwrap();
+            assert!(body.contains(
+                "hams_http_requests_total{route=\"/hams/alive\",method=\"GET\",status=\"200\"} 2\n"
+            ));
+            assert!(body.contains(
+                "hams_http_requests_total{route=\"other\",method=\"GET\",status=\"404\"} 1\n"
+            ));
+            assert!(body
+                .contains("hams_http_request_duration_seconds_count{route=\"/hams/alive\"} 2\n"));
+        }
+
+        /// Metrics registered by the application are served with the request metrics
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_registry_metrics() {
+            let hams = Hams::new(HamsConfig::default());
+            let counter = hams.counter("jobs_total", "Jobs run", &["queue"]).unwrap();
+            counter.inc(&["fast"]).unwrap();
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/metrics")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            let body = std::str::from_utf8(reply.body()).unwrap();
+            assert!(body.contains("# TYPE jobs_total counter\n"));
+            assert!(body.contains("jobs_total{queue=\"fast\"} 1\n"));
+            assert!(body.contains("# TYPE hams_http_requests_total counter\n"));
+        }
+
+        /// Process metrics are only served when configured
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_process_metrics() {
+            let api = hams_service(Hams::new(HamsConfig::default()));
+            let reply = warp::test::request()
+                .path("/hams/metrics")
+                .reply(&api)
+                .await;
+            assert!(!std::str::from_utf8(reply.body())
+                .unwrap()
+                .contains("process_"));
+
+            let mut config = HamsConfig::default();
+            config.metrics.process = true;
+            let api = hams_service(Hams::new(config));
+            let reply = warp::test::request()
+                .path("/hams/metrics")
+                .reply(&api)
+                .await;
+            let body = std::str::from_utf8(reply.body()).unwrap();
+            assert_eq!(
+                body.contains("# TYPE process_resident_memory_bytes gauge\n"),
+                cfg!(target_os = "linux")
+            );
+        }
+
+        /// The checks HaMS runs are exported as metrics, including those of filtered checks
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_health_metrics() {
+            let hams = Hams::new(HamsConfig::default());
+            hams.set_version("1.2.3").unwrap();
+            let mut probe = Manual::new("database", true);
+            hams.ready
+                .insert_async(FFIProbe::from(probe.clone()).into())
+                .await;
+            let api = hams_service(hams);
+
+            warp::test::request().path("/hams/ready").reply(&api).await;
+            probe.disable();
+            warp::test::request()
+                .path("/hams/ready?probe=database")
+                .reply(&api)
+                .await;
+
+            let reply = warp::test::request()
+                .path("/hams/metrics")
+                .reply(&api)
+                .await;
+            let body = std::str::from_utf8(reply.body()).unwrap();
+            assert!(body.contains("hams_check_up{check=\"ready\"} 1\n"));
+            assert!(body.contains("hams_probe_up{check=\"ready\",probe=\"database\"} 0\n"));
+            assert!(body
+                .contains("hams_probe_transitions_total{check=\"ready\",probe=\"database\"} 1\n"));
+            assert!(body.contains(
+                "hams_probe_check_duration_seconds_count{check=\"ready\",probe=\"database\"} 2\n"
+            ));
+            assert!(body.contains("hams_build_info{name=\"NO_NAME\",version=\"1.2.3\","));
+        }
+
+        /// Info reports the application details set after creation and a redacted config
+        /// Routes, their metrics and the served document follow the configured prefix
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_prefix() {
+            let api = hams_service(Hams::new(HamsConfig {
+                prefix: "/ops/health".to_string(),
+                ..Default::default()
+            }));
+
+            let reply = warp::test::request()
+                .path("/ops/health/alive")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            let reply = warp::test::request().path("/hams/alive").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+
+            let reply = warp::test::request()
+                .path("/ops/health/metrics")
+                .reply(&api)
+                .await;
+            let body = String::from_utf8(reply.body().to_vec()).unwrap();
+            assert!(body.contains(
+                "hams_http_requests_total{route=\"/hams/alive\",method=\"GET\",status=\"200\"} 1\n"
+            ));
+            assert!(body.contains(
+                "hams_http_requests_total{route=\"other\",method=\"GET\",status=\"404\"} 1\n"
+            ));
+
+            let reply = warp::test::request()
+                .path("/ops/health/openapi.json")
+                .reply(&api)
+                .await;
+            let doc: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert!(doc["paths"].get("/ops/health/alive").is_some());
+            assert!(doc["paths"]
+                .get("/ops/health/admin/faults/{check}/{probe}")
+                .is_some());
+            assert!(doc["paths"].get("/hams/alive").is_none());
+        }
+
+        /// Probes declared in the configuration are checked and can be set by the application
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_declared_probes() {
+            let config = HamsConfig::from_json(
+                r#"{"ready": {"probes": [{"type": "manual", "name": "warmup", "valid": false}]}}"#,
+            )
+            .unwrap();
+            let hams = Hams::new(config);
+            let api = hams_service(hams.clone());
+
+            let reply = warp::test::request()
+                .path("/hams/ready?verbose=true")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["details"][0]["name"], "warmup");
+
+            hams.ready.set_valid("warmup", true).await.unwrap();
+            let reply = warp::test::request().path("/hams/ready").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            let reply = warp::test::request().path("/hams/alive").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_info() {
+            let hams = Hams::new(HamsConfig {
+                name: "sample".to_string(),
+                auth: AuthConfig {
+                    info: Some(Access::Open),
+                    bearer_tokens: vec![Secret::Value("t0ken".to_string())],
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+            hams.set_version("1.2.3").unwrap();
+            hams.set_build_info(Some("abc123"), Some("2024-06-01T12:00:00Z"))
+                .unwrap();
+            hams.set_label("region", Some("eu")).unwrap();
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/info")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["name"], "sample");
+            assert_eq!(body["version"], "1.2.3");
+            assert_eq!(body["git_sha"], "abc123");
+            assert_eq!(body["build_time"], "2024-06-01T12:00:00Z");
+            assert_eq!(body["labels"]["region"], "eu");
+            assert_eq!(body["pid"], std::process::id());
+            assert_eq!(body["state"], "stopped");
+            assert!(body["uptime_seconds"].as_f64().unwrap() >= 0.0);
+            assert!(body["start_time"].as_str().unwrap().ends_with('Z'));
+            assert_eq!(body["config"]["name"], "sample");
+            assert!(!body.to_string().contains("t0ken"));
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/version")
+                .reply(&api)
+                .await;
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["version"], "1.2.3");
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_version() {
+            let hams = Hams::new(HamsConfig::default());
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/version")
+                .reply(&api)
+                .await;
+
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_shutdown() {
+            let hams = Hams::new(open_config());
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("POST")
+                .path("/hams/shutdown")
+                .reply(&api)
+                .await;
+
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            // Without credentials shutdown and admin stay closed unless opened
+            let api = hams_service(Hams::new(HamsConfig::default()));
+            let reply = warp::test::request()
+                .method("POST")
+                .path("/hams/shutdown")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::UNAUTHORIZED);
+            let reply = warp::test::request()
+                .method("PUT")
+                .path("/hams/maintenance")
+                .json(&serde_json::json!({"reason": "upgrade"}))
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::UNAUTHORIZED);
+            let reply = warp::test::request().path("/hams/alive").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_alive() {
+            let hams = Hams::new(HamsConfig::default());
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive")
+                .reply(&api)
+                .await;
+
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_ready() {
+            let hams = Hams::new(HamsConfig::default());
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/ready")
+                .reply(&api)
+                .await;
+
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        /// Create a HaMS with a passing and a failing probe on alive
+        async fn hams_with_failing_probe() -> Hams {
+            let hams = Hams::new(HamsConfig::default());
+            hams.alive
+                .insert_async(FFIProbe::from(Manual::new("good", true)).into())
+                .await;
+            hams.alive
+                .insert_async(FFIProbe::from(Manual::new("bad", false)).into())
+                .await;
+            hams
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_alive_verbose_query() {
+            let api = hams_service(hams_with_failing_probe().await);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert!(body.get("details").is_none());
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?verbose")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["details"].as_array().unwrap().len(), 2);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?verbose=splat")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::BAD_REQUEST);
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_alive_probe_filter() {
+            let api = hams_service(hams_with_failing_probe().await);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?probe=good")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?exclude=bad&verbose")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["details"].as_array().unwrap().len(), 1);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?probe=bad")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+            // A name that matches no probe must not pass a check of nothing
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?probe=typo")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?exclude=bad,typo")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::NOT_FOUND);
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_alive_accept() {
+            let api = hams_service(hams_with_failing_probe().await);
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?verbose")
+                .header("accept", "text/plain")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(reply.body(), "bad\n");
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive?probe=good")
+                .header("accept", "text/plain")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.body(), "ok\n");
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive")
+                .header("accept", "text/plain")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(reply.body(), "bad\n");
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/alive")
+                .header("accept", "application/health+json")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.headers()["content-type"], "application/health+json");
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["status"], "fail");
+        }
+
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_ready_head() {
+            let hams = Hams::new(HamsConfig::default());
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("HEAD")
+                .path("/hams/ready")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            assert!(reply.body().is_empty());
+
+            let reply = warp::test::request()
+                .method("POST")
+                .path("/hams/ready")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::METHOD_NOT_ALLOWED);
+        }
+
+        /// Failing checks use the configured status and Retry-After for both alive and ready
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_check_fail_status() {
+            let config = HamsConfig {
+                alive: CheckConfig {
+                    fail_status: 500,
+                    ..Default::default()
+                },
+                ready: CheckConfig {
+                    fail_status: 429,
+                    retry_after: Some(Duration::from_secs(5)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let hams = Hams::new(config);
+            let api = hams_service(hams.clone());
+
+            for (path, status) in [
+                ("/hams/alive", StatusCode::OK),
+                ("/hams/ready", StatusCode::OK),
+            ] {
+                let reply = warp::test::request().path(path).reply(&api).await;
+                assert_eq!(reply.status(), status);
+                assert!(reply.headers().get("retry-after").is_none());
+            }
+
+            hams.alive
+                .insert_async(FFIProbe::from(Manual::new("bad", false)).into())
+                .await;
+            hams.ready
+                .insert_async(FFIProbe::from(Manual::new("bad", false)).into())
+                .await;
+
+            let reply = warp::test::request().path("/hams/alive").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            assert!(reply.headers().get("retry-after").is_none());
+
+            for method in ["GET", "HEAD"] {
+                let reply = warp::test::request()
+                    .method(method)
+                    .path("/hams/ready")
+                    .reply(&api)
+                    .await;
+                assert_eq!(reply.status(), StatusCode::TOO_MANY_REQUESTS);
+                assert_eq!(reply.headers()["retry-after"], "5");
+            }
+        }
+
+        /// Create a HaMS protected by a bearer token and basic auth with a failing probe on ready
+        async fn hams_with_auth(auth: AuthConfig) -> Hams {
+            let hams = Hams::new(HamsConfig {
+                auth: AuthConfig {
+                    bearer_tokens: vec![Secret::Value("t0ken".to_string())],
+                    basic: vec![BasicCredential {
+                        username: "ops".to_string(),
+                        password: Secret::Value("letmein".to_string()), // pragma: allowlist secret
+                    }],
+                    ..auth
+                },
+                ..Default::default()
+            });
+            hams.ready
+                .insert_async(FFIProbe::from(Manual::new("internal-db", false)).into())
+                .await;
+            hams
+        }
+
+        /// Log levels are changed per target and read back
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_loglevel() {
+            let api = hams_service(Hams::new(open_config()));
+
+            let reply = warp::test::request()
+                .method("PUT")
+                .path("/hams/loglevel")
+                .json(&serde_json::json!({"target": "hams::test_loglevel", "level": "debug"}))
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["targets"]["hams::test_loglevel"], "DEBUG");
+
+            let reply = warp::test::request()
+                .method("GET")
+                .path("/hams/loglevel")
+                .reply(&api)
+                .await;
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["targets"]["hams::test_loglevel"], "DEBUG");
+            assert!(body["default"].is_string());
+
+            let reply = warp::test::request()
+                .method("PUT")
+                .path("/hams/loglevel")
+                .json(&serde_json::json!({"target": "hams::test_loglevel"}))
+                .reply(&api)
+                .await;
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert!(body["targets"].get("hams::test_loglevel").is_none());
+
+            for invalid in [
+                serde_json::json!({"level": "loud"}),
+                serde_json::json!({}),
+                serde_json::json!({"level": "debug", "other": 1}),
+            ] {
+                let reply = warp::test::request()
+                    .method("PUT")
+                    .path("/hams/loglevel")
+                    .json(&invalid)
+                    .reply(&api)
+                    .await;
+                assert_eq!(reply.status(), StatusCode::BAD_REQUEST, "{invalid}");
+            }
+        }
+
+        /// Probes are listed, disabled to take the service out of rotation and checked on demand
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_admin_probes() {
+            let hams = Hams::new(open_config());
+            hams.ready
+                .insert_async(FFIProbe::from(Manual::new("rotation", true)).into())
+                .await;
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .path("/hams/admin/probes")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body[0]["name"], "alive");
+            assert_eq!(body[1]["probes"][0]["name"], "rotation");
+            assert_eq!(body[1]["probes"][0]["type"], "manual");
+            assert_eq!(body[1]["probes"][0]["config"]["valid"], true);
+
+            let reply = warp::test::request()
+                .method("POST")
+                .path("/hams/admin/probes/ready/rotation/disable")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["config"]["valid"], false);
+
+            let reply = warp::test::request().path("/hams/ready").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+            let reply = warp::test::request()
+                .method("POST")
+                .path("/hams/admin/probes/ready/rotation/check")
+                .reply(&api)
+                .await;
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["valid"], false);
+
+            for (path, status) in [
+                ("/hams/admin/probes/ready/rotation/enable", StatusCode::OK),
+                (
+                    "/hams/admin/probes/ready/missing/enable",
+                    StatusCode::NOT_FOUND,
+                ),
+                (
+                    "/hams/admin/probes/other/rotation/enable",
+                    StatusCode::NOT_FOUND,
+                ),
+                (
+                    "/hams/admin/probes/ready/rotation/explode",
+                    StatusCode::NOT_FOUND,
+                ),
+            ] {
+                let reply = warp::test::request()
+                    .method("POST")
+                    .path(path)
+                    .reply(&api)
+                    .await;
+                assert_eq!(reply.status(), status, "{path}");
+            }
+
+            let reply = warp::test::request().path("/hams/ready").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        /// Maintenance takes ready down with a reason while alive stays up
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_maintenance() {
+            let api = hams_service(Hams::new(open_config()));
+
+            let reply = warp::test::request()
+                .method("PUT")
+                .path("/hams/maintenance")
+                .json(&serde_json::json!({"reason": "database upgrade", "expiry_secs": 600}))
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["active"], true);
+            assert_eq!(body["reason"], "database upgrade");
+            assert!(body["until"].is_string());
+
+            let reply = warp::test::request().path("/hams/ready").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body["reason"], "maintenance: database upgrade");
+
+            let reply = warp::test::request().path("/hams/alive").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            let reply = warp::test::request()
+                .method("DELETE")
+                .path("/hams/maintenance")
+                .reply(&api)
+                .await;
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body, serde_json::json!({"active": false}));
+
+            let reply = warp::test::request().path("/hams/ready").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            let reply = warp::test::request()
+                .method("PUT")
+                .path("/hams/maintenance")
+                .json(&serde_json::json!({"expiry_secs": 600}))
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::BAD_REQUEST);
+
+            let reply = warp::test::request()
+                .method("PUT")
+                .path("/hams/maintenance")
+                .json(&serde_json::json!({"reason": "forever", "expiry_secs": u64::MAX}))
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::BAD_REQUEST);
+            let reply = warp::test::request()
+                .method("PUT")
+                .path("/hams/maintenance")
+                .json(&serde_json::json!({"reason": "forever", "expiry_secs": 300_000_000_000u64}))
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::BAD_REQUEST);
+            let reply = warp::test::request()
+                .path("/hams/maintenance")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            let reply = warp::test::request().path("/hams/ready").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        /// Injected faults change the probe result, are labelled in verbose output and can be cleared
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_faults() {
+            let hams = Hams::new(open_config());
+            hams.ready
+                .insert_async(FFIProbe::from(Manual::new("database", true)).into())
+                .await;
+            let api = hams_service(hams);
+
+            let reply = warp::test::request()
+                .method("PUT")
+                .path("/hams/admin/faults/ready/database")
+                .json(&serde_json::json!({"fault": {"type": "error", "message": "timeout"}, "ttl_secs": 600}))
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body[0]["check"], "ready");
+            assert_eq!(body[0]["probe"], "database");
+            assert_eq!(body[0]["fault"]["type"], "error");
+
+            let reply = warp::test::request()
+                .path("/hams/ready?verbose")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert!(body["details"][0]["fault"]
+                .as_str()
+                .unwrap()
+                .starts_with("injected error: timeout until "));
+
+            let reply = warp::test::request()
+                .method("DELETE")
+                .path("/hams/admin/faults/ready/database")
+                .reply(&api)
+                .await;
+            let body: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+            assert_eq!(body, serde_json::json!([]));
+
+            let reply = warp::test::request().path("/hams/ready").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::OK);
+
+            for (path, body, status) in [
+                (
+                    "/hams/admin/faults/ready/missing",
+                    serde_json::json!({"fault": {"type": "fail"}, "ttl_secs": 60}),
+                    StatusCode::NOT_FOUND,
+                ),
+                (
+                    "/hams/admin/faults/ready/database",
+                    serde_json::json!({"fault": {"type": "fail"}, "ttl_secs": 0}),
+                    StatusCode::BAD_REQUEST,
+                ),
+                (
+                    "/hams/admin/faults/ready/database",
+                    serde_json::json!({"fault": {"type": "explode"}, "ttl_secs": 60}),
+                    StatusCode::BAD_REQUEST,
+                ),
+            ] {
+                let reply = warp::test::request()
+                    .method("PUT")
+                    .path(path)
+                    .json(&body)
+                    .reply(&api)
+                    .await;
+                assert_eq!(reply.status(), status, "{path} {body}");
+            }
+        }
+
+        /// Requests over the concurrency limit are rejected with the configured status
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_check_limit() {
+            let hams = Hams::new(HamsConfig {
+                ready: CheckConfig {
+                    max_concurrent: Some(1),
+                    retry_after: Some(Duration::from_secs(2)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            });
+            hams.ready
+                .insert_async(FFIProbe::from(Manual::new("slow", true)).into())
+                .await;
+            hams.ready
+                .set_fault(
+                    "slow",
+                    Some(
+                        Fault::new(
+                            FaultKind::Latency { latency_ms: 50 },
+                            Duration::from_secs(60),
+                        )
+                        .unwrap(),
+                    ),
+                )
+                .await
+                .unwrap();
+            let api = hams_service(hams);
+
+            let (all, filtered, alive) = tokio::join!(
+                warp::test::request().path("/hams/ready").reply(&api),
+                warp::test::request()
+                    .path("/hams/ready?probe=slow")
+                    .reply(&api),
+                warp::test::request().path("/hams/alive").reply(&api),
+            );
+            assert_eq!(all.status(), StatusCode::OK);
+            assert_eq!(filtered.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(filtered.headers()["retry-after"], "2");
+            assert_eq!(alive.status(), StatusCode::OK);
+
+            let reply = warp::test::request()
+                .path("/hams/ready?probe=slow")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::OK);
+        }
+
+        /// Transitions are streamed as they are observed until HaMS stops
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_events() {
+            let mut hams = Hams::new(HamsConfig::default());
+            hams.cancellation_token = CancellationToken::new();
+            let mut probe = Manual::new("flappy", true);
+            hams.ready
+                .insert_async(FFIProbe::from(probe.clone()).into())
+                .await;
+            let api = hams_service(hams.clone());
+
+            let stream = tokio::spawn(async move {
+                warp::test::request().path("/hams/events").reply(&api).await
+            });
+            while hams.events.subscribers() == 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+
+            hams.ready.check(SystemTime::now()).await;
+            hams.ready.check(SystemTime::now()).await;
+            probe.disable();
+            hams.ready.check(SystemTime::now()).await;
+            hams.set_state(HamsState::Stopping).unwrap();
+            hams.cancellation_token.cancel();
+
+            let reply = stream.await.unwrap();
+            assert_eq!(reply.status(), StatusCode::OK);
+            assert_eq!(reply.headers()["content-type"], "text/event-stream");
+            let body = String::from_utf8(reply.body().to_vec()).unwrap();
+            let events: Vec<(&str, serde_json::Value)> = body
+                .split("\n\n")
+                .filter_map(|event| {
+                    let name = event.lines().find_map(|line| line.strip_prefix("event:"))?;
+                    let data = event.lines().find_map(|line| line.strip_prefix("data:"))?;
+                    Some((name, serde_json::from_str(data).unwrap()))
+                })
+                .collect();
+
+            let transitions: Vec<_> = events
+                .iter()
+                .map(|(name, event)| {
+                    assert_eq!(event["kind"], *name);
+                    (
+                        event["probe"].as_str().or(event["check"].as_str()),
+                        event["old"].as_str().unwrap(),
+                        event["new"].as_str().unwrap(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                transitions,
+                vec![
+                    (Some("flappy"), "unknown", "pass"),
+                    (Some("ready"), "unknown", "pass"),
+                    (Some("flappy"), "pass", "fail"),
+                    (Some("ready"), "pass", "fail"),
+                    (None, "stopped", "stopping"),
+                ]
+            );
+            assert_eq!(events[3].1["reason"], "failing: flappy");
+        }
+
+        /// Sensitive routes need credentials once configured while plain checks stay open
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_auth_routes() {
+            let api = hams_service(hams_with_auth(AuthConfig::default()).await);
+
+            for (method, path, open) in [
+                ("GET", "/hams/alive", true),
+                ("HEAD", "/hams/ready", true),
+                ("GET", "/hams/version", true),
+                ("GET", "/hams/metrics", false),
+                ("POST", "/hams/shutdown", false),
+                ("GET", "/hams/info", false),
+                ("GET", "/hams/loglevel", false),
+                ("PUT", "/hams/loglevel", false),
+                ("GET", "/hams/admin/probes", false),
+                ("PUT", "/hams/maintenance", false),
+                ("DELETE", "/hams/maintenance", false),
+                ("POST", "/hams/admin/probes/ready/internal-db/enable", false),
+                ("GET", "/hams/admin/faults", false),
+                ("PUT", "/hams/admin/faults/ready/internal-db", false),
+                ("DELETE", "/hams/admin/faults/ready/internal-db", false),
+                ("GET", "/hams/events", false),
+            ] {
+                let reply = warp::test::request()
+                    .method(method)
+                    .path(path)
+                    .reply(&api)
+                    .await;
+                assert_eq!(
+                    reply.status() != StatusCode::UNAUTHORIZED,
+                    open,
+                    "{method} {path}"
+                );
+                if !open {
+                    assert!(reply.headers()["www-authenticate"]
+                        .to_str()
+                        .unwrap()
+                        .contains("Bearer"));
+                }
+            }
+
+            for authorization in ["Bearer t0ken", "Basic b3BzOmxldG1laW4="] {
+                let reply = warp::test::request()
+                    .path("/hams/metrics")
+                    .header("authorization", authorization)
+                    .reply(&api)
+                    .await;
+                assert_eq!(reply.status(), StatusCode::OK, "{authorization}");
+            }
+
+            let reply = warp::test::request()
+                .path("/hams/metrics")
+                .header("authorization", "Bearer guess")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        /// Unauthenticated verbose checks are redacted by default or refused when configured
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_auth_verbose() {
+            let api = hams_service(hams_with_auth(AuthConfig::default()).await);
+
+            let reply = warp::test::request()
+                .path("/hams/ready?verbose")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let body = std::str::from_utf8(reply.body()).unwrap();
+            assert!(!body.contains("internal-db"));
+            let reply = warp::test::request()
+                .path("/hams/ready")
+                .header("accept", "text/plain")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.body(), "fail\n");
+            let reply = warp::test::request()
+                .path("/hams/ready")
+                .header("accept", "text/plain")
+                .header("authorization", "Bearer t0ken")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.body(), "internal-db\n");
+
+            let reply = warp::test::request()
+                .path("/hams/ready?verbose")
+                .header("authorization", "Bearer t0ken")
+                .reply(&api)
+                .await;
+            let body = std::str::from_utf8(reply.body()).unwrap();
+            assert!(body.contains("internal-db"));
+
+            let api = hams_service(
+                hams_with_auth(AuthConfig {
+                    verbose: Some(Access::Authenticated),
+                    ..Default::default()
+                })
+                .await,
+            );
+            let reply = warp::test::request()
+                .path("/hams/ready?verbose")
+                .reply(&api)
+                .await;
+            assert_eq!(reply.status(), StatusCode::UNAUTHORIZED);
+            let reply = warp::test::request().path("/hams/ready").reply(&api).await;
+            assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        /// Without configuration a failing check is 503 on both endpoints
+        #[tokio::test]
+        #[cfg_attr(miri, ignore)]
+        async fn test_check_fail_status_default() {
+            let hams = Hams::new(HamsConfig::default());
+            let api = hams_service(hams.clone());
+
+            hams.alive
+                .insert_async(FFIProbe::from(Manual::new("bad", false)).into())
+                .await;
+            hams.ready
+                .insert_async(FFIProbe::from(Manual::new("bad", false)).into())
+                .await;
+
+            for path in ["/hams/alive", "/hams/ready"] {
+                let reply = warp::test::request().path(path).reply(&api).await;
+                assert_eq!(reply.status(), StatusCode::SERVICE_UNAVAILABLE);
+                assert!(reply.headers().get("retry-after").is_none());
+            }
+        }
+    }
+}
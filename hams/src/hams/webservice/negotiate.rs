@@ -0,0 +1,469 @@
+//! Query parameters and content negotiation for the check and metrics endpoints
+//!
+//! The alive and ready endpoints accept `?verbose`, `?probe=name` and `?exclude=name` and reply in the
+//! format selected by the `Accept` header. A failing check lists its failing probes in plain text, with
+//! their messages when verbose. The names are probe details, so callers whose verbose requests are
+//! redacted get `fail` instead. The metrics endpoint
+//! replies in OpenMetrics when it is accepted and compresses its reply when the `Accept-Encoding`
+//! header allows gzip.
+
+use std::{collections::BTreeMap, io::Write};
+
+use flate2::{write::GzEncoder, Compression};
+use serde::Serialize;
+use warp::http::{
+    header::{CONTENT_ENCODING, CONTENT_TYPE, VARY},
+    HeaderValue, Method, Response, StatusCode,
+};
+
+use crate::{
+    error::HamsError,
+    hams::check::{HealthCheckResult, ProbeFilter},
+    metrics::Format,
+    probe::HealthProbeResult,
+};
+
+/// Media type of the health check response format from draft-inadarei-api-health-check
+pub(crate) const HEALTH_JSON: &str = "application/health+json";
+
+/// Parameters accepted on the check endpoints
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct CheckQuery {
+    /// Include the probe details in the reply
+    pub(crate) verbose: bool,
+    /// Probes to include or exclude from the check
+    pub(crate) filter: ProbeFilter,
+}
+
+impl TryFrom<Vec<(String, String)>> for CheckQuery {
+    type Error = HamsError;
+
+    /// Build the query from the decoded query string. Probe names may be repeated or comma separated.
+    fn try_from(params: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut query = CheckQuery::default();
+
+        for (key, value) in params {
+            match key.as_str() {
+                "verbose" => {
+                    query.verbose = match value.as_str() {
+                        "" | "true" | "1" => true,
+                        "false" | "0" => false,
+                        other => {
+                            return Err(HamsError::Message(format!(
+                                "Invalid value for verbose: {other}"
+                            )))
+                        }
+                    }
+                }
+                "probe" => query.filter.include.extend(split_names(&value)),
+                "exclude" => query.filter.exclude.extend(split_names(&value)),
+                other => {
+                    return Err(HamsError::Message(format!(
+                        "Unknown query parameter: {other}"
+                    )))
+                }
+            }
+        }
+        Ok(query)
+    }
+}
+
+fn split_names(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+}
+
+/// Output formats supported by the check endpoints
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum CheckFormat {
+    /// [HealthCheckResult] as JSON
+    Json,
+    /// application/health+json
+    HealthJson,
+    /// `ok`, or the failing probes as plain text with their messages when verbose. Probes with an
+    /// injected fault are listed too. `fail` when the result has no probe details
+    Text,
+}
+
+impl CheckFormat {
+    /// Select the format from an `Accept` header, highest quality first. JSON is used when the header is
+    /// missing or names nothing we can produce.
+    pub(crate) fn negotiate(accept: Option<&str>) -> Self {
+        let Some(accept) = accept else {
+            return CheckFormat::Json;
+        };
+
+        ranges(accept)
+            .into_iter()
+            .find_map(|media| match media.to_ascii_lowercase().as_str() {
+                "application/json" | "application/*" | "*/*" => Some(CheckFormat::Json),
+                HEALTH_JSON => Some(CheckFormat::HealthJson),
+                "text/plain" | "text/*" => Some(CheckFormat::Text),
+                _ => None,
+            })
+            .unwrap_or(CheckFormat::Json)
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            CheckFormat::Json => "application/json",
+            CheckFormat::HealthJson => HEALTH_JSON,
+            CheckFormat::Text => "text/plain; charset=utf-8",
+        }
+    }
+
+    /// Render the result in this format. Verbose text includes the message of each probe listed
+    pub(crate) fn render(
+        &self,
+        result: &HealthCheckResult,
+        verbose: bool,
+    ) -> Result<String, HamsError> {
+        let body = match self {
+            CheckFormat::Json => serde_json::to_string(result)?,
+            CheckFormat::HealthJson => serde_json::to_string(&HealthJson::from(result))?,
+            CheckFormat::Text => {
+                // Faulted probes are listed even when they pass so an injected result is never
+                // mistaken for a real one
+                let listed = |body: String, probe: &HealthProbeResult| {
+                    let mut line = body + &probe.name;
+                    if let (true, Some(message)) = (verbose, &probe.message) {
+                        line = line + ": " + message;
+                    }
+                    if let Some(fault) = &probe.fault {
+                        line = line + " (" + fault + ")";
+                    }
+                    line + "\n"
+                };
+                let probes = result.details.iter().flatten();
+                if result.valid {
+                    probes
+                        .filter(|probe| probe.fault.is_some())
+                        .fold("ok\n".to_string(), listed)
+                } else if let Some(reason) = &result.reason {
+                    format!("{reason}\n")
+                } else if result.details.is_some() {
+                    probes
+                        .filter(|probe| !probe.valid || probe.fault.is_some())
+                        .fold(String::new(), listed)
+                } else {
+                    "fail\n".to_string()
+                }
+            }
+        };
+        Ok(body)
+    }
+
+    /// Build the reply for a check. HEAD requests get the same status and headers with no body.
+    pub(crate) fn reply(
+        &self,
+        method: &Method,
+        status: StatusCode,
+        result: &HealthCheckResult,
+        verbose: bool,
+    ) -> Result<Response<String>, HamsError> {
+        let body = if method == Method::HEAD {
+            String::new()
+        } else {
+            self.render(result, verbose)?
+        };
+
+        let mut response = Response::new(body);
+        *response.status_mut() = status;
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(self.content_type()));
+        Ok(response)
+    }
+}
+
+/// Check reply in the application/health+json format
+#[derive(Serialize)]
+struct HealthJson<'a> {
+    status: &'static str,
+    description: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checks: Option<BTreeMap<&'a str, Vec<HealthJsonCheck<'a>>>>,
+}
+
+#[derive(Serialize)]
+struct HealthJsonCheck<'a> {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<&'a str>,
+}
+
+/// Values of an `Accept` or `Accept-Encoding` header the client accepts, highest quality first
+fn ranges(header: &str) -> Vec<&str> {
+    let mut ranges: Vec<(f32, &str)> = header
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media = parts.next().filter(|media| !media.is_empty())?;
+            let quality = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((quality, media))
+        })
+        .filter(|(quality, _)| *quality > 0.0)
+        .collect();
+    // Stable sort keeps the client order for equal quality
+    ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranges.into_iter().map(|(_, media)| media).collect()
+}
+
+/// Select the metrics format from an `Accept` header. OpenMetrics must be named, as Prometheus servers
+/// that ask for it do, otherwise the Prometheus text format is used
+pub(crate) fn metrics_format(accept: Option<&str>) -> Format {
+    accept
+        .map(ranges)
+        .unwrap_or_default()
+        .into_iter()
+        .find_map(|media| match media.to_ascii_lowercase().as_str() {
+            "application/openmetrics-text" => Some(Format::OpenMetrics),
+            "text/plain" | "text/*" | "*/*" => Some(Format::Prometheus),
+            _ => None,
+        })
+        .unwrap_or(Format::Prometheus)
+}
+
+/// Whether an `Accept-Encoding` header allows a gzip reply
+pub(crate) fn accepts_gzip(accept_encoding: Option<&str>) -> bool {
+    accept_encoding
+        .map(ranges)
+        .unwrap_or_default()
+        .into_iter()
+        .any(|coding| coding.eq_ignore_ascii_case("gzip") || coding == "*")
+}
+
+/// Reply with metrics in the format, gzip compressed when allowed
+pub(crate) fn metrics_reply(
+    body: String,
+    format: Format,
+    gzip: bool,
+) -> Result<Response<Vec<u8>>, HamsError> {
+    let body = if gzip {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body.as_bytes())?;
+        encoder.finish()?
+    } else {
+        body.into_bytes()
+    };
+
+    let mut response = Response::new(body);
+    let headers = response.headers_mut();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(format.content_type()),
+    );
+    headers.insert(VARY, HeaderValue::from_static("Accept, Accept-Encoding"));
+    if gzip {
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    }
+    Ok(response)
+}
+
+fn health_status(valid: bool) -> &'static str {
+    if valid {
+        "pass"
+    } else {
+        "fail"
+    }
+}
+
+impl<'a> From<&'a HealthCheckResult> for HealthJson<'a> {
+    fn from(result: &'a HealthCheckResult) -> Self {
+        HealthJson {
+            status: health_status(result.valid),
+            description: &result.name,
+            output: result.reason.as_deref(),
+            checks: result.details.as_ref().map(|details| {
+                details
+                    .iter()
+                    .map(|probe| {
+                        (
+                            probe.name.as_str(),
+                            vec![HealthJsonCheck {
+                                status: health_status(probe.valid),
+                                output: probe.fault.as_deref().or(probe.message.as_deref()),
+                            }],
+                        )
+                    })
+                    .collect()
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_query_parse() {
+        let query = CheckQuery::try_from(params(&[])).unwrap();
+        assert_eq!(query, CheckQuery::default());
+
+        let query = CheckQuery::try_from(params(&[
+            ("verbose", ""),
+            ("probe", "a,b"),
+            ("probe", "c"),
+            ("exclude", "b"),
+        ]))
+        .unwrap();
+        assert!(query.verbose);
+        assert_eq!(query.filter.include, vec!["a", "b", "c"]);
+        assert_eq!(query.filter.exclude, vec!["b"]);
+
+        let query = CheckQuery::try_from(params(&[("verbose", "false")])).unwrap();
+        assert!(!query.verbose);
+
+        assert!(CheckQuery::try_from(params(&[("verbose", "maybe")])).is_err());
+        assert!(CheckQuery::try_from(params(&[("splat", "")])).is_err());
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(CheckFormat::negotiate(None), CheckFormat::Json);
+        assert_eq!(CheckFormat::negotiate(Some("*/*")), CheckFormat::Json);
+        assert_eq!(
+            CheckFormat::negotiate(Some("text/plain")),
+            CheckFormat::Text
+        );
+        assert_eq!(
+            CheckFormat::negotiate(Some("application/health+json")),
+            CheckFormat::HealthJson
+        );
+        assert_eq!(
+            CheckFormat::negotiate(Some("application/json;q=0.5, text/plain")),
+            CheckFormat::Text
+        );
+        assert_eq!(
+            CheckFormat::negotiate(Some("text/html, application/health+json;q=0.9, */*;q=0.1")),
+            CheckFormat::HealthJson
+        );
+        assert_eq!(CheckFormat::negotiate(Some("image/png")), CheckFormat::Json);
+    }
+
+    #[test]
+    fn test_metrics_negotiate() {
+        assert_eq!(metrics_format(None), Format::Prometheus);
+        assert_eq!(metrics_format(Some("*/*")), Format::Prometheus);
+        assert_eq!(
+            metrics_format(Some(
+                "application/openmetrics-text;version=1.0.0;q=0.9,text/plain;version=0.0.4;q=0.5"
+            )),
+            Format::OpenMetrics
+        );
+        assert_eq!(
+            metrics_format(Some("text/plain, application/openmetrics-text;q=0.5")),
+            Format::Prometheus
+        );
+
+        assert!(!accepts_gzip(None));
+        assert!(accepts_gzip(Some("deflate, gzip")));
+        assert!(!accepts_gzip(Some("gzip;q=0, br")));
+
+        let reply = metrics_reply("up 1\n".to_string(), Format::OpenMetrics, true).unwrap();
+        assert_eq!(reply.headers()[CONTENT_ENCODING], "gzip");
+        let mut body = String::new();
+        std::io::Read::read_to_string(
+            &mut flate2::read::GzDecoder::new(&reply.body()[..]),
+            &mut body,
+        )
+        .unwrap();
+        assert_eq!(body, "up 1\n");
+    }
+
+    #[test]
+    fn test_render() {
+        let result = HealthCheckResult {
+            name: "ready".to_string(),
+            valid: false,
+            reason: None,
+            stale: false,
+            details: Some(vec![
+                HealthProbeResult {
+                    name: "good".to_string(),
+                    valid: true,
+                    fault: None,
+                    message: None,
+                },
+                HealthProbeResult {
+                    name: "forced".to_string(),
+                    valid: true,
+                    fault: Some("injected pass until 2024-06-01T12:00:00Z".to_string()),
+                    message: None,
+                },
+                HealthProbeResult {
+                    name: "bad".to_string(),
+                    valid: false,
+                    fault: Some("injected fail until 2024-06-01T12:00:00Z".to_string()),
+                    message: None,
+                },
+                HealthProbeResult {
+                    name: "pool".to_string(),
+                    valid: false,
+                    fault: None,
+                    message: Some("pool exhausted".to_string()),
+                },
+            ]),
+        };
+
+        assert_eq!(
+            CheckFormat::Text.render(&result, true).unwrap(),
+            "forced (injected pass until 2024-06-01T12:00:00Z)\n\
+             bad (injected fail until 2024-06-01T12:00:00Z)\n\
+             pool: pool exhausted\n"
+        );
+        let mut passing = result.clone();
+        passing.valid = true;
+        passing.details.as_mut().unwrap().truncate(2);
+        assert_eq!(
+            CheckFormat::Text.render(&passing, true).unwrap(),
+            "ok\nforced (injected pass until 2024-06-01T12:00:00Z)\n"
+        );
+
+        let health: serde_json::Value =
+            serde_json::from_str(&CheckFormat::HealthJson.render(&result, true).unwrap()).unwrap();
+        assert_eq!(health["status"], "fail");
+        assert_eq!(health["checks"]["good"][0]["status"], "pass");
+        assert_eq!(health["checks"]["bad"][0]["status"], "fail");
+        assert_eq!(
+            health["checks"]["bad"][0]["output"],
+            "injected fail until 2024-06-01T12:00:00Z"
+        );
+        assert_eq!(health["checks"]["pool"][0]["output"], "pool exhausted");
+
+        assert_eq!(
+            CheckFormat::Text.render(&result, false).unwrap(),
+            "forced (injected pass until 2024-06-01T12:00:00Z)\n\
+             bad (injected fail until 2024-06-01T12:00:00Z)\n\
+             pool\n"
+        );
+
+        let mut result = result.with_details(false);
+        assert_eq!(CheckFormat::Text.render(&result, false).unwrap(), "fail\n");
+
+        result.reason = Some("maintenance: upgrade".to_string());
+        assert_eq!(
+            CheckFormat::Text.render(&result, true).unwrap(),
+            "maintenance: upgrade\n"
+        );
+        let health: serde_json::Value =
+            serde_json::from_str(&CheckFormat::HealthJson.render(&result, true).unwrap()).unwrap();
+        assert_eq!(health["output"], "maintenance: upgrade");
+    }
+}
@@ -0,0 +1,194 @@
+//! OpenAPI description of the HaMS endpoints
+//!
+//! The document is built from the annotations on the handlers and served at `/hams/openapi.json`,
+//! with the paths moved under the configured prefix.
+//! The tests request every documented operation against [super::hams_service] and look up every
+//! served route in the document so the two cannot drift apart.
+
+use utoipa::OpenApi;
+
+use super::handlers::{self, VersionReply};
+use crate::{
+    hams::{
+        check::{CheckProbes, HealthCheckResult, LastResult, ProbeDescription},
+        config::DEFAULT_PREFIX,
+        events::{EventKind, HamsEvent},
+        fault::{FaultKind, FaultReply, FaultRequest},
+        info::{AppInfo, HamsState, InfoReply},
+        loglevel::{LogLevelReply, LogLevelUpdate},
+        maintenance::{MaintenanceReply, MaintenanceRequest},
+    },
+    probe::HealthProbeResult,
+};
+
+/// OpenAPI document for the HaMS service
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "HaMS",
+        description = "Health and Monitoring System endpoints for liveness, readiness and metrics"
+    ),
+    paths(
+        handlers::version,
+        handlers::shutdown_handler,
+        handlers::alive,
+        handlers::ready,
+        handlers::metrics,
+        handlers::info,
+        handlers::loglevel,
+        handlers::set_loglevel,
+        handlers::probe_list,
+        handlers::probe_action,
+        handlers::maintenance,
+        handlers::set_maintenance,
+        handlers::clear_maintenance,
+        handlers::fault_list,
+        handlers::inject_fault,
+        handlers::clear_fault,
+        handlers::events,
+        handlers::openapi
+    ),
+    components(schemas(
+        VersionReply,
+        HealthCheckResult,
+        HealthProbeResult,
+        InfoReply,
+        AppInfo,
+        HamsState,
+        LogLevelReply,
+        LogLevelUpdate,
+        CheckProbes,
+        ProbeDescription,
+        LastResult,
+        MaintenanceReply,
+        MaintenanceRequest,
+        FaultKind,
+        FaultRequest,
+        FaultReply,
+        HamsEvent,
+        EventKind
+    )),
+    tags((name = "hams", description = "HaMS service endpoints"))
+)]
+pub(crate) struct ApiDoc;
+
+/// The document with the annotated paths under the default prefix moved under the given prefix
+pub(super) fn document(prefix: &str) -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    if prefix != DEFAULT_PREFIX {
+        doc.paths.paths = std::mem::take(&mut doc.paths.paths)
+            .into_iter()
+            .map(|(path, item)| match path.strip_prefix(DEFAULT_PREFIX) {
+                Some(route) => (format!("{prefix}{route}"), item),
+                None => (path, item),
+            })
+            .collect();
+    }
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use utoipa::openapi::PathItemType;
+    use warp::http::StatusCode;
+
+    use super::*;
+    use crate::{
+        hams::{
+            config::HamsConfig,
+            webservice::{
+                access::{PARAM_ROUTES, ROUTES},
+                hams_service,
+            },
+            Hams,
+        },
+        probe::{manual::Manual, FFIProbe},
+    };
+
+    /// Every documented operation is served and replies with one of its documented statuses.
+    /// Path parameters are filled in to name an existing probe.
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_openapi_matches_routes() {
+        let hams = Hams::new(HamsConfig::default());
+        hams.alive
+            .insert_async(FFIProbe::from(Manual::new("openapi", true)).into())
+            .await;
+        let api = hams_service(hams);
+        let doc = ApiDoc::openapi();
+
+        assert!(!doc.paths.paths.is_empty());
+        for (path, item) in doc.paths.paths.iter() {
+            for (method, operation) in item.operations.iter() {
+                let method = match method {
+                    PathItemType::Get => "GET",
+                    PathItemType::Post => "POST",
+                    PathItemType::Put => "PUT",
+                    PathItemType::Delete => "DELETE",
+                    _ => panic!("Unexpected method documented for {path}"),
+                };
+
+                let uri = path
+                    .replace("{check}", "alive")
+                    .replace("{probe}", "openapi")
+                    .replace("{action}", "check");
+                let reply = warp::test::request()
+                    .method(method)
+                    .path(&uri)
+                    .reply(&api)
+                    .await;
+
+                assert_ne!(reply.status(), StatusCode::NOT_FOUND, "{method} {path}");
+                assert!(
+                    operation
+                        .responses
+                        .responses
+                        .contains_key(reply.status().as_str()),
+                    "{method} {path} replied {} which is not documented",
+                    reply.status()
+                );
+            }
+        }
+    }
+
+    /// Every route served by HaMS is documented
+    #[test]
+    fn test_routes_documented() {
+        let doc = ApiDoc::openapi();
+        for route in ROUTES
+            .into_iter()
+            .chain(PARAM_ROUTES.into_iter().map(|(_, route)| route))
+        {
+            assert!(
+                doc.paths.paths.contains_key(route),
+                "{route} is not documented"
+            );
+        }
+    }
+
+    /// The served document is the generated document
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_openapi_served() {
+        let api = hams_service(Hams::new(HamsConfig::default()));
+
+        let reply = warp::test::request()
+            .path("/hams/openapi.json")
+            .reply(&api)
+            .await;
+        assert_eq!(reply.status(), StatusCode::OK);
+
+        let served: serde_json::Value = serde_json::from_slice(reply.body()).unwrap();
+        let generated = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert_eq!(served, generated);
+        assert!(served["openapi"].as_str().unwrap().starts_with("3."));
+        for schema in [
+            "VersionReply",
+            "HealthCheckResult",
+            "HealthProbeResult",
+            "InfoReply",
+        ] {
+            assert!(served["components"]["schemas"].get(schema).is_some());
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! TLS for the HaMS webservice
+//!
+//! Connections are accepted here rather than by warp so that a verified client certificate can be
+//! attached to each request as a [ClientIdentity] for the auth filters. warp does not see the remote
+//! address of these connections, so it is set as the access log [PEER] while each request is served.
+
+use std::{convert::Infallible, future::Future, net::SocketAddr, sync::Arc};
+
+use log::{debug, info, warn};
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        server::WebPkiClientVerifier,
+        RootCertStore, ServerConfig,
+    },
+    TlsAcceptor,
+};
+use tokio_util::sync::CancellationToken;
+use warp::hyper::{server::conn::Http, service::Service, Body, Request, Response};
+
+use super::{
+    access::PEER,
+    auth::{common_name, ClientIdentity},
+};
+use crate::{error::HamsError, hams::config::TlsConfig};
+
+fn tls_error(e: impl std::fmt::Display) -> HamsError {
+    HamsError::TlsError(e.to_string())
+}
+
+/// Load the certificates and keys of the TLS configuration
+pub(crate) fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>, HamsError> {
+    let certs = CertificateDer::pem_file_iter(&config.cert)
+        .map_err(tls_error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(tls_error)?;
+    let key = PrivateKeyDer::from_pem_file(&config.key).map_err(tls_error)?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(tls_error)?;
+
+    let builder = match &config.client_ca {
+        Some(client_ca) => {
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(client_ca).map_err(tls_error)? {
+                roots.add(cert.map_err(tls_error)?).map_err(tls_error)?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .allow_unauthenticated()
+                .build()
+                .map_err(tls_error)?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut server = builder.with_single_cert(certs, key).map_err(tls_error)?;
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(Arc::new(server))
+}
+
+/// Bind the address and return the bound address and the future accepting TLS connections until cancelled
+pub(crate) async fn serve<S>(
+    service: S,
+    address: SocketAddr,
+    config: Arc<ServerConfig>,
+    ct: CancellationToken,
+) -> Result<(SocketAddr, impl Future<Output = ()>), HamsError>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+{
+    let listener = TcpListener::bind(address).await?;
+    let address = listener.local_addr()?;
+    let acceptor = TlsAcceptor::from(config);
+
+    let server = async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                _ = ct.cancelled() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!("Error accepting connection: {e}");
+                        continue;
+                    }
+                },
+            };
+
+            let acceptor = acceptor.clone();
+            let service = service.clone();
+            tokio::spawn(async move {
+                let stream = match acceptor.accept(stream).await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        debug!("TLS handshake with {peer} failed: {e}");
+                        return;
+                    }
+                };
+
+                let identity = stream
+                    .get_ref()
+                    .1
+                    .peer_certificates()
+                    .and_then(|certs| certs.first())
+                    .map(|cert| ClientIdentity {
+                        common_name: common_name(cert),
+                    });
+
+                let service = warp::hyper::service::service_fn(move |mut request| {
+                    if let Some(identity) = &identity {
+                        request.extensions_mut().insert(identity.clone());
+                    }
+                    PEER.scope(peer, service.clone().call(request))
+                });
+
+                if let Err(e) = Http::new().serve_connection(stream, service).await {
+                    debug!("Error serving connection from {peer}: {e}");
+                }
+            });
+        }
+        info!("Stopped accepting TLS connections on {address}");
+    };
+    Ok((address, server))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::{
+        rustls::{pki_types::ServerName, ClientConfig},
+        TlsConnector,
+    };
+
+    use super::*;
+    use crate::hams::{
+        config::{AuthConfig, HamsConfig},
+        webservice::hams_service,
+        Hams,
+    };
+
+    fn cert_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/certs")
+            .join(name)
+    }
+
+    fn tls_config() -> TlsConfig {
+        TlsConfig {
+            cert: cert_path("server.pem"),
+            key: cert_path("server.key"),
+            client_ca: Some(cert_path("ca.pem")),
+        }
+    }
+
+    /// Send a GET over TLS, optionally presenting the client certificate, and return the status line
+    async fn get(address: SocketAddr, path: &str, client_cert: bool) -> String {
+        request(address, path, client_cert)
+            .await
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Send a GET over TLS, optionally presenting the client certificate, and return the reply
+    async fn request(address: SocketAddr, path: &str, client_cert: bool) -> String {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_file(cert_path("ca.pem")).unwrap())
+            .unwrap();
+        let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = if client_cert {
+            builder
+                .with_client_auth_cert(
+                    vec![CertificateDer::from_pem_file(cert_path("client.pem")).unwrap()],
+                    PrivateKeyDer::from_pem_file(cert_path("client.key")).unwrap(),
+                )
+                .unwrap()
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let tcp = tokio::net::TcpStream::connect(address).await.unwrap();
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .unwrap();
+        stream
+            .write_all(
+                format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                    .as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).await.ok();
+        reply
+    }
+
+    /// Anonymous clients reach the open routes while the client certificate authenticates for metrics
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_mtls() {
+        let tls = tls_config();
+        let hams = Hams::new(HamsConfig {
+            tls: Some(tls.clone()),
+            auth: AuthConfig {
+                client_names: vec!["kubelet-test".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let ct = CancellationToken::new();
+        let (address, server) = serve(
+            warp::service(hams_service(hams)),
+            "127.0.0.1:0".parse().unwrap(),
+            server_config(&tls).unwrap(),
+            ct.clone(),
+        )
+        .await
+        .unwrap();
+        let server = tokio::spawn(server);
+
+        assert_eq!(get(address, "/hams/alive", false).await, "HTTP/1.1 200 OK");
+        assert_eq!(
+            get(address, "/hams/metrics", false).await,
+            "HTTP/1.1 401 Unauthorized"
+        );
+        assert_eq!(get(address, "/hams/metrics", true).await, "HTTP/1.1 200 OK");
+
+        ct.cancel();
+        server.await.unwrap();
+    }
+
+    /// Requests are served knowing the peer of their connection
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn test_peer() {
+        let tls = tls_config();
+        let ct = CancellationToken::new();
+        let route = warp::Filter::map(warp::any(), || PEER.with(|peer| peer.ip().to_string()));
+        let (address, server) = serve(
+            warp::service(route),
+            "127.0.0.1:0".parse().unwrap(),
+            server_config(&tls).unwrap(),
+            ct.clone(),
+        )
+        .await
+        .unwrap();
+        let server = tokio::spawn(server);
+
+        let reply = request(address, "/hams/alive", false).await;
+        assert!(reply.ends_with("\r\n\r\n127.0.0.1"), "{reply}");
+
+        ct.cancel();
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_server_config_errors() {
+        assert!(server_config(&tls_config()).is_ok());
+
+        let missing = TlsConfig {
+            key: cert_path("missing.key"),
+            ..tls_config()
+        };
+        assert!(matches!(
+            server_config(&missing),
+            Err(HamsError::TlsError(_))
+        ));
+    }
+}
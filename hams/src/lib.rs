@@ -4,6 +4,7 @@
 
 pub mod error;
 mod hams;
+pub mod metrics;
 mod preflight;
 /// This module provides the health probes
 pub mod probe;
@@ -15,12 +16,16 @@ use crate::probe::ffitraits::HealthProbe;
 use crate::probe::{AsyncHealthProbe, FFIProbe};
 
 use self::hams::Hams;
-use error::{FFIEnum, HamsError};
+use error::{catch_code, FFIEnum, HamsError};
 use ffi_helpers::catch_panic;
 use ffi_log2::{logger_init, LogParam};
 use hams::config::HamsConfig;
+use hams::fault::FaultRequest;
+use hams::loglevel::LogLevelUpdate;
 use libc::{c_int, c_void};
 use log::{error, info};
+use metrics::{Counter, Gauge, Histogram};
+use probe::custom::{Custom, CustomCheckCallback, CustomFreeCallback};
 use probe::ffitraits::BoxedHealthProbe;
 use probe::kick::Kick;
 use probe::manual::Manual;
@@ -82,6 +87,18 @@ pub extern "C" fn hams_version() -> *const libc::c_char {
     c_version.into_raw()
 }
 
+/// Version of the layout of the HealthProbe vtable. It is raised whenever a method is added to or
+/// removed from the vtable, so probes must be built for the same version as the library.
+/// Version 2 added describe and set_valid and version 3 added message.
+pub const HAMS_PROBE_ABI_VERSION: u32 = 3;
+
+/// Return the HealthProbe vtable version of the library, to check it against HAMS_PROBE_ABI_VERSION
+/// of the header a host was built with before inserting probes
+#[no_mangle]
+pub extern "C" fn hams_probe_abi_version() -> u32 {
+    HAMS_PROBE_ABI_VERSION
+}
+
 #[cfg_attr(doc, aquamarine::aquamarine)]
 ///
 /// Register logging for uservice
@@ -106,7 +123,7 @@ pub extern "C" fn hams_version() -> *const libc::c_char {
 #[no_mangle]
 pub extern "C" fn hams_logger_init(param: LogParam) -> i32 {
     // ffi_helpers::null_pointer_check!(param);
-    catch_panic!(
+    catch_code!(
         logger_init(param);
         info!(
             "Logging registered for {}:{} (PID: {})",
@@ -118,6 +135,26 @@ pub extern "C" fn hams_logger_init(param: LogParam) -> i32 {
     )
 }
 
+/// # Safety
+///
+/// Change the level of the HaMS logs at runtime.
+///
+/// The level is one of off, error, warn, info, debug or trace. A NULL target changes the default
+/// level, otherwise the level of the target and the targets nested below it. A NULL level removes the
+/// override of the target.
+#[no_mangle]
+pub unsafe extern "C" fn hams_set_log_level(
+    level: *const libc::c_char,
+    target: *const libc::c_char,
+) -> i32 {
+    catch_code!(
+        let level = unsafe { optional_str(level) }?;
+        let target = unsafe { optional_str(target) }?;
+        LogLevelUpdate::parse(level, target)?.apply()?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
 /// # Safety
 ///
 /// Initialise the hams object giving it a name on construction
@@ -140,7 +177,8 @@ pub unsafe extern "C" fn hams_new(
 
         let config = HamsConfig{
             address: address_str.parse()?,
-            name: name_str.to_string()
+            name: name_str.to_string(),
+            ..Default::default()
          };
 
         info!("Registering HaMS: {}", name_str);
@@ -148,6 +186,41 @@ pub unsafe extern "C" fn hams_new(
     )
 }
 
+/// # Safety
+///
+/// Initialise the hams object from a configuration document in JSON, eg
+/// `{"name": "shop", "address": "0.0.0.0:8079", "ready": {"retry_after": 5}}`. Fields not given take
+/// their defaults and unknown fields are an error. Returns NULL when the document is not valid, with the
+/// reason in hams_last_error_message
+#[no_mangle]
+pub unsafe extern "C" fn hams_new_from_json(config: *const libc::c_char) -> *mut Hams {
+    ffi_helpers::null_pointer_check!(config);
+
+    catch_panic!(
+        let config = unsafe { CStr::from_ptr(config) }.to_str()?;
+        let config = HamsConfig::from_json(config)?;
+        info!("Registering HaMS: {}", config.name);
+        Ok(Box::into_raw(Box::new(Hams::new(config))))
+    )
+}
+
+/// # Safety
+///
+/// Initialise the hams object from a configuration document in YAML with the same fields as
+/// hams_new_from_json. Returns NULL when the document is not valid, with the reason in
+/// hams_last_error_message
+#[no_mangle]
+pub unsafe extern "C" fn hams_new_from_yaml(config: *const libc::c_char) -> *mut Hams {
+    ffi_helpers::null_pointer_check!(config);
+
+    catch_panic!(
+        let config = unsafe { CStr::from_ptr(config) }.to_str()?;
+        let config = HamsConfig::from_yaml(config)?;
+        info!("Registering HaMS: {}", config.name);
+        Ok(Box::into_raw(Box::new(Hams::new(config))))
+    )
+}
+
 /// # Safety
 ///
 /// Free the HaMS. The object must be created wtih the hams_init function
@@ -157,7 +230,7 @@ pub unsafe extern "C" fn hams_free(ptr: *mut Hams) -> i32 {
 
     let hams = AssertUnwindSafe(unsafe { Box::from_raw(ptr) });
 
-    catch_panic!(
+    catch_code!(
         let name = &hams.as_ref().name;
 
         info!("Releasing hams: {}", name);
@@ -166,6 +239,76 @@ pub unsafe extern "C" fn hams_free(ptr: *mut Hams) -> i32 {
     )
 }
 
+/// Read an optional C string where NULL is None
+///
+/// # Safety
+/// The pointer must be NULL or a valid nul terminated string
+unsafe fn optional_str<'a>(ptr: *const libc::c_char) -> Result<Option<&'a str>, HamsError> {
+    if ptr.is_null() {
+        Ok(None)
+    } else {
+        Ok(Some(unsafe { CStr::from_ptr(ptr) }.to_str()?))
+    }
+}
+
+/// # Safety
+///
+/// Set the version of the application reported by HaMS on version and info
+#[no_mangle]
+pub unsafe extern "C" fn hams_set_version(ptr: *mut Hams, version: *const libc::c_char) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(version);
+
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let version = unsafe { CStr::from_ptr(version) }.to_str()?;
+        hams.set_version(version)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Set the git commit and build time of the application reported by HaMS on info.
+/// Either may be NULL to clear it.
+#[no_mangle]
+pub unsafe extern "C" fn hams_set_build_info(
+    ptr: *mut Hams,
+    git_sha: *const libc::c_char,
+    build_time: *const libc::c_char,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let git_sha = unsafe { optional_str(git_sha) }?;
+        let build_time = unsafe { optional_str(build_time) }?;
+        hams.set_build_info(git_sha, build_time)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Set a label of the application reported by HaMS on info. A NULL value removes the label.
+#[no_mangle]
+pub unsafe extern "C" fn hams_set_label(
+    ptr: *mut Hams,
+    key: *const libc::c_char,
+    value: *const libc::c_char,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(key);
+
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let key = unsafe { CStr::from_ptr(key) }.to_str()?;
+        let value = unsafe { optional_str(value) }?;
+        hams.set_label(key, value)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
 /// # Safety
 /// Register the prometheus callback
 /// This will register the prometheus callback with the HaMS object
@@ -225,13 +368,11 @@ pub unsafe extern "C" fn hams_register_prometheus(
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
     info!("Registering Prometheus callback for {}", hams.name);
 
-    catch_panic!(
-        AssertUnwindSafe(hams).register_prometheus(my_cb, my_cb_free, state).expect("Register prometheus callbacks");
+    catch_code!(
+        AssertUnwindSafe(hams).register_prometheus(my_cb, my_cb_free, state)?;
 
         Ok(1)
     )
-
-    // )
 }
 
 /// Degregister promethues from HaMS
@@ -246,12 +387,61 @@ pub unsafe extern "C" fn hams_deregister_prometheus(ptr: *mut Hams) -> i32 {
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
     info!("Deregistering Prometheus callback for {}", hams.name);
 
-    catch_panic!(
-        AssertUnwindSafe(hams).deregister_prometheus().expect("Deregister prometheus");
+    catch_code!(
+        AssertUnwindSafe(hams).deregister_prometheus()?;
         Ok(1)
     )
 }
 
+/// # Safety
+///
+/// Register a named collector called on every scrape of the metrics. Its output is served after the
+/// output of the collectors before it by name. Collectors with other names are kept, a collector already
+/// registered with the name is an error.
+///
+/// The output must be in the Prometheus text format. Invalid families, and families named like native
+/// metrics, are dropped, logged and counted in `hams_metrics_callback_errors_total`.
+///
+/// The collector is called on a HaMS worker thread and may still be running after its scrape has timed
+/// out, so its state must be safe to use from that thread and outlive its deregistration
+#[no_mangle]
+pub unsafe extern "C" fn hams_register_collector(
+    ptr: *mut Hams,
+    name: *const libc::c_char,
+    my_cb: extern "C" fn(ptr: *const c_void) -> *mut libc::c_char,
+    my_cb_free: extern "C" fn(*mut libc::c_char),
+    state: *const c_void,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(name);
+
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let name = unsafe { CStr::from_ptr(name) }.to_str()?;
+        hams.register_collector(name, my_cb, my_cb_free, state)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Deregister a named collector. A collector not registered is an error
+#[no_mangle]
+pub unsafe extern "C" fn hams_deregister_collector(
+    ptr: *mut Hams,
+    name: *const libc::c_char,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(name);
+
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let name = unsafe { CStr::from_ptr(name) }.to_str()?;
+        hams.deregister_collector(name)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
 /// Register a shutdown callback
 /// # Safety
 #[no_mangle]
@@ -265,8 +455,8 @@ pub unsafe extern "C" fn hams_register_shutdown(
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
     info!("Registering Shutdown callback for {}", hams.name);
 
-    catch_panic!(
-        AssertUnwindSafe(hams).register_shutdown(my_cb, state).expect("Register shutdown callbacks");
+    catch_code!(
+        AssertUnwindSafe(hams).register_shutdown(my_cb, state)?;
         Ok(FFIEnum::Success as i32)
     )
 }
@@ -280,8 +470,8 @@ pub unsafe extern "C" fn hams_deregister_shutdown(ptr: *mut Hams) -> i32 {
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
     info!("Deregistering Shutdown callback for {}", hams.name);
 
-    catch_panic!(
-        AssertUnwindSafe(hams).deregister_shutdown().expect("Deregister shutdown");
+    catch_code!(
+        AssertUnwindSafe(hams).deregister_shutdown()?;
         Ok(FFIEnum::Success as i32)
     )
 }
@@ -295,8 +485,8 @@ pub unsafe extern "C" fn hams_start(ptr: *mut Hams) -> i32 {
 
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
     info!("start my ham {}", hams.name);
-    catch_panic!(
-        AssertUnwindSafe(hams).start().expect("Start HaMS");
+    catch_code!(
+        AssertUnwindSafe(hams).start()?;
         Ok(1)
     )
 }
@@ -310,7 +500,7 @@ pub unsafe extern "C" fn hams_stop(ptr: *mut Hams) -> i32 {
 
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
     info!("Stop HaMS {}", hams.name);
-    catch_panic!(match AssertUnwindSafe(hams).stop() {
+    catch_code!(match AssertUnwindSafe(hams).stop() {
         Ok(_) => {
             info!("HaMS stopped");
             Ok(FFIEnum::Success as i32)
@@ -343,7 +533,7 @@ pub unsafe extern "C" fn hams_alive_insert(
     ffi_helpers::null_pointer_check!(probe);
 
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
-    catch_panic!(
+    catch_code!(
 
         // Take ownership of the probe
         let probe = unsafe { BoxedHealthProbe::from_raw(probe as *mut () ) };
@@ -353,12 +543,11 @@ pub unsafe extern "C" fn hams_alive_insert(
         // Convert a BoxedHealthProbe to a FFIProbe (which is a Box<dyn AsyncHealthProbe>) so we can store it
         let ffi_probe = Box::new(FFIProbe::from(probe)) as Box<dyn AsyncHealthProbe>;
 
-        let retval_bool = if AssertUnwindSafe(hams).alive_insert(ffi_probe) {
-            1
-        } else {
-            0
-        };
-        Ok(retval_bool)
+        let name = ffi_probe.name()?;
+        if !AssertUnwindSafe(hams).alive_insert(ffi_probe) {
+            Err(HamsError::AlreadyExists(format!("alive probe {name}")))?;
+        }
+        Ok(FFIEnum::Success as i32)
     )
 }
 
@@ -374,17 +563,17 @@ pub unsafe extern "C" fn hams_alive_remove(
 
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
 
-    catch_panic!(
+    catch_code!(
         // Take ownership of the probe
         let probe = unsafe { BoxedHealthProbe::from_raw(probe as *mut () ) };
 
         info!("Removing alive probe: {}", CString::from_raw(probe.name()).into_string().unwrap());
 
         let ffi_probe = Box::new(FFIProbe::from(probe)) as Box<dyn AsyncHealthProbe>;
-        match AssertUnwindSafe(hams).alive_remove(&ffi_probe) {
-            true => Ok(1),
-            false => Ok(0),
+        if !AssertUnwindSafe(hams).alive_remove(&ffi_probe) {
+            Err(HamsError::NotFound(format!("alive probe {}", ffi_probe.name()?)))?;
         }
+        Ok(FFIEnum::Success as i32)
         // drop ffi_probe will delete the probe from the heap
         // AND the alive_remove should have dropped the one from the list
     )
@@ -402,7 +591,7 @@ pub unsafe extern "C" fn hams_ready_insert(
     ffi_helpers::null_pointer_check!(probe);
 
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
-    catch_panic!(
+    catch_code!(
 
           // Take ownership of the probe
           let probe = unsafe { BoxedHealthProbe::from_raw(probe as *mut () ) };
@@ -414,11 +603,11 @@ pub unsafe extern "C" fn hams_ready_insert(
           let ffi_probe = Box::new(FFIProbe::from(probe)) as Box<dyn AsyncHealthProbe>;
           println!("using FFIProbe {:?}", ffi_probe.name());
 
-        if AssertUnwindSafe(hams).ready_insert(ffi_probe) {
-            Ok(1)
-        } else {
-            Ok(0)
+        let name = ffi_probe.name()?;
+        if !AssertUnwindSafe(hams).ready_insert(ffi_probe) {
+            Err(HamsError::AlreadyExists(format!("ready probe {name}")))?;
         }
+        Ok(FFIEnum::Success as i32)
     )
 }
 
@@ -434,165 +623,612 @@ pub unsafe extern "C" fn hams_ready_remove(
 
     let hams = AssertUnwindSafe(unsafe { &mut *ptr });
 
-    catch_panic!(
+    catch_code!(
         // Take ownership of the probe
         let probe = unsafe { BoxedHealthProbe::from_raw(probe as *mut () ) };
 
         info!("Removing alive probe: {}", CString::from_raw(probe.name()).into_string().unwrap());
 
         let ffi_probe = Box::new(FFIProbe::from(probe)) as Box<dyn AsyncHealthProbe>;
-        match AssertUnwindSafe(hams).ready_remove(&ffi_probe) {
-            true => Ok(1),
-            false => Ok(0),
+        if !AssertUnwindSafe(hams).ready_remove(&ffi_probe) {
+            Err(HamsError::NotFound(format!("ready probe {}", ffi_probe.name()?)))?;
         }
+        Ok(FFIEnum::Success as i32)
         // drop ffi_probe will delete the probe from the heap
         // AND the alive_remove should have dropped the one from the list
     )
 }
 
 /// # Safety
-/// Check the alive probe to see if it is still alive
-/// TODO: This will require to store the runtime and block on teh thred while we execute on the async runtime
-// #[no_mangle]
-// pub unsafe extern "C" fn hams_alive_check(ptr: *mut Hams) -> i32 {
-//     ffi_helpers::null_pointer_check!(ptr, -1);
-
-//     let now = Instant::now();
-//     catch_panic!(
-//         let hams = unsafe {&mut *ptr};
-
-//         if hams.alive.check(now).await.valid {
-//             Ok(1)
-//         } else {
-//             Ok(0)
-//         }
-//     )
-// }
-
-/// Return a manual health probe
-///   We must return a ManualHealthProbe so that we can call set/enable etc. Later we box it for poly use
-/// # Safety
-/// Create a manual health probe
+///
+/// Set maintenance which fails ready with the reason, regardless of its probes, while alive is unaffected.
+/// Maintenance ends after expiry_secs, at most a year, or lasts until cleared when expiry_secs is 0.
+/// A NULL reason clears maintenance.
 #[no_mangle]
-pub unsafe extern "C" fn probe_manual_new(name: *const libc::c_char, check: bool) -> *mut Manual {
-    ffi_helpers::null_pointer_check!(name);
-
-    let name_str = unsafe { CStr::from_ptr(name).to_str().map_err(HamsError::from) };
+pub unsafe extern "C" fn hams_set_maintenance(
+    ptr: *mut Hams,
+    reason: *const libc::c_char,
+    expiry_secs: u64,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
 
-    match name_str {
-        Ok(name_str) => {
-            info!("Creating ManualHealthProbe: {}", name_str);
-            Box::into_raw(Box::new(Manual::new(name_str, check)))
-        }
-        Err(e) => {
-            error!("Failed to create ManualHealthProbe");
-            (e.into_ffi_enum_with_update() as u32) as *mut Manual
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        match unsafe { optional_str(reason) }? {
+            Some(reason) => {
+                let expiry = (expiry_secs > 0).then(|| std::time::Duration::from_secs(expiry_secs));
+                hams.set_maintenance(reason, expiry)?
+            }
+            None => hams.clear_maintenance()?,
         }
-    }
+        Ok(FFIEnum::Success as i32)
+    )
 }
 
-/// Free Manual Health Probe
-///
 /// # Safety
-/// Free the Manual Health Probe. The object must be created with HaMS library
+///
+/// List the probes of every check as JSON with their type, configuration and last result.
+/// The caller owns the string and must release it with hams_string_free. Returns NULL on error.
 #[no_mangle]
-pub unsafe extern "C" fn probe_manual_free(ptr: *mut Manual) -> i32 {
+pub unsafe extern "C" fn hams_probe_list(ptr: *mut Hams) -> *mut libc::c_char {
     ffi_helpers::null_pointer_check!(ptr);
 
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
     catch_panic!(
-        let probe = Box::from_raw(ptr);
-
-        info!("Releasing manual probe: {}", CString::from_raw(probe.name()).into_string().unwrap());
-        drop(probe);
-        Ok(1)
+        let probes = futures::executor::block_on(hams.probe_list());
+        let json = serde_json::to_string(&probes)?;
+        Ok(CString::new(json)?.into_raw())
     )
 }
 
-/// Return a boxed health probe from the manual health probe
 /// # Safety
-/// Return a boxed health probe from the manual health probe
+///
+/// Free a string returned by HaMS. The string must have been created by the HaMS library
 #[no_mangle]
-// pub unsafe extern "C" fn probe_manual_boxed(ptr: *mut Manual) -> *mut () {
-pub unsafe extern "C" fn probe_manual_boxed(ptr: *mut Manual) -> *mut BoxedHealthProbe<'static> {
+pub unsafe extern "C" fn hams_string_free(ptr: *mut libc::c_char) -> i32 {
     ffi_helpers::null_pointer_check!(ptr);
 
-    catch_panic!(
-        let x = {
-            let probe =  (*ptr).clone();
-            let boxed_probe = BoxedHealthProbe::new(probe);
+    catch_code!(
+        drop(unsafe { CString::from_raw(ptr) });
+        Ok(FFIEnum::Success as i32)
+    )
+}
 
+/// Length of the message of the last error of a HaMS call on this thread in bytes, including the NUL
+/// terminator, or 0 when there is no error
+#[no_mangle]
+pub extern "C" fn hams_last_error_length() -> c_int {
+    ffi_helpers::error_handling::last_error_length()
+}
 
-            // Use into_raw to pass ownership to the caller
-            boxed_probe.into_raw() as *mut BoxedHealthProbe<'static>
-        };
+/// # Safety
+///
+/// Copy the message of the last error of a HaMS call on this thread into the buffer of length bytes,
+/// NUL terminated. Returns the bytes written including the NUL, 0 when there is no error and -1 when the
+/// buffer is NULL or too small. The error is kept until the next error or hams_clear_last_error
+#[no_mangle]
+pub unsafe extern "C" fn hams_last_error_message(
+    buffer: *mut libc::c_char,
+    length: c_int,
+) -> c_int {
+    // Checked here as the check of ffi_helpers would replace the error being read
+    if buffer.is_null() || length < 0 {
+        return -1;
+    }
+    unsafe { ffi_helpers::error_handling::error_message_utf8(buffer, length) }
+}
 
-        Ok(x)
-    )
+/// Clear the last error of a HaMS call on this thread
+#[no_mangle]
+pub extern "C" fn hams_clear_last_error() {
+    ffi_helpers::error_handling::clear_last_error()
 }
 
-/// Free Health Probe
 /// # Safety
-/// Free the Health Probe. The object must be created with HaMS library
+///
+/// Enable or disable a manual probe of the alive or ready check by name
 #[no_mangle]
-pub unsafe extern "C" fn probe_free(ptr: *mut BoxedHealthProbe) -> i32 {
+pub unsafe extern "C" fn hams_probe_set_valid(
+    ptr: *mut Hams,
+    check: *const libc::c_char,
+    probe: *const libc::c_char,
+    valid: bool,
+) -> i32 {
     ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(check);
+    ffi_helpers::null_pointer_check!(probe);
 
-    catch_panic!(
-        let probe = Box::from_raw(ptr);
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let check = unsafe { CStr::from_ptr(check) }.to_str()?;
+        let probe = unsafe { CStr::from_ptr(probe) }.to_str()?;
+        futures::executor::block_on(hams.check_group(check)?.set_valid(probe, valid))?;
+        info!("Probe {probe} of {check} set to {valid}");
+        Ok(FFIEnum::Success as i32)
+    )
+}
 
-        info!("Releasing probe: {}", CString::from_raw(probe.name()).into_string().unwrap());
-        drop(probe);
-        Ok(1)
+/// # Safety
+///
+/// Check a single probe of the alive or ready check by name now.
+/// Returns 1 if the probe passes, 0 if it fails and a negative FFIEnum on error
+#[no_mangle]
+pub unsafe extern "C" fn hams_probe_check(
+    ptr: *mut Hams,
+    check: *const libc::c_char,
+    probe: *const libc::c_char,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr, -1);
+    ffi_helpers::null_pointer_check!(check, -1);
+    ffi_helpers::null_pointer_check!(probe, -1);
+
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let check = unsafe { CStr::from_ptr(check) }.to_str()?;
+        let probe = unsafe { CStr::from_ptr(probe) }.to_str()?;
+        let result = futures::executor::block_on(hams.check_group(check)?.check_probe(probe, SystemTime::now()))?;
+        Ok(result.valid as i32)
     )
 }
 
-/// Enable the Manual Health Probe
 /// # Safety
-/// Enable the Manual Health Probe
-/// This will set the check to true
+///
+/// Inject a fault into a probe of the alive or ready check by name. The fault is the JSON used by the
+/// admin API, eg `{"fault": {"type": "fail"}, "ttl_secs": 60}`, and the probe reverts to its real check
+/// after the TTL, which is at most a day. A NULL fault reverts the probe now
 #[no_mangle]
-pub unsafe extern "C" fn probe_manual_enable(ptr: *mut Manual) -> i32 {
+pub unsafe extern "C" fn hams_inject_fault(
+    ptr: *mut Hams,
+    check: *const libc::c_char,
+    probe: *const libc::c_char,
+    fault: *const libc::c_char,
+) -> i32 {
     ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(check);
+    ffi_helpers::null_pointer_check!(probe);
 
-    catch_panic!(
-        let probe = &mut *ptr;
-        probe.enable();
-        Ok(1)
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let check = unsafe { CStr::from_ptr(check) }.to_str()?;
+        let probe = unsafe { CStr::from_ptr(probe) }.to_str()?;
+        let fault = unsafe { optional_str(fault) }?
+            .map(|fault| serde_json::from_str::<FaultRequest>(fault)?.into_fault())
+            .transpose()?;
+        futures::executor::block_on(hams.set_fault(check, probe, fault))?;
+        Ok(FFIEnum::Success as i32)
     )
 }
 
-/// Disable the Manual Health Probe
+/// Read an array of C strings such as label names or values. NULL is an empty array
+unsafe fn str_array<'a>(
+    ptr: *const *const libc::c_char,
+    len: usize,
+) -> Result<Vec<&'a str>, HamsError> {
+    if ptr.is_null() || len == 0 {
+        return Ok(Vec::new());
+    }
+    unsafe { std::slice::from_raw_parts(ptr, len) }
+        .iter()
+        .map(|item| {
+            if item.is_null() {
+                return Err(HamsError::InvalidArgument(
+                    "NULL string in array".to_string(),
+                ));
+            }
+            Ok(unsafe { CStr::from_ptr(*item) }.to_str()?)
+        })
+        .collect()
+}
+
 /// # Safety
-/// Disable the Manual Health Probe
-/// This will set the check to false
+///
+/// Register a counter with the label names, or get the counter already registered with the same name and
+/// labels. The counter must be released with hams_counter_free. Returns NULL on error
 #[no_mangle]
-pub unsafe extern "C" fn probe_manual_disable(ptr: *mut Manual) -> i32 {
+pub unsafe extern "C" fn hams_counter_new(
+    ptr: *mut Hams,
+    name: *const libc::c_char,
+    help: *const libc::c_char,
+    labels: *const *const libc::c_char,
+    label_count: usize,
+) -> *mut Counter {
     ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(name);
 
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
     catch_panic!(
-        let probe = &mut *ptr;
-        probe.disable();
-        Ok(1)
+        let name = unsafe { CStr::from_ptr(name) }.to_str()?;
+        let help = unsafe { optional_str(help) }?.unwrap_or_default();
+        let labels = unsafe { str_array(labels, label_count) }?;
+        let counter = hams.registry.counter(name, help, &labels)?;
+        Ok(Box::into_raw(Box::new(counter)))
     )
 }
 
-/// Toggle the Manual Health Probe
 /// # Safety
-/// Toggle the Manual Health Probe
-/// This will set the check to the opposite of the current value
+///
+/// Add the amount, which cannot be negative, to the series of the counter with the label values
 #[no_mangle]
-pub unsafe extern "C" fn probe_manual_toggle(ptr: *mut Manual) -> i32 {
+pub unsafe extern "C" fn hams_counter_inc(
+    ptr: *mut Counter,
+    values: *const *const libc::c_char,
+    value_count: usize,
+    amount: f64,
+) -> i32 {
     ffi_helpers::null_pointer_check!(ptr);
 
-    catch_panic!(
-        let probe = &mut *ptr;
-        probe.toggle();
-        Ok(1)
+    let counter = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let values = unsafe { str_array(values, value_count) }?;
+        counter.inc_by(&values, amount)?;
+        Ok(FFIEnum::Success as i32)
     )
 }
 
-/// Check the Manual Health Probe
-/// # Safety
+/// Read the label names and values of an exemplar as pairs
+unsafe fn exemplar_labels<'a>(
+    names: *const *const libc::c_char,
+    values: *const *const libc::c_char,
+    count: usize,
+) -> Result<Vec<(&'a str, &'a str)>, HamsError> {
+    let names = unsafe { str_array(names, count) }?;
+    let values = unsafe { str_array(values, count) }?;
+    if names.len() != values.len() {
+        return Err(HamsError::InvalidArgument(
+            "Exemplar label names and values differ in number".to_string(),
+        ));
+    }
+    Ok(names.into_iter().zip(values).collect())
+}
+
+/// # Safety
+///
+/// Add the amount, which cannot be negative, to the series of the counter with the label values and keep
+/// an exemplar of the increase with the exemplar labels, eg trace_id. Exemplars are served with OpenMetrics
+#[no_mangle]
+pub unsafe extern "C" fn hams_counter_inc_exemplar(
+    ptr: *mut Counter,
+    values: *const *const libc::c_char,
+    value_count: usize,
+    amount: f64,
+    exemplar_names: *const *const libc::c_char,
+    exemplar_values: *const *const libc::c_char,
+    exemplar_count: usize,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    let counter = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let values = unsafe { str_array(values, value_count) }?;
+        let exemplar = unsafe { exemplar_labels(exemplar_names, exemplar_values, exemplar_count) }?;
+        counter.inc_by_with_exemplar(&values, amount, &exemplar)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Release a counter. The metric stays registered and reported
+#[no_mangle]
+pub unsafe extern "C" fn hams_counter_free(ptr: *mut Counter) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        drop(unsafe { Box::from_raw(ptr) });
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Register a gauge with the label names, or get the gauge already registered with the same name and
+/// labels. The gauge must be released with hams_gauge_free. Returns NULL on error
+#[no_mangle]
+pub unsafe extern "C" fn hams_gauge_new(
+    ptr: *mut Hams,
+    name: *const libc::c_char,
+    help: *const libc::c_char,
+    labels: *const *const libc::c_char,
+    label_count: usize,
+) -> *mut Gauge {
+    ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(name);
+
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_panic!(
+        let name = unsafe { CStr::from_ptr(name) }.to_str()?;
+        let help = unsafe { optional_str(help) }?.unwrap_or_default();
+        let labels = unsafe { str_array(labels, label_count) }?;
+        let gauge = hams.registry.gauge(name, help, &labels)?;
+        Ok(Box::into_raw(Box::new(gauge)))
+    )
+}
+
+/// # Safety
+///
+/// Set the series of the gauge with the label values
+#[no_mangle]
+pub unsafe extern "C" fn hams_gauge_set(
+    ptr: *mut Gauge,
+    values: *const *const libc::c_char,
+    value_count: usize,
+    value: f64,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    let gauge = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let values = unsafe { str_array(values, value_count) }?;
+        gauge.set(&values, value)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Add the amount, which may be negative, to the series of the gauge with the label values
+#[no_mangle]
+pub unsafe extern "C" fn hams_gauge_add(
+    ptr: *mut Gauge,
+    values: *const *const libc::c_char,
+    value_count: usize,
+    amount: f64,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    let gauge = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let values = unsafe { str_array(values, value_count) }?;
+        gauge.add(&values, amount)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Release a gauge. The metric stays registered and reported
+#[no_mangle]
+pub unsafe extern "C" fn hams_gauge_free(ptr: *mut Gauge) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        drop(unsafe { Box::from_raw(ptr) });
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Register a histogram with the label names and the increasing upper bounds of its buckets, or get the
+/// histogram already registered with the same name, labels and buckets. NULL buckets use the default
+/// buckets. The histogram must be released with hams_histogram_free. Returns NULL on error
+#[no_mangle]
+pub unsafe extern "C" fn hams_histogram_new(
+    ptr: *mut Hams,
+    name: *const libc::c_char,
+    help: *const libc::c_char,
+    labels: *const *const libc::c_char,
+    label_count: usize,
+    buckets: *const f64,
+    bucket_count: usize,
+) -> *mut Histogram {
+    ffi_helpers::null_pointer_check!(ptr);
+    ffi_helpers::null_pointer_check!(name);
+
+    let hams = AssertUnwindSafe(unsafe { &*ptr });
+    catch_panic!(
+        let name = unsafe { CStr::from_ptr(name) }.to_str()?;
+        let help = unsafe { optional_str(help) }?.unwrap_or_default();
+        let labels = unsafe { str_array(labels, label_count) }?;
+        let buckets = (!buckets.is_null())
+            .then(|| unsafe { std::slice::from_raw_parts(buckets, bucket_count) });
+        let histogram = hams.registry.histogram(name, help, &labels, buckets)?;
+        Ok(Box::into_raw(Box::new(histogram)))
+    )
+}
+
+/// # Safety
+///
+/// Record an observation in the series of the histogram with the label values
+#[no_mangle]
+pub unsafe extern "C" fn hams_histogram_observe(
+    ptr: *mut Histogram,
+    values: *const *const libc::c_char,
+    value_count: usize,
+    observation: f64,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    let histogram = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let values = unsafe { str_array(values, value_count) }?;
+        histogram.observe(&values, observation)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Record an observation in the series of the histogram with the label values and keep it as the exemplar
+/// of its bucket with the exemplar labels, eg trace_id. Exemplars are served with OpenMetrics
+#[no_mangle]
+pub unsafe extern "C" fn hams_histogram_observe_exemplar(
+    ptr: *mut Histogram,
+    values: *const *const libc::c_char,
+    value_count: usize,
+    observation: f64,
+    exemplar_names: *const *const libc::c_char,
+    exemplar_values: *const *const libc::c_char,
+    exemplar_count: usize,
+) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    let histogram = AssertUnwindSafe(unsafe { &*ptr });
+    catch_code!(
+        let values = unsafe { str_array(values, value_count) }?;
+        let exemplar = unsafe { exemplar_labels(exemplar_names, exemplar_values, exemplar_count) }?;
+        histogram.observe_with_exemplar(&values, observation, &exemplar)?;
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+///
+/// Release a histogram. The metric stays registered and reported
+#[no_mangle]
+pub unsafe extern "C" fn hams_histogram_free(ptr: *mut Histogram) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        drop(unsafe { Box::from_raw(ptr) });
+        Ok(FFIEnum::Success as i32)
+    )
+}
+
+/// # Safety
+/// Check the alive probe to see if it is still alive
+/// TODO: This will require to store the runtime and block on teh thred while we execute on the async runtime
+// #[no_mangle]
+// pub unsafe extern "C" fn hams_alive_check(ptr: *mut Hams) -> i32 {
+//     ffi_helpers::null_pointer_check!(ptr, -1);
+
+//     let now = Instant::now();
+//     catch_panic!(
+//         let hams = unsafe {&mut *ptr};
+
+//         if hams.alive.check(now).await.valid {
+//             Ok(1)
+//         } else {
+//             Ok(0)
+//         }
+//     )
+// }
+
+/// Return a manual health probe
+///   We must return a ManualHealthProbe so that we can call set/enable etc. Later we box it for poly use
+/// # Safety
+/// Create a manual health probe
+#[no_mangle]
+pub unsafe extern "C" fn probe_manual_new(name: *const libc::c_char, check: bool) -> *mut Manual {
+    ffi_helpers::null_pointer_check!(name);
+
+    let name_str = unsafe { CStr::from_ptr(name).to_str().map_err(HamsError::from) };
+
+    match name_str {
+        Ok(name_str) => {
+            info!("Creating ManualHealthProbe: {}", name_str);
+            Box::into_raw(Box::new(Manual::new(name_str, check)))
+        }
+        Err(e) => {
+            error!("Failed to create ManualHealthProbe");
+            ffi_helpers::update_last_error(e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Free Manual Health Probe
+///
+/// # Safety
+/// Free the Manual Health Probe. The object must be created with HaMS library
+#[no_mangle]
+pub unsafe extern "C" fn probe_manual_free(ptr: *mut Manual) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        let probe = Box::from_raw(ptr);
+
+        info!("Releasing manual probe: {}", CString::from_raw(probe.name()).into_string().unwrap());
+        drop(probe);
+        Ok(1)
+    )
+}
+
+/// Return a boxed health probe from the manual health probe
+/// # Safety
+/// Return a boxed health probe from the manual health probe
+#[no_mangle]
+// pub unsafe extern "C" fn probe_manual_boxed(ptr: *mut Manual) -> *mut () {
+pub unsafe extern "C" fn probe_manual_boxed(ptr: *mut Manual) -> *mut BoxedHealthProbe<'static> {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_panic!(
+        let x = {
+            let probe =  (*ptr).clone();
+            let boxed_probe = BoxedHealthProbe::new(probe);
+
+
+            // Use into_raw to pass ownership to the caller
+            boxed_probe.into_raw() as *mut BoxedHealthProbe<'static>
+        };
+
+        Ok(x)
+    )
+}
+
+/// Free Health Probe
+/// # Safety
+/// Free the Health Probe. The object must be created with HaMS library
+#[no_mangle]
+pub unsafe extern "C" fn probe_free(ptr: *mut BoxedHealthProbe) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        let probe = Box::from_raw(ptr);
+
+        info!("Releasing probe: {}", CString::from_raw(probe.name()).into_string().unwrap());
+        drop(probe);
+        Ok(1)
+    )
+}
+
+/// Enable the Manual Health Probe
+/// # Safety
+/// Enable the Manual Health Probe
+/// This will set the check to true
+#[no_mangle]
+pub unsafe extern "C" fn probe_manual_enable(ptr: *mut Manual) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        let probe = &mut *ptr;
+        probe.enable();
+        Ok(1)
+    )
+}
+
+/// Disable the Manual Health Probe
+/// # Safety
+/// Disable the Manual Health Probe
+/// This will set the check to false
+#[no_mangle]
+pub unsafe extern "C" fn probe_manual_disable(ptr: *mut Manual) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        let probe = &mut *ptr;
+        probe.disable();
+        Ok(1)
+    )
+}
+
+/// Toggle the Manual Health Probe
+/// # Safety
+/// Toggle the Manual Health Probe
+/// This will set the check to the opposite of the current value
+#[no_mangle]
+pub unsafe extern "C" fn probe_manual_toggle(ptr: *mut Manual) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        let probe = &mut *ptr;
+        probe.toggle();
+        Ok(1)
+    )
+}
+
+/// Check the Manual Health Probe
+/// # Safety
 /// Check the Manual Health Probe
 /// This will return the current value of the check
 #[no_mangle]
@@ -604,7 +1240,7 @@ pub unsafe extern "C" fn probe_manual_check(ptr: *mut Manual) -> i32 {
         .unwrap()
         .as_secs();
 
-    catch_panic!(
+    catch_code!(
         let probe = &mut *ptr;
 
         Ok(probe.check(now.try_into()?) as i32)
@@ -640,7 +1276,7 @@ pub unsafe extern "C" fn probe_kick_new(
 pub unsafe extern "C" fn probe_kick_free(ptr: *mut Kick) -> i32 {
     ffi_helpers::null_pointer_check!(ptr);
 
-    catch_panic!(
+    catch_code!(
         let probe = Box::from_raw(ptr);
 
         // let name = &probe.name();
@@ -660,7 +1296,7 @@ pub unsafe extern "C" fn probe_kick_free(ptr: *mut Kick) -> i32 {
 pub unsafe extern "C" fn probe_kick_kick(ptr: *mut Kick) -> i32 {
     ffi_helpers::null_pointer_check!(ptr);
 
-    catch_panic!(
+    catch_code!(
         let probe = &mut *ptr;
         probe.kick();
         Ok(1)
@@ -682,6 +1318,63 @@ pub unsafe extern "C" fn probe_kick_boxed(ptr: *mut Kick) -> *mut BoxedHealthPro
     )
 }
 
+/// Return a custom health probe
+///
+/// # Safety
+/// Create a custom health probe whose check calls `check_cb` with `user_data` on every check of the
+/// probe. The callback runs on a HaMS worker thread, so `user_data` must be safe to use from any thread.
+/// `free_user_data_cb`, which may be NULL, is called with `user_data` once the probe and every copy of it
+/// inserted into a HaMS have been freed
+#[no_mangle]
+pub unsafe extern "C" fn probe_custom_new(
+    name: *const libc::c_char,
+    check_cb: CustomCheckCallback,
+    user_data: *mut c_void,
+    free_user_data_cb: CustomFreeCallback,
+) -> *mut Custom {
+    ffi_helpers::null_pointer_check!(name);
+
+    catch_panic!(
+        let name_str = unsafe { CStr::from_ptr(name) }.to_str()?;
+        info!("Creating CustomHealthProbe: {}", name_str);
+
+        let probe = Custom::new(name_str, check_cb, user_data, free_user_data_cb);
+        Ok(Box::into_raw(Box::new(probe)))
+    )
+}
+
+/// Free Custom Health Probe
+///
+/// # Safety
+/// Free the Custom Health Probe. The object must be created with HaMS library
+#[no_mangle]
+pub unsafe extern "C" fn probe_custom_free(ptr: *mut Custom) -> i32 {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_code!(
+        let probe = Box::from_raw(ptr);
+
+        info!("Releasing custom probe: {}", CString::from_raw(probe.name()).into_string().unwrap());
+        drop(probe);
+        Ok(1)
+    )
+}
+
+/// Return a boxed health probe from the custom health probe
+/// # Safety
+/// Return a boxed health probe from the custom health probe
+#[no_mangle]
+pub unsafe extern "C" fn probe_custom_boxed(ptr: *mut Custom) -> *mut BoxedHealthProbe<'static> {
+    ffi_helpers::null_pointer_check!(ptr);
+
+    catch_panic!(
+        let probe = (*ptr).clone();
+        let boxed_probe = BoxedHealthProbe::new(probe);
+
+        Ok(boxed_probe.into_raw() as *mut BoxedHealthProbe<'static>)
+    )
+}
+
 /// Test the FFI interfaces
 #[cfg(test)]
 mod tests {
@@ -745,6 +1438,41 @@ mod tests {
         let result = unsafe { hams_deregister_prometheus(my_hams) };
         assert_eq!(result, 1);
 
+        let name = std::ffi::CString::new("library").unwrap();
+        let result = unsafe {
+            hams_register_collector(
+                my_hams,
+                name.as_ptr(),
+                prometheus_callback,
+                prometheus_callback_free,
+                ptr::null(),
+            )
+        };
+        assert_eq!(result, 1);
+        let result = unsafe {
+            hams_register_collector(
+                my_hams,
+                name.as_ptr(),
+                prometheus_callback,
+                prometheus_callback_free,
+                ptr::null(),
+            )
+        };
+        assert_eq!(result, FFIEnum::AlreadyExists as i32);
+        assert_eq!(
+            ffi_error_to_result().err().unwrap().to_string(),
+            "FFI Error: Already exists: collector library"
+        );
+
+        assert_eq!(
+            unsafe { hams_deregister_collector(my_hams, name.as_ptr()) },
+            1
+        );
+        assert_eq!(
+            unsafe { hams_deregister_collector(my_hams, name.as_ptr()) },
+            FFIEnum::NotFound as i32
+        );
+
         let retval = unsafe { hams_free(my_hams) };
 
         assert_eq!(retval, 1);
@@ -766,6 +1494,337 @@ mod tests {
         assert_eq!(retval, 1);
     }
 
+    #[test]
+    fn hams_set_app_info() {
+        let c_library_name = std::ffi::CString::new("name").unwrap();
+        let c_address = std::ffi::CString::new("0.0.0.0:8079").unwrap();
+        let my_hams = unsafe { hams_new(c_library_name.as_ptr(), c_address.as_ptr()) };
+        assert_ne!(my_hams, ptr::null_mut());
+
+        let version = std::ffi::CString::new("1.2.3").unwrap();
+        let git_sha = std::ffi::CString::new("abc123").unwrap();
+        let key = std::ffi::CString::new("region").unwrap();
+        let value = std::ffi::CString::new("eu").unwrap();
+
+        assert_eq!(unsafe { hams_set_version(my_hams, version.as_ptr()) }, 1);
+        assert_eq!(
+            unsafe { hams_set_build_info(my_hams, git_sha.as_ptr(), ptr::null()) },
+            1
+        );
+        assert_eq!(
+            unsafe { hams_set_label(my_hams, key.as_ptr(), value.as_ptr()) },
+            1
+        );
+
+        {
+            let app_info = unsafe { &*my_hams }.app_info.read().unwrap();
+            assert_eq!(app_info.version, "1.2.3");
+            assert_eq!(app_info.git_sha.as_deref(), Some("abc123"));
+            assert_eq!(app_info.build_time, None);
+            assert_eq!(app_info.labels["region"], "eu");
+        }
+
+        assert_eq!(unsafe { hams_set_version(my_hams, ptr::null()) }, 0);
+        assert_eq!(
+            ffi_error_to_result().err().unwrap().to_string(),
+            "FFI Error: A null pointer was passed in where it wasn't expected"
+        );
+
+        assert_eq!(unsafe { hams_free(my_hams) }, 1);
+    }
+
+    #[test]
+    fn hams_set_log_level_target() {
+        let level = std::ffi::CString::new("trace").unwrap();
+        let target = std::ffi::CString::new("hams::ffi_test").unwrap();
+
+        assert_eq!(
+            unsafe { hams_set_log_level(level.as_ptr(), target.as_ptr()) },
+            1
+        );
+        assert_eq!(
+            ffi_log2::levels().targets["hams::ffi_test"],
+            log::LevelFilter::Trace
+        );
+
+        assert_eq!(
+            unsafe { hams_set_log_level(ptr::null(), target.as_ptr()) },
+            1
+        );
+        assert!(!ffi_log2::levels().targets.contains_key("hams::ffi_test"));
+
+        let invalid = std::ffi::CString::new("loud").unwrap();
+        assert_eq!(
+            unsafe { hams_set_log_level(invalid.as_ptr(), ptr::null()) },
+            FFIEnum::InvalidArgument as i32
+        );
+        assert!(ffi_error_to_result().is_err());
+        assert_eq!(
+            unsafe { hams_set_log_level(ptr::null(), ptr::null()) },
+            FFIEnum::InvalidArgument as i32
+        );
+    }
+
+    #[test]
+    fn hams_set_clear_maintenance() {
+        let c_library_name = std::ffi::CString::new("name").unwrap();
+        let c_address = std::ffi::CString::new("0.0.0.0:8079").unwrap();
+        let my_hams = unsafe { hams_new(c_library_name.as_ptr(), c_address.as_ptr()) };
+
+        let reason = std::ffi::CString::new("upgrade").unwrap();
+        assert_eq!(
+            unsafe { hams_set_maintenance(my_hams, reason.as_ptr(), 60) },
+            1
+        );
+        let maintenance = unsafe { &*my_hams }.maintenance().unwrap();
+        assert_eq!(maintenance.reason, "upgrade");
+        assert!(maintenance.until.is_some());
+
+        assert_eq!(
+            unsafe { hams_set_maintenance(my_hams, reason.as_ptr(), 0) },
+            1
+        );
+        assert!(unsafe { &*my_hams }.maintenance().unwrap().until.is_none());
+
+        assert_eq!(unsafe { hams_set_maintenance(my_hams, ptr::null(), 0) }, 1);
+        assert!(unsafe { &*my_hams }.maintenance().is_none());
+
+        assert_eq!(
+            unsafe { hams_set_maintenance(my_hams, reason.as_ptr(), u64::MAX) },
+            FFIEnum::InvalidArgument as i32
+        );
+        assert!(unsafe { &*my_hams }.maintenance().is_none());
+        assert_eq!(
+            unsafe { hams_set_maintenance(my_hams, reason.as_ptr(), 300_000_000_000) },
+            FFIEnum::InvalidArgument as i32
+        );
+        assert!(unsafe { &*my_hams }.maintenance().is_none());
+
+        assert_eq!(
+            unsafe { hams_set_maintenance(ptr::null_mut(), reason.as_ptr(), 0) },
+            0
+        );
+        assert_eq!(unsafe { hams_free(my_hams) }, 1);
+    }
+
+    #[test]
+    fn hams_probe_admin() {
+        let c_library_name = std::ffi::CString::new("name").unwrap();
+        let c_address = std::ffi::CString::new("0.0.0.0:8079").unwrap();
+        let my_hams = unsafe { hams_new(c_library_name.as_ptr(), c_address.as_ptr()) };
+
+        let probe_name = std::ffi::CString::new("admin").unwrap();
+        let probe = unsafe { probe_manual_new(probe_name.as_ptr(), true) };
+        assert_eq!(
+            unsafe { hams_ready_insert(my_hams, probe_manual_boxed(probe)) },
+            1
+        );
+
+        let ready = std::ffi::CString::new("ready").unwrap();
+        let missing = std::ffi::CString::new("missing").unwrap();
+        assert_eq!(
+            unsafe { hams_probe_set_valid(my_hams, ready.as_ptr(), probe_name.as_ptr(), false) },
+            1
+        );
+        assert_eq!(unsafe { probe_manual_check(probe) }, 0);
+        assert_eq!(
+            unsafe { hams_probe_check(my_hams, ready.as_ptr(), probe_name.as_ptr()) },
+            0
+        );
+        assert_eq!(
+            unsafe { hams_probe_check(my_hams, ready.as_ptr(), missing.as_ptr()) },
+            FFIEnum::NotFound as i32
+        );
+        assert_eq!(
+            ffi_error_to_result().err().unwrap().to_string(),
+            "FFI Error: Not found: probe missing in ready"
+        );
+        assert_eq!(
+            unsafe { hams_probe_set_valid(my_hams, missing.as_ptr(), probe_name.as_ptr(), true) },
+            FFIEnum::NotFound as i32
+        );
+
+        let list = unsafe { hams_probe_list(my_hams) };
+        assert!(!list.is_null());
+        let json: serde_json::Value =
+            serde_json::from_str(unsafe { CStr::from_ptr(list) }.to_str().unwrap()).unwrap();
+        assert_eq!(json[1]["name"], "ready");
+        assert_eq!(json[1]["probes"][0]["name"], "admin");
+        assert_eq!(json[1]["probes"][0]["type"], "manual");
+        assert_eq!(json[1]["probes"][0]["last"]["valid"], false);
+        assert_eq!(unsafe { hams_string_free(list) }, 1);
+
+        assert_eq!(unsafe { probe_manual_free(probe) }, 1);
+        assert_eq!(unsafe { hams_free(my_hams) }, 1);
+    }
+
+    #[test]
+    fn hams_inject_clear_fault() {
+        let c_library_name = std::ffi::CString::new("name").unwrap();
+        let c_address = std::ffi::CString::new("0.0.0.0:8079").unwrap();
+        let my_hams = unsafe { hams_new(c_library_name.as_ptr(), c_address.as_ptr()) };
+
+        let probe_name = std::ffi::CString::new("faulty").unwrap();
+        let probe = unsafe { probe_manual_new(probe_name.as_ptr(), true) };
+        assert_eq!(
+            unsafe { hams_alive_insert(my_hams, probe_manual_boxed(probe)) },
+            1
+        );
+
+        let alive = std::ffi::CString::new("alive").unwrap();
+        let fail =
+            std::ffi::CString::new(r#"{"fault": {"type": "fail"}, "ttl_secs": 60}"#).unwrap();
+        assert_eq!(
+            unsafe {
+                hams_inject_fault(my_hams, alive.as_ptr(), probe_name.as_ptr(), fail.as_ptr())
+            },
+            1
+        );
+        assert_eq!(
+            unsafe { hams_probe_check(my_hams, alive.as_ptr(), probe_name.as_ptr()) },
+            0
+        );
+        assert_eq!(unsafe { probe_manual_check(probe) }, 1);
+
+        assert_eq!(
+            unsafe { hams_inject_fault(my_hams, alive.as_ptr(), probe_name.as_ptr(), ptr::null()) },
+            1
+        );
+        assert_eq!(
+            unsafe { hams_probe_check(my_hams, alive.as_ptr(), probe_name.as_ptr()) },
+            1
+        );
+
+        let invalid = std::ffi::CString::new(r#"{"fault": {"type": "fail"}}"#).unwrap();
+        assert_eq!(
+            unsafe {
+                hams_inject_fault(
+                    my_hams,
+                    alive.as_ptr(),
+                    probe_name.as_ptr(),
+                    invalid.as_ptr(),
+                )
+            },
+            FFIEnum::InvalidArgument as i32
+        );
+        assert!(ffi_error_to_result()
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("ttl_secs"));
+
+        assert_eq!(unsafe { probe_manual_free(probe) }, 1);
+        assert_eq!(unsafe { hams_free(my_hams) }, 1);
+    }
+
+    #[test]
+    fn hams_metrics() {
+        let c_library_name = std::ffi::CString::new("name").unwrap();
+        let c_address = std::ffi::CString::new("0.0.0.0:8079").unwrap();
+        let my_hams = unsafe { hams_new(c_library_name.as_ptr(), c_address.as_ptr()) };
+
+        let name = std::ffi::CString::new("jobs_total").unwrap();
+        let help = std::ffi::CString::new("Jobs run").unwrap();
+        let queue = std::ffi::CString::new("queue").unwrap();
+        let fast = std::ffi::CString::new("fast").unwrap();
+        let labels = [queue.as_ptr()];
+        let values = [fast.as_ptr()];
+
+        let counter =
+            unsafe { hams_counter_new(my_hams, name.as_ptr(), help.as_ptr(), labels.as_ptr(), 1) };
+        assert!(!counter.is_null());
+        assert_eq!(
+            unsafe { hams_counter_inc(counter, values.as_ptr(), 1, 2.0) },
+            1
+        );
+        assert_eq!(
+            unsafe { hams_counter_inc(counter, ptr::null(), 0, 1.0) },
+            FFIEnum::InvalidArgument as i32
+        );
+        assert_eq!(
+            ffi_error_to_result().err().unwrap().to_string(),
+            "FFI Error: Invalid argument: Metric jobs_total has 1 labels but 0 values were given"
+        );
+
+        let name = std::ffi::CString::new("queue_items").unwrap();
+        let gauge = unsafe { hams_gauge_new(my_hams, name.as_ptr(), ptr::null(), ptr::null(), 0) };
+        assert_eq!(unsafe { hams_gauge_set(gauge, ptr::null(), 0, 5.0) }, 1);
+        assert_eq!(unsafe { hams_gauge_add(gauge, ptr::null(), 0, -2.0) }, 1);
+
+        let name = std::ffi::CString::new("latency_seconds").unwrap();
+        let buckets = [0.1, 1.0];
+        let histogram = unsafe {
+            hams_histogram_new(
+                my_hams,
+                name.as_ptr(),
+                help.as_ptr(),
+                ptr::null(),
+                0,
+                buckets.as_ptr(),
+                buckets.len(),
+            )
+        };
+        assert_eq!(
+            unsafe { hams_histogram_observe(histogram, ptr::null(), 0, 0.5) },
+            1
+        );
+        let trace_id = std::ffi::CString::new("trace_id").unwrap();
+        let trace = std::ffi::CString::new("4bf92f3577b34da6").unwrap();
+        let exemplar_names = [trace_id.as_ptr()];
+        let exemplar_values = [trace.as_ptr()];
+        assert_eq!(
+            unsafe {
+                hams_histogram_observe_exemplar(
+                    histogram,
+                    ptr::null(),
+                    0,
+                    0.05,
+                    exemplar_names.as_ptr(),
+                    exemplar_values.as_ptr(),
+                    1,
+                )
+            },
+            1
+        );
+        assert_eq!(
+            unsafe {
+                hams_counter_inc_exemplar(
+                    counter,
+                    values.as_ptr(),
+                    1,
+                    1.0,
+                    exemplar_names.as_ptr(),
+                    ptr::null(),
+                    1,
+                )
+            },
+            FFIEnum::InvalidArgument as i32
+        );
+
+        let rendered = unsafe { &*my_hams }
+            .registry
+            .render(metrics::Format::Prometheus);
+        assert!(rendered.contains("jobs_total{queue=\"fast\"} 2\n"));
+        assert!(rendered.contains("queue_items 3\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"1\"} 2\n"));
+        let rendered = unsafe { &*my_hams }
+            .registry
+            .render(metrics::Format::OpenMetrics);
+        assert!(rendered.contains(
+            "latency_seconds_bucket{le=\"0.1\"} 1 # {trace_id=\"4bf92f3577b34da6\"} 0.05 "
+        ));
+
+        let name = std::ffi::CString::new("hams_jobs_total").unwrap();
+        let reserved =
+            unsafe { hams_counter_new(my_hams, name.as_ptr(), ptr::null(), ptr::null(), 0) };
+        assert!(reserved.is_null());
+
+        assert_eq!(unsafe { hams_counter_free(counter) }, 1);
+        assert_eq!(unsafe { hams_gauge_free(gauge) }, 1);
+        assert_eq!(unsafe { hams_histogram_free(histogram) }, 1);
+        assert_eq!(unsafe { hams_free(my_hams) }, 1);
+    }
+
     #[test]
     fn null_init_name() {
         let c_address = std::ffi::CString::new("0.0.0.0:8079").unwrap();
@@ -890,6 +1949,75 @@ mod tests {
         );
     }
 
+    #[test]
+    fn probe_custom_check() {
+        use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+        static FREED: AtomicBool = AtomicBool::new(false);
+
+        extern "C" fn check(
+            user_data: *mut c_void,
+            message: *mut libc::c_char,
+            message_len: usize,
+        ) -> c_int {
+            let reply = unsafe { &*(user_data as *const AtomicI32) }.load(Ordering::Relaxed);
+            if reply == 0 {
+                let text = std::ffi::CString::new("pool exhausted").unwrap();
+                let length = text.as_bytes_with_nul().len().min(message_len);
+                unsafe { ptr::copy_nonoverlapping(text.as_ptr(), message, length) };
+            }
+            reply
+        }
+
+        extern "C" fn free(user_data: *mut c_void) {
+            drop(unsafe { Box::from_raw(user_data as *mut AtomicI32) });
+            FREED.store(true, Ordering::Relaxed);
+        }
+
+        let c_library_name = std::ffi::CString::new("name").unwrap();
+        let c_address = std::ffi::CString::new("0.0.0.0:8079").unwrap();
+        let my_hams = unsafe { hams_new(c_library_name.as_ptr(), c_address.as_ptr()) };
+
+        let state = Box::into_raw(Box::new(AtomicI32::new(1)));
+        let probe_name = std::ffi::CString::new("pool").unwrap();
+        let probe = unsafe {
+            probe_custom_new(probe_name.as_ptr(), check, state as *mut c_void, Some(free))
+        };
+        assert_ne!(probe, ptr::null_mut());
+        assert_eq!(
+            unsafe { hams_ready_insert(my_hams, probe_custom_boxed(probe)) },
+            1
+        );
+
+        let ready = std::ffi::CString::new("ready").unwrap();
+        assert_eq!(
+            unsafe { hams_probe_check(my_hams, ready.as_ptr(), probe_name.as_ptr()) },
+            1
+        );
+        unsafe { &*state }.store(0, Ordering::Relaxed);
+        assert_eq!(
+            unsafe { hams_probe_check(my_hams, ready.as_ptr(), probe_name.as_ptr()) },
+            0
+        );
+
+        // The message of the check is reported with its result
+        let result = futures::executor::block_on(
+            unsafe { &*my_hams }
+                .ready
+                .check_verbose(std::time::SystemTime::now()),
+        );
+        assert_eq!(
+            result.details.unwrap()[0].message.as_deref(),
+            Some("pool exhausted")
+        );
+
+        // The user data is freed with the last copy of the probe
+        assert_eq!(unsafe { probe_custom_free(probe) }, 1);
+        assert!(!FREED.load(Ordering::Relaxed));
+        assert_eq!(unsafe { hams_free(my_hams) }, 1);
+        assert!(FREED.load(Ordering::Relaxed));
+    }
+
     // Create Hams and insert + remove manual probe
     #[test]
     fn ffi_hams_start_stop() {
@@ -945,12 +2073,12 @@ mod tests {
         assert_ne!(my_hams2, ptr::null_mut());
 
         let retval = unsafe { hams_start(my_hams) };
-        assert_ne!(retval, 1);
+        assert_eq!(retval, FFIEnum::AlreadyRunning as i32);
 
         assert!(ffi_error_to_result().is_err(), "Error should be returned");
         assert_eq!(
             ffi_error_to_result().err().unwrap().to_string(),
-            "FFI Error: Panic: Start HaMS: AlreadyRunning"
+            "FFI Error: Service is already running and cannot be started again"
         );
         let retval = unsafe { hams_free(my_hams2) };
         assert_eq!(retval, 1);
@@ -963,6 +2091,69 @@ mod tests {
         assert_eq!(retval, 1);
     }
 
+    /// Create HaMS from configuration documents
+    #[test]
+    fn hams_new_from_document() {
+        let json =
+            std::ffi::CString::new(r#"{"name": "shop", "ready": {"retry_after": 5}}"#).unwrap();
+        let my_hams = unsafe { hams_new_from_json(json.as_ptr()) };
+        assert_ne!(my_hams, ptr::null_mut());
+        assert_eq!(unsafe { &*my_hams }.name, "shop");
+        assert_eq!(unsafe { hams_free(my_hams) }, 1);
+
+        let yaml = std::ffi::CString::new("name: shop\naddress: 127.0.0.1:8079\n").unwrap();
+        let my_hams = unsafe { hams_new_from_yaml(yaml.as_ptr()) };
+        assert_ne!(my_hams, ptr::null_mut());
+        assert_eq!(unsafe { hams_free(my_hams) }, 1);
+
+        let invalid = std::ffi::CString::new("alive:\n  fail_status: 200\n").unwrap();
+        assert_eq!(
+            unsafe { hams_new_from_yaml(invalid.as_ptr()) },
+            ptr::null_mut()
+        );
+        assert!(ffi_error_to_result()
+            .unwrap_err()
+            .to_string()
+            .contains("status must be between 400 and 599, got 200"));
+        assert_eq!(unsafe { hams_new_from_json(ptr::null()) }, ptr::null_mut());
+    }
+
+    /// Read the message of the last error through the C API
+    #[test]
+    fn last_error_message() {
+        hams_clear_last_error();
+        assert_eq!(hams_last_error_length(), 0);
+        let mut buffer = [0 as libc::c_char; 64];
+        assert_eq!(
+            unsafe { hams_last_error_message(buffer.as_mut_ptr(), buffer.len() as c_int) },
+            0
+        );
+
+        let invalid = std::ffi::CString::new("loud").unwrap();
+        assert_eq!(
+            unsafe { hams_set_log_level(invalid.as_ptr(), ptr::null()) },
+            FFIEnum::InvalidArgument as i32
+        );
+        let message = "Invalid argument: Invalid log level: loud";
+        assert_eq!(hams_last_error_length(), message.len() as c_int + 1);
+        assert_eq!(
+            unsafe { hams_last_error_message(buffer.as_mut_ptr(), 8) },
+            -1
+        );
+        assert_eq!(unsafe { hams_last_error_message(ptr::null_mut(), 64) }, -1);
+        assert_eq!(
+            unsafe { hams_last_error_message(buffer.as_mut_ptr(), buffer.len() as c_int) },
+            message.len() as c_int + 1
+        );
+        assert_eq!(
+            unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_str().unwrap(),
+            message
+        );
+
+        hams_clear_last_error();
+        assert_eq!(hams_last_error_length(), 0);
+    }
+
     // Test insert remove of manual probe into hams
     #[test]
     fn hams_insert_remove_manual() {
@@ -988,6 +2179,12 @@ mod tests {
         let retval = unsafe { hams_alive_insert(my_hams, probe_boxed) };
         assert_eq!(retval, 1);
 
+        // A probe with the same name is a distinct error from a NULL pointer
+        let retval = unsafe { hams_alive_insert(my_hams, probe_manual_boxed(my_probe)) };
+        assert_eq!(retval, FFIEnum::AlreadyExists as i32);
+        let retval = unsafe { hams_alive_insert(my_hams, ptr::null_mut()) };
+        assert_eq!(retval, FFIEnum::NullError as i32);
+
         // let check_response = unsafe { hams_alive_check(my_hams) };
         // assert_eq!(check_response, 1);
 
@@ -996,6 +2193,8 @@ mod tests {
 
         let retval = unsafe { hams_alive_remove(my_hams, probe_boxed) };
         assert_eq!(retval, 1);
+        let retval = unsafe { hams_alive_remove(my_hams, probe_manual_boxed(my_probe)) };
+        assert_eq!(retval, FFIEnum::NotFound as i32);
 
         // let check_response = unsafe { hams_alive_check(my_hams) };
         // assert_eq!(check_response, 1);
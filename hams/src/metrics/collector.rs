@@ -0,0 +1,422 @@
+//! Named collectors whose exposition text is served on `/hams/metrics`
+//!
+//! Each library in a process registers its own collector, so registering one does not replace another.
+//! Every collector is called on each scrape and its output validated and merged in name order, dropping
+//! the invalid families and those of native metrics with a logged error. Collectors are
+//! called on blocking threads, all at once, and each is given up on after the configured timeout so a
+//! slow collector cannot stall the webservice. A collector still running from an earlier scrape is not
+//! called again until it returns.
+//!
+//! A collector that times out, returns NULL or returns text that is not UTF-8 is counted as an error and
+//! fails the scrape, unless the last good output of collectors is configured to be served instead. The
+//! time each collector takes is reported so a slow one can be found.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::CStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use log::{error, warn};
+
+use super::{exposition::Exposition, openmetrics, Counter, Format, Gauge, Registry};
+use crate::{
+    error::HamsError,
+    hams::{config::MetricsConfig, PrometheusCallback},
+};
+
+/// Collectors by name with the metrics of their scrapes
+#[derive(Debug)]
+pub(crate) struct Collectors {
+    callbacks: Mutex<BTreeMap<String, PrometheusCallback>>,
+    /// Time a collector may take on a scrape
+    timeout: Duration,
+    /// Serve the last good output of a failing collector
+    serve_last_good: bool,
+    /// Latest output of each collector that succeeded
+    last_good: Mutex<BTreeMap<String, String>>,
+    /// Collectors being called, including those a scrape has given up on
+    running: Arc<Mutex<BTreeSet<String>>>,
+    registry: Registry,
+    duration: Gauge,
+    errors: Counter,
+    invalid: Counter,
+}
+
+impl Default for Collectors {
+    fn default() -> Self {
+        Collectors::new(&MetricsConfig::default())
+    }
+}
+
+impl Collectors {
+    pub(crate) fn new(config: &MetricsConfig) -> Self {
+        let registry = Registry::reserved();
+        // The names and labels are fixed and valid, so registering them cannot fail
+        let duration = registry
+            .gauge(
+                "hams_collector_duration_seconds",
+                "Time taken by the collector in the latest scrape",
+                &["collector"],
+            )
+            .expect("hams_collector_duration_seconds is valid");
+        let errors = registry
+            .counter(
+                "hams_collector_errors_total",
+                "Scrapes in which the collector returned no valid output",
+                &["collector"],
+            )
+            .expect("hams_collector_errors_total is valid");
+        let invalid = registry
+            .counter(
+                "hams_metrics_callback_errors_total",
+                "Invalid families and lines dropped from the output of the collector",
+                &["collector"],
+            )
+            .expect("hams_metrics_callback_errors_total is valid");
+        Collectors {
+            callbacks: Mutex::new(BTreeMap::new()),
+            timeout: config.timeout,
+            serve_last_good: config.serve_last_good,
+            last_good: Mutex::new(BTreeMap::new()),
+            running: Arc::new(Mutex::new(BTreeSet::new())),
+            registry,
+            duration,
+            errors,
+            invalid,
+        }
+    }
+
+    /// Add a collector. A collector already registered with the name is not replaced
+    pub(crate) fn register(
+        &self,
+        name: &str,
+        callback: PrometheusCallback,
+    ) -> Result<(), HamsError> {
+        let mut callbacks = self.callbacks.lock()?;
+        if callbacks.contains_key(name) {
+            return Err(HamsError::AlreadyExists(format!("collector {name}")));
+        }
+        callbacks.insert(name.to_string(), callback);
+        // Report the collector before its first scrape so its errors can be alerted on
+        self.errors.inc_by(&[name], 0.0)?;
+        self.invalid.inc_by(&[name], 0.0)?;
+        Ok(())
+    }
+
+    /// Remove a collector and its metrics
+    pub(crate) fn deregister(&self, name: &str) -> Result<(), HamsError> {
+        if self.callbacks.lock()?.remove(name).is_none() {
+            return Err(HamsError::NotFound(format!("collector {name}")));
+        }
+        self.last_good.lock()?.remove(name);
+        self.duration.remove(&[name]);
+        self.errors.remove(&[name]);
+        self.invalid.remove(&[name]);
+        Ok(())
+    }
+
+    /// Call every collector and merge their valid output, converted to the format where possible.
+    /// `native` tells whether a family name is used by a native metric
+    pub(crate) async fn collect(
+        &self,
+        format: Format,
+        native: impl Fn(&str) -> bool,
+    ) -> Result<String, HamsError> {
+        // Call without the lock so a collector may register or deregister collectors
+        let callbacks = self.callbacks.lock()?.clone();
+
+        let outputs = join_all(callbacks.into_iter().map(|(name, callback)| async move {
+            let started = Instant::now();
+            let output = self.call(&name, callback).await;
+            let _ = self.duration.set(&[&name], started.elapsed().as_secs_f64());
+            (name, output)
+        }))
+        .await;
+
+        let mut exposition = Exposition::default();
+        for (name, output) in outputs {
+            let output = match output {
+                Ok(output) => {
+                    self.last_good.lock()?.insert(name.clone(), output.clone());
+                    output
+                }
+                Err(e) => {
+                    let _ = self.errors.inc(&[&name]);
+                    match self.last_good(&name)? {
+                        Some(output) => {
+                            warn!("Collector {name} failed, serving its last good output: {e}");
+                            output
+                        }
+                        None => {
+                            error!("Collector {name} failed: {e}");
+                            return Err(HamsError::CollectorFailed(name));
+                        }
+                    }
+                }
+            };
+            let errors = exposition.add(&output, &native);
+            for e in &errors {
+                error!("Collector {name}: {e}");
+            }
+            if !errors.is_empty() {
+                let _ = self.invalid.inc_by(&[&name], errors.len() as f64);
+            }
+        }
+        let out = exposition.render();
+        match format {
+            Format::Prometheus => Ok(out),
+            Format::OpenMetrics => Ok(openmetrics::from_prometheus(&out)),
+        }
+    }
+
+    /// Render the durations and errors of the collectors
+    pub(crate) fn render(&self, format: Format) -> String {
+        self.registry.render(format)
+    }
+
+    /// The registry of the durations and errors of the collectors
+    pub(crate) fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// The output to serve for a failing collector, when configured
+    fn last_good(&self, name: &str) -> Result<Option<String>, HamsError> {
+        if !self.serve_last_good {
+            return Ok(None);
+        }
+        Ok(self.last_good.lock()?.get(name).cloned())
+    }
+
+    /// Call a collector on a blocking thread, giving up after the timeout
+    async fn call(&self, name: &str, callback: PrometheusCallback) -> Result<String, HamsError> {
+        if !self.running.lock()?.insert(name.to_string()) {
+            return Err(HamsError::Message(
+                "still running from an earlier scrape".to_string(),
+            ));
+        }
+        let running = self.running.clone();
+        let name = name.to_string();
+        let task = tokio::task::spawn_blocking(move || {
+            let output = call(&callback);
+            // The collector may be called again once it has returned, even if the scrape gave up on it
+            if let Ok(mut running) = running.lock() {
+                running.remove(&name);
+            }
+            output
+        });
+        match tokio::time::timeout(self.timeout, task).await {
+            Ok(output) => output?,
+            Err(_) => Err(HamsError::Message(format!(
+                "timed out after {:?}",
+                self.timeout
+            ))),
+        }
+    }
+}
+
+/// Call a collector and take its output, which the collector frees
+fn call(callback: &PrometheusCallback) -> Result<String, HamsError> {
+    let c_output = (callback.my_cb)(callback.state);
+    if c_output.is_null() {
+        return Err(HamsError::Message("no output".to_string()));
+    }
+    let output = unsafe { CStr::from_ptr(c_output) }
+        .to_str()
+        .map(str::to_string);
+    (callback.my_cb_free)(c_output);
+    Ok(output?)
+}
+
+#[cfg(test)]
+mod tests {
+    use libc::c_void;
+
+    use super::*;
+
+    extern "C" fn collector(ptr: *const c_void) -> *mut libc::c_char {
+        let state = unsafe { &*(ptr as *const String) };
+        std::ffi::CString::new(state.as_str()).unwrap().into_raw()
+    }
+
+    extern "C" fn failing(_ptr: *const c_void) -> *mut libc::c_char {
+        std::ptr::null_mut()
+    }
+
+    extern "C" fn not_utf8(_ptr: *const c_void) -> *mut libc::c_char {
+        std::ffi::CString::new(vec![0xff, 0xfe]).unwrap().into_raw()
+    }
+
+    /// Output the text until it is taken away
+    extern "C" fn flaky(ptr: *const c_void) -> *mut libc::c_char {
+        let state = unsafe { &*(ptr as *const Mutex<Option<String>>) };
+        match state.lock().unwrap().as_deref() {
+            Some(text) => std::ffi::CString::new(text).unwrap().into_raw(),
+            None => std::ptr::null_mut(),
+        }
+    }
+
+    extern "C" fn slow(ptr: *const c_void) -> *mut libc::c_char {
+        std::thread::sleep(Duration::from_millis(200));
+        collector(ptr)
+    }
+
+    extern "C" fn collector_free(ptr: *mut libc::c_char) {
+        if !ptr.is_null() {
+            drop(unsafe { std::ffi::CString::from_raw(ptr) });
+        }
+    }
+
+    fn callback<T>(
+        my_cb: extern "C" fn(*const c_void) -> *mut libc::c_char,
+        state: &T,
+    ) -> PrometheusCallback {
+        PrometheusCallback {
+            my_cb,
+            my_cb_free: collector_free,
+            state: state as *const T as *const c_void,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collectors() {
+        let app = "app_jobs 1".to_string();
+        let library = "library_queue 2\n".to_string();
+        let collectors = Collectors::default();
+
+        collectors
+            .register("app", callback(collector, &app))
+            .unwrap();
+        collectors
+            .register("library", callback(collector, &library))
+            .unwrap();
+        assert!(collectors
+            .register("app", callback(collector, &library))
+            .is_err());
+        assert_eq!(
+            collectors
+                .collect(Format::Prometheus, |_| false)
+                .await
+                .unwrap(),
+            "app_jobs 1\nlibrary_queue 2\n"
+        );
+
+        collectors
+            .register("broken", callback(failing, &app))
+            .unwrap();
+        assert!(matches!(
+            collectors.collect(Format::Prometheus, |_| false).await,
+            Err(HamsError::CollectorFailed(name)) if name == "broken"
+        ));
+        let rendered = collectors.render(Format::Prometheus);
+        assert!(rendered.contains("hams_collector_errors_total{collector=\"broken\"} 1\n"));
+        assert!(rendered.contains("hams_collector_errors_total{collector=\"app\"} 0\n"));
+        assert!(rendered.contains("hams_collector_duration_seconds{collector=\"library\"} "));
+
+        collectors.deregister("broken").unwrap();
+        assert!(matches!(
+            collectors.deregister("broken"),
+            Err(HamsError::NotFound(_))
+        ));
+        assert!(!collectors.render(Format::Prometheus).contains("broken"));
+
+        collectors
+            .register("garbled", callback(not_utf8, &app))
+            .unwrap();
+        assert!(collectors
+            .collect(Format::Prometheus, |_| false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_output() {
+        let app =
+            "# TYPE app_jobs counter\napp_jobs 1\napp_broken{a=\"1\" 2\nhams_up 1\n".to_string();
+        let library = "# TYPE app_jobs gauge\napp_jobs 2\nlibrary_queue 3\n".to_string();
+        let collectors = Collectors::default();
+        collectors
+            .register("app", callback(collector, &app))
+            .unwrap();
+        collectors
+            .register("library", callback(collector, &library))
+            .unwrap();
+
+        let native = |name: &str| name.starts_with("hams_");
+        assert_eq!(
+            collectors
+                .collect(Format::Prometheus, native)
+                .await
+                .unwrap(),
+            "# TYPE app_jobs counter\napp_jobs 1\nlibrary_queue 3\n"
+        );
+        let rendered = collectors.render(Format::Prometheus);
+        assert!(rendered.contains("hams_metrics_callback_errors_total{collector=\"app\"} 2\n"));
+        assert!(rendered.contains("hams_metrics_callback_errors_total{collector=\"library\"} 1\n"));
+        assert!(rendered.contains("hams_collector_errors_total{collector=\"app\"} 0\n"));
+    }
+
+    #[tokio::test]
+    async fn test_last_good() {
+        let text = Mutex::new(Some("app_jobs 1\n".to_string()));
+        let collectors = Collectors::new(&MetricsConfig {
+            serve_last_good: true,
+            ..Default::default()
+        });
+        collectors.register("app", callback(flaky, &text)).unwrap();
+
+        assert_eq!(
+            collectors
+                .collect(Format::Prometheus, |_| false)
+                .await
+                .unwrap(),
+            "app_jobs 1\n"
+        );
+        *text.lock().unwrap() = None;
+        assert_eq!(
+            collectors
+                .collect(Format::Prometheus, |_| false)
+                .await
+                .unwrap(),
+            "app_jobs 1\n"
+        );
+        assert!(collectors
+            .render(Format::Prometheus)
+            .contains("hams_collector_errors_total{collector=\"app\"} 1\n"));
+
+        let collectors = Collectors::default();
+        collectors.register("app", callback(flaky, &text)).unwrap();
+        assert!(collectors
+            .collect(Format::Prometheus, |_| false)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let app = "app_jobs 1\n".to_string();
+        let collectors = Collectors::new(&MetricsConfig {
+            timeout: Duration::from_millis(20),
+            ..Default::default()
+        });
+        collectors.register("app", callback(slow, &app)).unwrap();
+
+        let started = Instant::now();
+        assert!(collectors
+            .collect(Format::Prometheus, |_| false)
+            .await
+            .is_err());
+        assert!(started.elapsed() < Duration::from_millis(200));
+        // Not called again while the call the scrape gave up on is still running
+        assert!(collectors.running.lock().unwrap().contains("app"));
+        assert!(collectors
+            .collect(Format::Prometheus, |_| false)
+            .await
+            .is_err());
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert!(collectors.running.lock().unwrap().is_empty());
+    }
+}
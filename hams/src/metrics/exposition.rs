@@ -0,0 +1,491 @@
+//! Validation and merging of the Prometheus text returned by collectors
+//!
+//! The text of a collector is parsed rather than copied into the scrape, as a single malformed line, a
+//! repeated HELP or TYPE line or a bucket whose `le` is not a number makes Prometheus reject the whole
+//! scrape. A family with such an error is dropped and the rest of the text is served. Families are written
+//! with their HELP and TYPE first and their samples together, and a repeated series is dropped. Families
+//! of the same name and type from different collectors are merged, while a family with the name of a
+//! native metric or of a family of another type is dropped. Comments other than HELP and TYPE and blank
+//! lines are dropped.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::{escape_label_value, format_value, valid_name};
+
+/// Label names and values of a sample
+type Labels = Vec<(String, String)>;
+
+/// Types of the families of the Prometheus text format
+const KINDS: [&str; 5] = ["counter", "gauge", "histogram", "summary", "untyped"];
+
+/// Families merged from the text of the collectors
+#[derive(Debug, Default)]
+pub(crate) struct Exposition {
+    families: Vec<Family>,
+    /// Position of each family by name
+    index: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Family {
+    name: String,
+    /// Help text, escaped as in the text format
+    help: Option<String>,
+    /// Type, None when there was no TYPE line
+    kind: Option<String>,
+    samples: Vec<Sample>,
+}
+
+impl Family {
+    fn new(name: &str) -> Self {
+        Family {
+            name: name.to_string(),
+            help: None,
+            kind: None,
+            samples: Vec::new(),
+        }
+    }
+
+    fn kind(&self) -> &str {
+        self.kind.as_deref().unwrap_or("untyped")
+    }
+
+    /// Add a sample unless the family has a sample of the same series
+    fn add(&mut self, sample: Sample) {
+        if !self.samples.iter().any(|other| other.same_series(&sample)) {
+            self.samples.push(sample);
+        }
+    }
+
+    /// Whether a sample of the name belongs to the family
+    fn has_sample(&self, name: &str) -> bool {
+        let suffix = name.strip_prefix(self.name.as_str());
+        matches!(
+            (self.kind(), suffix),
+            ("histogram", Some("_bucket" | "_sum" | "_count"))
+                | ("summary", Some("_sum" | "_count"))
+                | (_, Some(""))
+        )
+    }
+
+    fn render(&self, out: &mut String) {
+        if let Some(help) = &self.help {
+            out.push_str(&format!("# HELP {} {help}\n", self.name));
+        }
+        if let Some(kind) = &self.kind {
+            out.push_str(&format!("# TYPE {} {kind}\n", self.name));
+        }
+        for sample in &self.samples {
+            sample.render(out);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Sample {
+    name: String,
+    labels: Labels,
+    value: f64,
+    /// Milliseconds since the epoch
+    timestamp: Option<i64>,
+}
+
+impl Sample {
+    fn same_series(&self, other: &Sample) -> bool {
+        let sorted = |labels: &[(String, String)]| {
+            let mut labels = labels.to_vec();
+            labels.sort();
+            labels
+        };
+        self.name == other.name && sorted(&self.labels) == sorted(&other.labels)
+    }
+
+    fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str(&self.name);
+        if !self.labels.is_empty() {
+            let labels: Vec<String> = self
+                .labels
+                .iter()
+                .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+                .collect();
+            out.push_str(&format!("{{{}}}", labels.join(",")));
+        }
+        out.push(' ');
+        out.push_str(&format_value(self.value));
+        if let Some(timestamp) = self.timestamp {
+            out.push_str(&format!(" {timestamp}"));
+        }
+        out.push('\n');
+    }
+}
+
+impl Exposition {
+    /// Merge the text of a collector, returning why each family or line that was dropped is invalid.
+    /// `native` tells whether a family name is used by a native metric
+    pub(crate) fn add(&mut self, text: &str, native: impl Fn(&str) -> bool) -> Vec<String> {
+        let (families, mut errors) = parse(text);
+        for family in families {
+            if native(&family.name) {
+                errors.push(format!("family {} is a native metric", family.name));
+                continue;
+            }
+            let Some(&position) = self.index.get(&family.name) else {
+                self.index.insert(family.name.clone(), self.families.len());
+                self.families.push(family);
+                continue;
+            };
+            let merged = &mut self.families[position];
+            // A family without a TYPE line takes the type of the family it is merged with
+            if merged.kind.is_some() && family.kind.is_some() && merged.kind() != family.kind() {
+                errors.push(format!(
+                    "family {} is a {} in another collector",
+                    family.name,
+                    merged.kind()
+                ));
+                continue;
+            }
+            merged.help = merged.help.take().or(family.help);
+            merged.kind = merged.kind.take().or(family.kind);
+            for sample in family.samples {
+                merged.add(sample);
+            }
+        }
+        errors
+    }
+
+    /// Render the families in the Prometheus text format
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        for family in &self.families {
+            family.render(&mut out);
+        }
+        out
+    }
+}
+
+/// Parse Prometheus text into its valid families, with why each family or line left out is invalid
+fn parse(text: &str) -> (Vec<Family>, Vec<String>) {
+    let mut families: Vec<Family> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+    // The first error of each invalid family
+    let mut invalid: BTreeMap<String, String> = BTreeMap::new();
+    let mut errors = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let result = if let Some(comment) = line.strip_prefix('#') {
+            let mut parts = comment.trim_start().splitn(3, char::is_whitespace);
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(keyword @ ("HELP" | "TYPE")), Some(name), text) if valid_name(name, true) => {
+                    let position = *index.entry(name.to_string()).or_insert_with(|| {
+                        families.push(Family::new(name));
+                        families.len() - 1
+                    });
+                    let family = &mut families[position];
+                    let text = text.unwrap_or_default().trim();
+                    let result = match keyword {
+                        "HELP" => set_help(family, text),
+                        _ => set_kind(family, text),
+                    };
+                    result.map_err(|e| (Some(name.to_string()), e))
+                }
+                (Some("HELP" | "TYPE"), name, _) => Err((
+                    None,
+                    format!("invalid metric name {:?}", name.unwrap_or_default()),
+                )),
+                _ => Ok(()),
+            }
+        } else {
+            parse_sample(line).and_then(|sample| {
+                let family = families
+                    .iter()
+                    .position(|family| family.kind.is_some() && family.has_sample(&sample.name))
+                    .or_else(|| index.get(&sample.name).copied());
+                let position = family.unwrap_or_else(|| {
+                    index.insert(sample.name.clone(), families.len());
+                    families.push(Family::new(&sample.name));
+                    families.len() - 1
+                });
+                let family = &mut families[position];
+                check_sample(family, &sample).map_err(|e| (Some(family.name.clone()), e))?;
+                family.add(sample);
+                Ok(())
+            })
+        };
+
+        match result {
+            Ok(()) => {}
+            Err((Some(family), e)) => {
+                invalid
+                    .entry(family)
+                    .or_insert_with(|| format!("line {}: {e}", number + 1));
+            }
+            Err((None, e)) => errors.push(format!("line {}: {e}", number + 1)),
+        }
+    }
+
+    errors.extend(
+        invalid
+            .iter()
+            .map(|(family, e)| format!("family {family} dropped, {e}")),
+    );
+    families.retain(|family| !invalid.contains_key(&family.name));
+    (families, errors)
+}
+
+fn set_help(family: &mut Family, help: &str) -> Result<(), String> {
+    let mut chars = help.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' && !matches!(chars.next(), Some('\\' | 'n')) {
+            return Err("invalid escape in HELP".to_string());
+        }
+    }
+    match &family.help {
+        Some(existing) if existing != help => Err("repeated HELP".to_string()),
+        _ => {
+            family.help = Some(help.to_string());
+            Ok(())
+        }
+    }
+}
+
+fn set_kind(family: &mut Family, kind: &str) -> Result<(), String> {
+    if !KINDS.contains(&kind) {
+        return Err(format!("unknown TYPE {kind:?}"));
+    }
+    match &family.kind {
+        Some(existing) if existing != kind => Err("repeated TYPE".to_string()),
+        _ if family.kind.is_none() && !family.samples.is_empty() => {
+            Err("TYPE after samples".to_string())
+        }
+        _ => {
+            family.kind = Some(kind.to_string());
+            Ok(())
+        }
+    }
+}
+
+/// Check the labels a histogram or summary needs are numbers
+fn check_sample(family: &Family, sample: &Sample) -> Result<(), String> {
+    let required = match family.kind() {
+        "histogram" if sample.name.ends_with("_bucket") => "le",
+        "summary" if sample.name == family.name => "quantile",
+        _ => return Ok(()),
+    };
+    match sample.label(required).map(str::parse::<f64>) {
+        Some(Ok(bound)) if !bound.is_nan() => Ok(()),
+        Some(_) => Err(format!("{required} is not a number")),
+        None => Err(format!("{required} is missing")),
+    }
+}
+
+/// A parse error with the name of the family it makes invalid, when it is known
+type ParseError = (Option<String>, String);
+
+fn parse_sample(line: &str) -> Result<Sample, ParseError> {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .unwrap_or(line.len());
+    let name = &line[..name_end];
+    if !valid_name(name, true) {
+        return Err((None, format!("invalid metric name {name:?}")));
+    }
+    let error = |e: String| (Some(name.to_string()), e);
+
+    let mut rest = line[name_end..].trim_start();
+    let mut labels = Vec::new();
+    if rest.starts_with('{') {
+        (labels, rest) = parse_labels(rest).map_err(error)?;
+    }
+    let mut parts = rest.split_whitespace();
+    let value = parts
+        .next()
+        .ok_or_else(|| error("missing value".to_string()))?;
+    let value = value
+        .parse::<f64>()
+        .map_err(|_| error(format!("invalid value {value:?}")))?;
+    let timestamp = match parts.next() {
+        Some(timestamp) => Some(
+            timestamp
+                .parse::<i64>()
+                .map_err(|_| error(format!("invalid timestamp {timestamp:?}")))?,
+        ),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return Err(error("text after the timestamp".to_string()));
+    }
+    Ok(Sample {
+        name: name.to_string(),
+        labels,
+        value,
+        timestamp,
+    })
+}
+
+/// Parse the label set at the start of the text, returning the labels and the text after it
+fn parse_labels(text: &str) -> Result<(Labels, &str), String> {
+    let mut labels = Labels::new();
+    let mut rest = text[1..].trim_start();
+    loop {
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok((labels, after));
+        }
+        let name_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .ok_or_else(|| "unterminated labels".to_string())?;
+        let name = &rest[..name_end];
+        if !valid_name(name, false) {
+            return Err(format!("invalid label name {name:?}"));
+        }
+        if labels.iter().any(|(label, _)| label == name) {
+            return Err(format!("repeated label {name}"));
+        }
+        rest = rest[name_end..]
+            .trim_start()
+            .strip_prefix('=')
+            .map(str::trim_start)
+            .and_then(|rest| rest.strip_prefix('"'))
+            .ok_or_else(|| format!("label {name} has no quoted value"))?;
+
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next() {
+                None => return Err("unterminated label value".to_string()),
+                Some((end, '"')) => break end,
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, '\\')) => value.push('\\'),
+                    Some((_, '"')) => value.push('"'),
+                    Some((_, 'n')) => value.push('\n'),
+                    _ => return Err(format!("invalid escape in label {name}")),
+                },
+                Some((_, c)) => value.push(c),
+            }
+        };
+        labels.push((name.to_string(), value));
+
+        rest = rest[end + 1..].trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+        } else if !rest.starts_with('}') {
+            return Err("expected , or } after a label".to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalise(text: &str) -> (String, Vec<String>) {
+        let mut exposition = Exposition::default();
+        let errors = exposition.add(text, |_| false);
+        (exposition.render(), errors)
+    }
+
+    #[test]
+    fn test_normalise() {
+        let text = r#"
+# A comment
+jobs_total{queue="a",} 3 1700000000123
+# HELP jobs_total Jobs run\n on "queues"
+# HELP jobs_total Jobs run\n on "queues"
+# TYPE latency_seconds histogram
+latency_seconds_bucket{le="0.1"} 1
+latency_seconds_bucket{le="+Inf"} 2
+latency_seconds_sum 0.3
+latency_seconds_count 2
+jobs_total { queue = "b\"c" } 4
+jobs_total{queue="a"} 5
+"#;
+        let (rendered, errors) = normalise(text);
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(
+            rendered,
+            r#"# HELP jobs_total Jobs run\n on "queues"
+jobs_total{queue="a"} 3 1700000000123
+jobs_total{queue="b\"c"} 4
+# TYPE latency_seconds histogram
+latency_seconds_bucket{le="0.1"} 1
+latency_seconds_bucket{le="+Inf"} 2
+latency_seconds_sum 0.3
+latency_seconds_count 2
+"#
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let text = r#"# TYPE good gauge
+good 1
+# TYPE twice counter
+# TYPE twice gauge
+twice 1
+# TYPE buckets histogram
+buckets_bucket{le="NaN"} 1
+quantiles{quantile="0.5"} 1
+# TYPE summary summary
+summary{quantile="half"} 1
+repeated{a="1",a="2"} 1
+unquoted{a=1} 1
+bad_value abc
+extra 1 2 3
+9starts_with_digit 1
+# TYPE odd histogram2
+"#;
+        let (rendered, errors) = normalise(text);
+        assert_eq!(
+            rendered,
+            "# TYPE good gauge\ngood 1\nquantiles{quantile=\"0.5\"} 1\n"
+        );
+        assert_eq!(
+            errors,
+            [
+                "line 15: invalid metric name \"9starts_with_digit\"",
+                "family bad_value dropped, line 13: invalid value \"abc\"",
+                "family buckets dropped, line 7: le is not a number",
+                "family extra dropped, line 14: text after the timestamp",
+                "family odd dropped, line 16: unknown TYPE \"histogram2\"",
+                "family repeated dropped, line 11: repeated label a",
+                "family summary dropped, line 10: quantile is not a number",
+                "family twice dropped, line 4: repeated TYPE",
+                "family unquoted dropped, line 12: label a has no quoted value",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut exposition = Exposition::default();
+        let native = |name: &str| name == "app_jobs_total";
+        assert!(exposition
+            .add("# TYPE queue gauge\nqueue{q=\"a\"} 1\n", native)
+            .is_empty());
+        assert_eq!(
+            exposition.add(
+                "# HELP queue Items\nqueue{q=\"a\"} 9\nqueue{q=\"b\"} 2\napp_jobs_total 3\n",
+                native
+            ),
+            ["family app_jobs_total is a native metric"]
+        );
+        assert_eq!(
+            exposition.add("# TYPE queue counter\nqueue 1\n", native),
+            ["family queue is a gauge in another collector"]
+        );
+        assert_eq!(
+            exposition.render(),
+            "# HELP queue Items\n# TYPE queue gauge\nqueue{q=\"a\"} 1\nqueue{q=\"b\"} 2\n"
+        );
+    }
+}
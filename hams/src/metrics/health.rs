@@ -0,0 +1,175 @@
+//! Metrics HaMS exports about its own checks, probes and build
+//!
+//! Every check HaMS runs is recorded, not only those whose result kubelet keeps, so flapping probes can
+//! be alerted on from `hams_probe_transitions_total`. The metrics are rendered on `/hams/metrics` after
+//! the metrics registered by the application.
+
+use std::sync::Arc;
+
+use super::{Counter, Format, Gauge, Histogram, Registry};
+use crate::hams::info::AppInfo;
+
+/// Metrics of the checks, probes and build of HaMS
+#[derive(Debug, Clone)]
+pub(crate) struct HealthMetrics {
+    registry: Arc<Registry>,
+    check_up: Gauge,
+    probe_up: Gauge,
+    probe_duration: Histogram,
+    probe_transitions: Counter,
+    build_info: Gauge,
+}
+
+impl Default for HealthMetrics {
+    fn default() -> Self {
+        let registry = Registry::reserved();
+        // The names and labels are fixed and valid, so registering them cannot fail
+        let check_up = registry
+            .gauge(
+                "hams_check_up",
+                "Whether the latest check of every probe passed",
+                &["check"],
+            )
+            .expect("hams_check_up is valid");
+        let probe_up = registry
+            .gauge(
+                "hams_probe_up",
+                "Whether the latest check of the probe passed",
+                &["check", "probe"],
+            )
+            .expect("hams_probe_up is valid");
+        let probe_duration = registry
+            .histogram(
+                "hams_probe_check_duration_seconds",
+                "Time taken to check the probe",
+                &["check", "probe"],
+                None,
+            )
+            .expect("hams_probe_check_duration_seconds is valid");
+        let probe_transitions = registry
+            .counter(
+                "hams_probe_transitions_total",
+                "Changes of the probe between passing and failing",
+                &["check", "probe"],
+            )
+            .expect("hams_probe_transitions_total is valid");
+        let build_info = registry
+            .gauge(
+                "hams_build_info",
+                "Build details of the application and HaMS, always 1",
+                &["name", "version", "git_sha", "hams_version"],
+            )
+            .expect("hams_build_info is valid");
+        HealthMetrics {
+            registry: Arc::new(registry),
+            check_up,
+            probe_up,
+            probe_duration,
+            probe_transitions,
+            build_info,
+        }
+    }
+}
+
+/// Value of an up gauge
+fn up(valid: bool) -> f64 {
+    if valid {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+impl HealthMetrics {
+    /// Record the status of a check of every probe
+    pub(crate) fn check(&self, check: &str, valid: bool) {
+        let _ = self.check_up.set(&[check], up(valid));
+    }
+
+    /// Record the result of a probe and whether it changed from the previous result
+    pub(crate) fn probe(&self, check: &str, probe: &str, valid: bool, changed: bool) {
+        let _ = self.probe_up.set(&[check, probe], up(valid));
+        // Counting zero reports the series from the first check so increases can be alerted on
+        let _ = self.probe_transitions.inc_by(&[check, probe], up(changed));
+    }
+
+    /// Record how long checking a probe took
+    pub(crate) fn probe_duration(&self, check: &str, probe: &str, seconds: f64) {
+        let _ = self.probe_duration.observe(&[check, probe], seconds);
+    }
+
+    /// Drop the series of a probe that has been removed
+    pub(crate) fn forget_probe(&self, check: &str, probe: &str) {
+        self.probe_up.remove(&[check, probe]);
+        self.probe_duration.remove(&[check, probe]);
+        self.probe_transitions.remove(&[check, probe]);
+    }
+
+    /// Render the metrics with the current build details
+    pub(crate) fn render(
+        &self,
+        name: &str,
+        app_info: &AppInfo,
+        hams_version: &str,
+        format: Format,
+    ) -> String {
+        self.refresh(name, app_info, hams_version).render(format)
+    }
+
+    /// Set the current build details and get the registry of the metrics
+    pub(crate) fn refresh(&self, name: &str, app_info: &AppInfo, hams_version: &str) -> &Registry {
+        // The version can be set at any time, so only the current build details are reported
+        self.build_info.clear();
+        let _ = self.build_info.set(
+            &[
+                name,
+                &app_info.version,
+                app_info.git_sha.as_deref().unwrap_or_default(),
+                hams_version,
+            ],
+            1.0,
+        );
+        &self.registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_metrics() {
+        let metrics = HealthMetrics::default();
+        metrics.check("ready", false);
+        metrics.probe("ready", "database", true, false);
+        metrics.probe("ready", "database", false, true);
+        metrics.probe_duration("ready", "database", 0.02);
+
+        let app_info = AppInfo {
+            version: "1.2.3".to_string(),
+            git_sha: Some("abc123".to_string()),
+            ..Default::default()
+        };
+        let rendered = metrics.render("app", &app_info, "0.1.0", Format::Prometheus);
+        assert!(rendered.contains("hams_check_up{check=\"ready\"} 0\n"));
+        assert!(rendered.contains("hams_probe_up{check=\"ready\",probe=\"database\"} 0\n"));
+        assert!(rendered
+            .contains("hams_probe_transitions_total{check=\"ready\",probe=\"database\"} 1\n"));
+        assert!(rendered.contains(
+            "hams_probe_check_duration_seconds_bucket{check=\"ready\",probe=\"database\",le=\"0.025\"} 1\n"
+        ));
+        assert!(rendered.contains(
+            "hams_build_info{name=\"app\",version=\"1.2.3\",git_sha=\"abc123\",hams_version=\"0.1.0\"} 1\n"
+        ));
+
+        let app_info = AppInfo {
+            version: "1.2.4".to_string(),
+            ..Default::default()
+        };
+        metrics.forget_probe("ready", "database");
+        let rendered = metrics.render("app", &app_info, "0.1.0", Format::Prometheus);
+        assert!(!rendered.contains("probe=\"database\""));
+        assert!(!rendered.contains("version=\"1.2.3\""));
+        assert!(rendered.contains("version=\"1.2.4\",git_sha=\"\""));
+    }
+}
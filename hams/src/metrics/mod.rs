@@ -0,0 +1,761 @@
+//! Registry of metrics created by the application and rendered on `/hams/metrics`
+//!
+//! Counters, gauges and histograms are registered with their label names and updated with label values
+//! from Rust, C or hamsrs. Rendering writes the HELP and TYPE lines and escapes help text and label
+//! values, which is easy to get wrong when the exposition text is built by hand in the metrics callback.
+//! Metrics are rendered in the Prometheus text format or in OpenMetrics, which adds the `_created`
+//! series and the exemplars recorded with counters and histograms.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::error::HamsError;
+
+pub(crate) mod collector;
+pub(crate) mod exposition;
+pub(crate) mod health;
+pub(crate) mod openmetrics;
+#[cfg(feature = "otel")]
+pub(crate) mod otlp;
+pub(crate) mod process;
+pub(crate) mod statsd;
+
+use openmetrics::format_timestamp;
+
+/// Upper bounds of histogram buckets when none are given, the same as the Prometheus clients
+pub const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Prefix of the metrics of HaMS itself, which applications cannot register
+pub(crate) const RESERVED_PREFIX: &str = "hams_";
+
+/// Longest combined length of the label names and values of an exemplar allowed by OpenMetrics
+const EXEMPLAR_MAX_LENGTH: usize = 128;
+
+/// Exposition format of the metrics
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Format {
+    /// Prometheus text format 0.0.4
+    Prometheus,
+    /// OpenMetrics text format 1.0.0
+    OpenMetrics,
+}
+
+impl Format {
+    /// Content type of a reply in this format
+    pub(crate) fn content_type(&self) -> &'static str {
+        match self {
+            Format::Prometheus => "text/plain; version=0.0.4; charset=utf-8",
+            Format::OpenMetrics => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        }
+    }
+}
+
+/// Type of a metric family
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricKind {
+    /// Name of the type on the TYPE line
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        }
+    }
+}
+
+/// Current value of a series
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    /// Value of a counter or gauge
+    Number(f64),
+    /// Cumulative count per bucket with the sum and count of observations
+    Histogram {
+        buckets: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+/// An observation linked to a series, eg the trace it was recorded in
+#[derive(Debug, Clone, PartialEq)]
+struct Exemplar {
+    labels: Vec<(String, String)>,
+    value: f64,
+    time: SystemTime,
+}
+
+impl Exemplar {
+    fn new(labels: &[(&str, &str)], value: f64) -> Result<Self, HamsError> {
+        if let Some((name, _)) = labels.iter().find(|(name, _)| !valid_name(name, false)) {
+            return Err(HamsError::InvalidArgument(format!(
+                "Invalid exemplar label name: {name}"
+            )));
+        }
+        let length: usize = labels
+            .iter()
+            .map(|(name, value)| name.chars().count() + value.chars().count())
+            .sum();
+        if length > EXEMPLAR_MAX_LENGTH {
+            return Err(HamsError::InvalidArgument(format!(
+                "Exemplar labels are {length} characters, more than {EXEMPLAR_MAX_LENGTH}"
+            )));
+        }
+        Ok(Exemplar {
+            labels: labels
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            value,
+            time: SystemTime::now(),
+        })
+    }
+
+    /// The exemplar as written after a sample, `# {labels} value timestamp`
+    fn render(&self) -> String {
+        let labels = self
+            .labels
+            .iter()
+            .map(|(name, value)| format!("{name}=\"{}\"", escape_label_value(value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            " # {{{labels}}} {} {}",
+            format_value(self.value),
+            format_timestamp(self.time)
+        )
+    }
+}
+
+/// A series with when it was created and its latest exemplars
+#[derive(Debug, Clone, PartialEq)]
+struct Series {
+    value: Value,
+    created: SystemTime,
+    /// Latest exemplar of a counter at 0, or of each histogram bucket by index with +Inf last
+    exemplars: BTreeMap<usize, Exemplar>,
+}
+
+/// A metric with its series by label values
+#[derive(Debug)]
+struct Family {
+    name: String,
+    help: String,
+    kind: MetricKind,
+    labels: Vec<String>,
+    /// Upper bounds of the buckets of a histogram, empty otherwise
+    buckets: Vec<f64>,
+    series: Mutex<BTreeMap<Vec<String>, Series>>,
+}
+
+impl Family {
+    fn zero(&self) -> Series {
+        let value = match self.kind {
+            MetricKind::Histogram => Value::Histogram {
+                buckets: vec![0; self.buckets.len()],
+                sum: 0.0,
+                count: 0,
+            },
+            _ => Value::Number(0.0),
+        };
+        Series {
+            value,
+            created: SystemTime::now(),
+            exemplars: BTreeMap::new(),
+        }
+    }
+
+    /// Update the series with the label values, creating it at zero if needed
+    fn update<F: FnOnce(&mut Series)>(&self, values: &[&str], update: F) -> Result<(), HamsError> {
+        if values.len() != self.labels.len() {
+            return Err(HamsError::InvalidArgument(format!(
+                "Metric {} has {} labels but {} values were given",
+                self.name,
+                self.labels.len(),
+                values.len()
+            )));
+        }
+        let mut series = self.series.lock().map_err(|_e| HamsError::PoisonError)?;
+        let value = series
+            .entry(values.iter().map(|value| value.to_string()).collect())
+            .or_insert_with(|| self.zero());
+        update(value);
+        Ok(())
+    }
+
+    /// Drop the series with the label values, eg for a probe that no longer exists
+    fn remove(&self, values: &[&str]) {
+        if let Ok(mut series) = self.series.lock() {
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            series.remove(&values);
+        }
+    }
+
+    /// Drop every series
+    fn clear(&self) {
+        if let Ok(mut series) = self.series.lock() {
+            series.clear();
+        }
+    }
+
+    /// Write the family in the exposition format
+    fn render(&self, out: &mut String, format: Format) {
+        let Ok(series) = self.series.lock() else {
+            return;
+        };
+        let open_metrics = format == Format::OpenMetrics;
+        // OpenMetrics names a counter family without the suffix of its samples
+        let family = match (format, self.kind) {
+            (Format::OpenMetrics, MetricKind::Counter) => {
+                self.name.strip_suffix("_total").unwrap_or(&self.name)
+            }
+            _ => &self.name,
+        };
+        let help = match format {
+            Format::Prometheus => escape_help(&self.help),
+            Format::OpenMetrics => escape_label_value(&self.help),
+        };
+        let _ = writeln!(out, "# HELP {family} {help}");
+        let _ = writeln!(out, "# TYPE {family} {}", self.kind.as_str());
+        for (values, series) in series.iter() {
+            let labels = self.label_pairs(values);
+            let exemplar = |index: usize| match format {
+                Format::OpenMetrics => series
+                    .exemplars
+                    .get(&index)
+                    .map(Exemplar::render)
+                    .unwrap_or_default(),
+                Format::Prometheus => String::new(),
+            };
+            match &series.value {
+                Value::Number(number) => {
+                    let name = match (format, self.kind) {
+                        (Format::OpenMetrics, MetricKind::Counter) => format!("{family}_total"),
+                        _ => family.to_string(),
+                    };
+                    let _ = writeln!(
+                        out,
+                        "{name}{} {}{}",
+                        braces(&labels),
+                        format_value(*number),
+                        exemplar(0)
+                    );
+                }
+                Value::Histogram {
+                    buckets,
+                    sum,
+                    count,
+                } => {
+                    for (index, (bucket, bound)) in buckets.iter().zip(&self.buckets).enumerate() {
+                        let le = format!("le=\"{}\"", format_value(*bound));
+                        let _ = writeln!(
+                            out,
+                            "{family}_bucket{} {bucket}{}",
+                            braces(&with_label(&labels, &le)),
+                            exemplar(index)
+                        );
+                    }
+                    let _ = writeln!(
+                        out,
+                        "{family}_bucket{} {count}{}",
+                        braces(&with_label(&labels, "le=\"+Inf\"")),
+                        exemplar(buckets.len())
+                    );
+                    let _ = writeln!(
+                        out,
+                        "{family}_sum{} {}",
+                        braces(&labels),
+                        format_value(*sum)
+                    );
+                    let _ = writeln!(out, "{family}_count{} {count}", braces(&labels));
+                }
+            }
+            if open_metrics && self.kind != MetricKind::Gauge {
+                let _ = writeln!(
+                    out,
+                    "{family}_created{} {}",
+                    braces(&labels),
+                    format_timestamp(series.created)
+                );
+            }
+        }
+    }
+
+    /// Label names and escaped values joined as `name="value",...`
+    fn label_pairs(&self, values: &[String]) -> String {
+        self.labels
+            .iter()
+            .zip(values)
+            .map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value)))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// A counter which only goes up, eg requests handled
+#[derive(Debug, Clone)]
+pub struct Counter(Arc<Family>);
+
+impl Counter {
+    /// Add one to the series with the label values
+    pub fn inc(&self, labels: &[&str]) -> Result<(), HamsError> {
+        self.inc_by(labels, 1.0)
+    }
+
+    /// Add to the series with the label values. The amount cannot be negative
+    pub fn inc_by(&self, labels: &[&str], amount: f64) -> Result<(), HamsError> {
+        self.increase(labels, amount, None)
+    }
+
+    /// Add to the series with the label values and keep an exemplar of the increase, eg with the
+    /// `trace_id` label of the trace it was made in. Exemplars are served with OpenMetrics
+    pub fn inc_by_with_exemplar(
+        &self,
+        labels: &[&str],
+        amount: f64,
+        exemplar: &[(&str, &str)],
+    ) -> Result<(), HamsError> {
+        self.increase(labels, amount, Some(Exemplar::new(exemplar, amount)?))
+    }
+
+    fn increase(
+        &self,
+        labels: &[&str],
+        amount: f64,
+        exemplar: Option<Exemplar>,
+    ) -> Result<(), HamsError> {
+        if amount.is_nan() || amount < 0.0 {
+            return Err(HamsError::InvalidArgument(format!(
+                "Counter {} cannot be increased by {amount}",
+                self.0.name
+            )));
+        }
+        self.0.update(labels, |series| {
+            if let Value::Number(number) = &mut series.value {
+                *number += amount;
+            }
+            if let Some(exemplar) = exemplar {
+                series.exemplars.insert(0, exemplar);
+            }
+        })
+    }
+
+    /// Set the series to a total counted outside the registry, eg CPU time read from the kernel
+    pub(crate) fn set_total(&self, labels: &[&str], total: f64) -> Result<(), HamsError> {
+        self.0
+            .update(labels, |series| series.value = Value::Number(total))
+    }
+
+    /// Drop the series with the label values
+    pub(crate) fn remove(&self, labels: &[&str]) {
+        self.0.remove(labels)
+    }
+}
+
+/// A gauge which goes up and down, eg items in a queue
+#[derive(Debug, Clone)]
+pub struct Gauge(Arc<Family>);
+
+impl Gauge {
+    /// Set the series with the label values
+    pub fn set(&self, labels: &[&str], value: f64) -> Result<(), HamsError> {
+        self.0
+            .update(labels, |series| series.value = Value::Number(value))
+    }
+
+    /// Add to the series with the label values. A negative amount subtracts
+    pub fn add(&self, labels: &[&str], amount: f64) -> Result<(), HamsError> {
+        self.0.update(labels, |series| {
+            if let Value::Number(number) = &mut series.value {
+                *number += amount;
+            }
+        })
+    }
+
+    /// Drop the series with the label values
+    pub(crate) fn remove(&self, labels: &[&str]) {
+        self.0.remove(labels)
+    }
+
+    /// Drop every series
+    pub(crate) fn clear(&self) {
+        self.0.clear()
+    }
+}
+
+/// A histogram counting observations in buckets, eg request durations
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<Family>);
+
+impl Histogram {
+    /// Record an observation in the series with the label values
+    pub fn observe(&self, labels: &[&str], observation: f64) -> Result<(), HamsError> {
+        self.record(labels, observation, None)
+    }
+
+    /// Record an observation in the series with the label values and keep it as the exemplar of its
+    /// bucket, eg with the `trace_id` label of the trace it was made in. Exemplars are served with
+    /// OpenMetrics
+    pub fn observe_with_exemplar(
+        &self,
+        labels: &[&str],
+        observation: f64,
+        exemplar: &[(&str, &str)],
+    ) -> Result<(), HamsError> {
+        self.record(
+            labels,
+            observation,
+            Some(Exemplar::new(exemplar, observation)?),
+        )
+    }
+
+    fn record(
+        &self,
+        labels: &[&str],
+        observation: f64,
+        exemplar: Option<Exemplar>,
+    ) -> Result<(), HamsError> {
+        if observation.is_nan() {
+            return Err(HamsError::InvalidArgument(format!(
+                "Histogram {} cannot observe NaN",
+                self.0.name
+            )));
+        }
+        let bounds = &self.0.buckets;
+        self.0.update(labels, |series| {
+            if let Some(exemplar) = exemplar {
+                // The exemplar belongs to the first bucket the observation falls in
+                let index = bounds
+                    .iter()
+                    .position(|bound| observation <= *bound)
+                    .unwrap_or(bounds.len());
+                series.exemplars.insert(index, exemplar);
+            }
+            if let Value::Histogram {
+                buckets,
+                sum,
+                count,
+            } = &mut series.value
+            {
+                for (bucket, bound) in buckets.iter_mut().zip(bounds) {
+                    if observation <= *bound {
+                        *bucket += 1;
+                    }
+                }
+                *sum += observation;
+                *count += 1;
+            }
+        })
+    }
+
+    /// Drop the series with the label values
+    pub(crate) fn remove(&self, labels: &[&str]) {
+        self.0.remove(labels)
+    }
+}
+
+/// The metrics registered with a HaMS
+#[derive(Debug, Default)]
+pub struct Registry {
+    families: Mutex<BTreeMap<String, Arc<Family>>>,
+    /// Whether the reserved prefix may be registered, only for the metrics of HaMS itself
+    reserved: bool,
+}
+
+impl Registry {
+    /// A registry for the metrics of HaMS itself, which use the reserved prefix
+    pub(crate) fn reserved() -> Self {
+        Registry {
+            reserved: true,
+            ..Default::default()
+        }
+    }
+
+    /// Register a counter, or get the counter already registered with the same name and labels
+    pub fn counter(&self, name: &str, help: &str, labels: &[&str]) -> Result<Counter, HamsError> {
+        self.check_prefix(name)?;
+        self.register(name, help, MetricKind::Counter, labels, &[])
+            .map(Counter)
+    }
+
+    /// Register a gauge, or get the gauge already registered with the same name and labels
+    pub fn gauge(&self, name: &str, help: &str, labels: &[&str]) -> Result<Gauge, HamsError> {
+        self.check_prefix(name)?;
+        self.register(name, help, MetricKind::Gauge, labels, &[])
+            .map(Gauge)
+    }
+
+    /// Register a histogram, or get the histogram already registered with the same name, labels and
+    /// buckets. The buckets are the increasing upper bounds, [DEFAULT_BUCKETS] when not given
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        buckets: Option<&[f64]>,
+    ) -> Result<Histogram, HamsError> {
+        self.check_prefix(name)?;
+        let buckets = buckets.unwrap_or(&DEFAULT_BUCKETS);
+        if buckets.iter().any(|bound| !bound.is_finite())
+            || buckets.windows(2).any(|pair| pair[0] >= pair[1])
+        {
+            return Err(HamsError::InvalidArgument(format!(
+                "Buckets of histogram {name} must be finite and increasing"
+            )));
+        }
+        self.register(name, help, MetricKind::Histogram, labels, buckets)
+            .map(Histogram)
+    }
+
+    fn check_prefix(&self, name: &str) -> Result<(), HamsError> {
+        if !self.reserved && name.starts_with(RESERVED_PREFIX) {
+            return Err(HamsError::InvalidArgument(format!(
+                "Metric names starting with {RESERVED_PREFIX} are reserved for HaMS: {name}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Register a family
+    fn register(
+        &self,
+        name: &str,
+        help: &str,
+        kind: MetricKind,
+        labels: &[&str],
+        buckets: &[f64],
+    ) -> Result<Arc<Family>, HamsError> {
+        if !valid_name(name, true) {
+            return Err(HamsError::InvalidArgument(format!(
+                "Invalid metric name: {name}"
+            )));
+        }
+        if let Some(label) = labels.iter().find(|label| {
+            !valid_name(label, false)
+                || label.starts_with("__")
+                || (kind == MetricKind::Histogram && **label == "le")
+        }) {
+            return Err(HamsError::InvalidArgument(format!(
+                "Invalid label name for metric {name}: {label}"
+            )));
+        }
+
+        let mut families = self.families.lock().map_err(|_e| HamsError::PoisonError)?;
+        if let Some(family) = families.get(name) {
+            if family.kind == kind && family.labels == labels && family.buckets == buckets {
+                return Ok(family.clone());
+            }
+            return Err(HamsError::AlreadyExists(format!(
+                "Metric {name} is already registered as a {} with other labels or buckets",
+                family.kind.as_str()
+            )));
+        }
+
+        let family = Arc::new(Family {
+            name: name.to_string(),
+            help: help.to_string(),
+            kind,
+            labels: labels.iter().map(|label| label.to_string()).collect(),
+            buckets: buckets.to_vec(),
+            series: Mutex::new(BTreeMap::new()),
+        });
+        // A metric without labels has a single series, reported from the start
+        if labels.is_empty() {
+            family.update(&[], |_| {})?;
+        }
+        families.insert(name.to_string(), family.clone());
+        Ok(family)
+    }
+
+    /// Whether a family is registered with the name
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.families
+            .lock()
+            .is_ok_and(|families| families.contains_key(name))
+    }
+
+    /// Render every metric in the exposition format
+    pub(crate) fn render(&self, format: Format) -> String {
+        let mut out = String::new();
+        if let Ok(families) = self.families.lock() {
+            for family in families.values() {
+                family.render(&mut out, format);
+            }
+        }
+        out
+    }
+}
+
+/// Metric names match `[a-zA-Z_:][a-zA-Z0-9_:]*`, label names the same without colons
+fn valid_name(name: &str, colons: bool) -> bool {
+    let allowed = |c: char| c.is_ascii_alphabetic() || c == '_' || (colons && c == ':');
+    let mut chars = name.chars();
+    chars.next().is_some_and(allowed) && chars.all(|c| allowed(c) || c.is_ascii_digit())
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Format a sample value, with the special values spelled as Prometheus expects
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn braces(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{labels}}}")
+    }
+}
+
+fn with_label(labels: &str, label: &str) -> String {
+    if labels.is_empty() {
+        label.to_string()
+    } else {
+        format!("{labels},{label}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let registry = Registry::default();
+        let requests = registry
+            .counter("requests_total", "Requests handled", &["method"])
+            .unwrap();
+        requests.inc(&["GET"]).unwrap();
+        requests.inc_by(&["POST"], 2.5).unwrap();
+        let queue = registry
+            .gauge("queue_items", "Items waiting\nto be handled", &[])
+            .unwrap();
+        queue.set(&[], 3.0).unwrap();
+        queue.add(&[], -1.0).unwrap();
+        let latency = registry
+            .histogram("latency_seconds", "Latency", &["path"], Some(&[0.1, 1.0]))
+            .unwrap();
+        latency.observe(&["/a\"b\\c"], 0.5).unwrap();
+        latency.observe(&["/a\"b\\c"], 2.0).unwrap();
+
+        assert_eq!(
+            registry.render(Format::Prometheus),
+            r#"# HELP latency_seconds Latency
+# TYPE latency_seconds histogram
+latency_seconds_bucket{path="/a\"b\\c",le="0.1"} 0
+latency_seconds_bucket{path="/a\"b\\c",le="1"} 1
+latency_seconds_bucket{path="/a\"b\\c",le="+Inf"} 2
+latency_seconds_sum{path="/a\"b\\c"} 2.5
+latency_seconds_count{path="/a\"b\\c"} 2
+# HELP queue_items Items waiting\nto be handled
+# TYPE queue_items gauge
+queue_items 2
+# HELP requests_total Requests handled
+# TYPE requests_total counter
+requests_total{method="GET"} 1
+requests_total{method="POST"} 2.5
+"#
+        );
+    }
+
+    #[test]
+    fn test_render_open_metrics() {
+        let registry = Registry::default();
+        let requests = registry
+            .counter("requests_total", "Requests \"handled\"", &[])
+            .unwrap();
+        requests
+            .inc_by_with_exemplar(&[], 1.0, &[("trace_id", "abc")])
+            .unwrap();
+        let latency = registry
+            .histogram("latency_seconds", "Latency", &[], Some(&[0.1, 1.0]))
+            .unwrap();
+        latency
+            .observe_with_exemplar(&[], 0.5, &[("trace_id", "def")])
+            .unwrap();
+
+        let rendered = registry.render(Format::OpenMetrics);
+        assert!(rendered
+            .contains("# HELP requests Requests \\\"handled\\\"\n# TYPE requests counter\n"));
+        assert!(rendered.contains("requests_total 1 # {trace_id=\"abc\"} 1 "));
+        assert!(rendered.contains("\nrequests_created "));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"0.1\"} 0\n"));
+        assert!(rendered.contains("latency_seconds_bucket{le=\"1\"} 1 # {trace_id=\"def\"} 0.5 "));
+        assert!(rendered.contains("\nlatency_seconds_created "));
+        assert!(!registry.render(Format::Prometheus).contains(" # {"));
+
+        assert!(requests
+            .inc_by_with_exemplar(&[], 1.0, &[("trace-id", "abc")])
+            .is_err());
+        assert!(requests
+            .inc_by_with_exemplar(&[], 1.0, &[("trace_id", &"a".repeat(121))])
+            .is_err());
+    }
+
+    #[test]
+    fn test_register() {
+        let registry = Registry::default();
+        let counter = registry.counter("jobs_total", "Jobs", &["queue"]).unwrap();
+        let again = registry.counter("jobs_total", "Jobs", &["queue"]).unwrap();
+        again.inc(&["fast"]).unwrap();
+        counter.inc(&["fast"]).unwrap();
+        assert!(registry
+            .render(Format::Prometheus)
+            .contains("jobs_total{queue=\"fast\"} 2\n"));
+
+        assert!(registry.gauge("jobs_total", "Jobs", &["queue"]).is_err());
+        assert!(registry.counter("jobs_total", "Jobs", &[]).is_err());
+        assert!(registry.counter("hams_jobs_total", "Jobs", &[]).is_err());
+        assert!(registry.counter("9jobs", "Jobs", &[]).is_err());
+        assert!(registry.counter("jobs", "Jobs", &["a-b"]).is_err());
+        assert!(registry.counter("jobs", "Jobs", &["__name"]).is_err());
+        assert!(registry.histogram("jobs", "Jobs", &["le"], None).is_err());
+        assert!(registry
+            .histogram("jobs", "Jobs", &[], Some(&[1.0, 0.5]))
+            .is_err());
+        assert!(registry
+            .histogram("jobs", "Jobs", &[], Some(&[f64::INFINITY]))
+            .is_err());
+        assert!(registry.counter("name:space_total", "Jobs", &[]).is_ok());
+
+        assert!(counter.inc(&[]).is_err());
+        assert!(counter.inc_by(&["fast"], -1.0).is_err());
+        assert!(counter.inc_by(&["fast"], f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value(1.0), "1");
+        assert_eq!(format_value(0.25), "0.25");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+        assert_eq!(format_value(f64::NAN), "NaN");
+    }
+}
@@ -0,0 +1,159 @@
+//! OpenMetrics output for text that is only available in the Prometheus format
+//!
+//! The collectors and the request metrics produce Prometheus text. When OpenMetrics is requested their
+//! output is converted where the formats differ: counter families lose their `_total` suffix and their
+//! samples gain it, `untyped` becomes `unknown`, millisecond timestamps become seconds and quotes in help
+//! text are escaped. Comments other than HELP and TYPE, blank lines and any `# EOF` are dropped, as
+//! OpenMetrics allows neither, and lines that cannot be parsed are passed through unchanged.
+
+use std::{
+    collections::HashSet,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Marker that ends an OpenMetrics exposition
+pub(crate) const EOF: &str = "# EOF\n";
+
+/// Seconds since the Unix epoch with millisecond precision, as OpenMetrics timestamps are written
+pub(crate) fn format_timestamp(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:03}", since.as_secs(), since.subsec_millis())
+}
+
+/// Convert Prometheus text to OpenMetrics, without the `# EOF`
+pub(crate) fn from_prometheus(text: &str) -> String {
+    // HELP usually comes before TYPE, so find the counters first
+    let counters: HashSet<&str> = text
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.strip_prefix("# TYPE ")?.split_whitespace();
+            let name = parts.next()?;
+            (parts.next() == Some("counter")).then_some(name)
+        })
+        .collect();
+    let family = |name: &str| -> String {
+        if counters.contains(name) {
+            name.strip_suffix("_total").unwrap_or(name).to_string()
+        } else {
+            name.to_string()
+        }
+    };
+
+    let mut out = String::new();
+    for line in text.lines().map(str::trim_end) {
+        if line.is_empty() {
+            continue;
+        }
+        let converted = if let Some(rest) = line.strip_prefix("# HELP ") {
+            let (name, help) = rest.split_once(' ').unwrap_or((rest, ""));
+            format!("# HELP {} {}", family(name), escape_quotes(help))
+        } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+            let (name, kind) = rest.split_once(' ').unwrap_or((rest, "unknown"));
+            let kind = match kind.trim() {
+                "untyped" => "unknown",
+                kind => kind,
+            };
+            format!("# TYPE {} {kind}", family(name))
+        } else if line.starts_with('#') {
+            continue;
+        } else {
+            sample(line, &counters)
+        };
+        out.push_str(&converted);
+        out.push('\n');
+    }
+    out
+}
+
+/// Convert a sample line, renaming counter samples and converting the timestamp
+fn sample(line: &str, counters: &HashSet<&str>) -> String {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .unwrap_or(line.len());
+    let (name, rest) = line.split_at(name_end);
+    let Some(labels_end) = labels_end(rest) else {
+        return line.to_string();
+    };
+    let (labels, rest) = rest.split_at(labels_end);
+
+    let name = if counters.contains(name) && !name.ends_with("_total") {
+        format!("{name}_total")
+    } else {
+        name.to_string()
+    };
+    let mut parts = rest.split_whitespace();
+    let value = parts.next().unwrap_or_default();
+    match parts.next().map(str::parse::<i64>) {
+        Some(Ok(millis)) => {
+            let seconds = millis.div_euclid(1000);
+            let millis = millis.rem_euclid(1000);
+            format!("{name}{labels} {value} {seconds}.{millis:03}")
+        }
+        _ => format!("{name}{labels}{rest}"),
+    }
+}
+
+/// Length of the label set at the start of the text, zero when there is none, None when unterminated
+fn labels_end(text: &str) -> Option<usize> {
+    if !text.starts_with('{') {
+        return Some(0);
+    }
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '}' if !quoted => return Some(index + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Escape the quotes that Prometheus leaves unescaped in help text
+fn escape_quotes(help: &str) -> String {
+    help.replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_from_prometheus() {
+        let text = r#"# HELP jobs_total Jobs "run"
+# TYPE jobs_total counter
+jobs_total{queue="a} b"} 3 1700000000123
+# A comment
+
+# HELP cache_hits Hits
+# TYPE cache_hits counter
+cache_hits 4
+# TYPE temperature untyped
+temperature{room="kitchen"} 21.5
+# EOF
+"#;
+        assert_eq!(
+            from_prometheus(text),
+            r#"# HELP jobs Jobs \"run\"
+# TYPE jobs counter
+jobs_total{queue="a} b"} 3 1700000000.123
+# HELP cache_hits Hits
+# TYPE cache_hits counter
+cache_hits_total 4
+# TYPE temperature unknown
+temperature{room="kitchen"} 21.5
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_005);
+        assert_eq!(format_timestamp(time), "1700000000.005");
+    }
+}
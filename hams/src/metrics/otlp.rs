@@ -0,0 +1,184 @@
+//! OTLP encoding of the native metrics
+//!
+//! Metrics are encoded as the JSON of the OTLP/HTTP protocol so they can be sent to an OpenTelemetry
+//! collector without a protobuf or gRPC stack. Counters become cumulative monotonic sums, gauges become
+//! gauges and histograms become explicit bucket histograms, whose bucket counts OTLP holds per bucket
+//! rather than cumulatively. Exemplar labels named `trace_id` and `span_id` link the exemplar to its trace.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value as Json};
+
+use super::{Exemplar, Family, MetricKind, Registry, Value};
+
+/// Cumulative aggregation temporality of OTLP
+const CUMULATIVE: u8 = 2;
+
+/// The metrics of the registry at the time as OTLP metrics
+pub(crate) fn metrics(registry: &Registry, time: SystemTime) -> Vec<Json> {
+    let Ok(families) = registry.families.lock() else {
+        return Vec::new();
+    };
+    families
+        .values()
+        .filter_map(|family| family_metric(family, time))
+        .collect()
+}
+
+/// Nanoseconds since the epoch as OTLP writes 64 bit integers in JSON
+pub(crate) fn nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+/// Attributes with string values in OTLP
+pub(crate) fn attributes<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<Json> {
+    pairs
+        .into_iter()
+        .map(|(key, value)| json!({"key": key, "value": {"stringValue": value}}))
+        .collect()
+}
+
+/// A family as an OTLP metric, None when it has no series
+fn family_metric(family: &Family, time: SystemTime) -> Option<Json> {
+    let series = family.series.lock().ok()?;
+    if series.is_empty() {
+        return None;
+    }
+
+    let points: Vec<Json> = series
+        .iter()
+        .map(|(values, series)| {
+            let mut point = json!({
+                "attributes": attributes(
+                    family.labels.iter().map(String::as_str).zip(values.iter().map(String::as_str))
+                ),
+                "startTimeUnixNano": nanos(series.created),
+                "timeUnixNano": nanos(time),
+            });
+            let exemplars: Vec<Json> = series.exemplars.values().map(exemplar).collect();
+            if !exemplars.is_empty() {
+                point["exemplars"] = Json::from(exemplars);
+            }
+            match &series.value {
+                Value::Number(number) => point["asDouble"] = json!(number),
+                Value::Histogram {
+                    buckets,
+                    sum,
+                    count,
+                } => {
+                    // The buckets are cumulative, OTLP counts each bucket and the overflow on its own
+                    let mut previous = 0;
+                    let mut counts: Vec<String> = buckets
+                        .iter()
+                        .map(|bucket| {
+                            let in_bucket = bucket - previous;
+                            previous = *bucket;
+                            in_bucket.to_string()
+                        })
+                        .collect();
+                    counts.push((count - previous).to_string());
+                    point["count"] = json!(count.to_string());
+                    point["sum"] = json!(sum);
+                    point["bucketCounts"] = json!(counts);
+                    point["explicitBounds"] = json!(family.buckets);
+                }
+            }
+            point
+        })
+        .collect();
+
+    let data = match family.kind {
+        MetricKind::Counter => json!({
+            "sum": {
+                "dataPoints": points,
+                "aggregationTemporality": CUMULATIVE,
+                "isMonotonic": true,
+            }
+        }),
+        MetricKind::Gauge => json!({"gauge": {"dataPoints": points}}),
+        MetricKind::Histogram => json!({
+            "histogram": {
+                "dataPoints": points,
+                "aggregationTemporality": CUMULATIVE,
+            }
+        }),
+    };
+    let mut metric = json!({"name": family.name, "description": family.help});
+    if let (Some(metric), Json::Object(data)) = (metric.as_object_mut(), data) {
+        metric.extend(data);
+    }
+    Some(metric)
+}
+
+/// An exemplar in OTLP, with the trace and span ids taken from its labels
+fn exemplar(exemplar: &Exemplar) -> Json {
+    let mut encoded = json!({
+        "timeUnixNano": nanos(exemplar.time),
+        "asDouble": exemplar.value,
+        "filteredAttributes": attributes(
+            exemplar
+                .labels
+                .iter()
+                .filter(|(name, _)| name != "trace_id" && name != "span_id")
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+        ),
+    });
+    for (name, value) in &exemplar.labels {
+        match name.as_str() {
+            "trace_id" => encoded["traceId"] = json!(value),
+            "span_id" => encoded["spanId"] = json!(value),
+            _ => {}
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics() {
+        let registry = Registry::default();
+        registry
+            .counter("jobs_total", "Jobs", &["queue"])
+            .unwrap()
+            .inc_by_with_exemplar(&["fast"], 2.0, &[("trace_id", "4bf92f35"), ("user", "a")])
+            .unwrap();
+        registry.gauge("queue_items", "Items", &[]).unwrap();
+        let latency = registry
+            .histogram("latency_seconds", "Latency", &[], Some(&[0.1, 1.0]))
+            .unwrap();
+        latency.observe(&[], 0.05).unwrap();
+        latency.observe(&[], 0.5).unwrap();
+        latency.observe(&[], 5.0).unwrap();
+
+        let metrics = metrics(&registry, SystemTime::now());
+        assert_eq!(metrics.len(), 3);
+
+        // Families are in name order
+        let sum = &metrics[0]["sum"];
+        assert_eq!(sum["isMonotonic"], true);
+        let point = &sum["dataPoints"][0];
+        assert_eq!(point["asDouble"], 2.0);
+        assert_eq!(point["attributes"][0]["key"], "queue");
+        assert_eq!(point["attributes"][0]["value"]["stringValue"], "fast");
+        assert_eq!(point["exemplars"][0]["traceId"], "4bf92f35");
+        assert_eq!(
+            point["exemplars"][0]["filteredAttributes"][0]["key"],
+            "user"
+        );
+
+        let histogram = &metrics[1];
+        assert_eq!(histogram["name"], "latency_seconds");
+        let point = &histogram["histogram"]["dataPoints"][0];
+        assert_eq!(point["count"], "3");
+        assert_eq!(point["bucketCounts"], json!(["1", "1", "1"]));
+        assert_eq!(point["explicitBounds"], json!([0.1, 1.0]));
+
+        assert_eq!(metrics[2]["gauge"]["dataPoints"][0]["asDouble"], 0.0);
+    }
+}
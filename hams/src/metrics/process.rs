@@ -0,0 +1,198 @@
+//! Standard process metrics read from `/proc/self`
+//!
+//! HaMS runs inside the process of the application, so it can export the `process_*` metrics of the
+//! Prometheus clients for C, Kotlin and TypeScript services without any host code. The values are read
+//! on each scrape. Only Linux has `/proc`; elsewhere, or when it cannot be read, nothing is reported.
+
+use std::sync::Arc;
+
+use super::{Counter, Format, Gauge, Registry};
+
+/// Process metrics with the registry they are rendered from
+#[derive(Debug, Clone)]
+pub(crate) struct ProcessMetrics {
+    registry: Arc<Registry>,
+    cpu_seconds: Counter,
+    resident_memory: Gauge,
+    virtual_memory: Gauge,
+    open_fds: Gauge,
+    max_fds: Gauge,
+    threads: Gauge,
+    start_time: Gauge,
+}
+
+impl Default for ProcessMetrics {
+    fn default() -> Self {
+        let registry = Registry::default();
+        // The names are fixed and valid, so registering them cannot fail
+        let gauge = |name: &str, help: &str| {
+            registry
+                .gauge(name, help, &[])
+                .unwrap_or_else(|_| panic!("{name} is valid"))
+        };
+        let resident_memory = gauge(
+            "process_resident_memory_bytes",
+            "Resident memory size in bytes",
+        );
+        let virtual_memory = gauge(
+            "process_virtual_memory_bytes",
+            "Virtual memory size in bytes",
+        );
+        let open_fds = gauge("process_open_fds", "Number of open file descriptors");
+        let max_fds = gauge("process_max_fds", "Maximum number of open file descriptors");
+        let threads = gauge("process_threads", "Number of OS threads in the process");
+        let start_time = gauge(
+            "process_start_time_seconds",
+            "Start time of the process since unix epoch in seconds",
+        );
+        let cpu_seconds = registry
+            .counter(
+                "process_cpu_seconds_total",
+                "Total user and system CPU time spent in seconds",
+                &[],
+            )
+            .expect("process_cpu_seconds_total is valid");
+        ProcessMetrics {
+            registry: Arc::new(registry),
+            cpu_seconds,
+            resident_memory,
+            virtual_memory,
+            open_fds,
+            max_fds,
+            threads,
+            start_time,
+        }
+    }
+}
+
+impl ProcessMetrics {
+    /// Read the process and render its metrics, or nothing when `/proc` cannot be read
+    pub(crate) fn render(&self, format: Format) -> String {
+        self.refresh()
+            .map(|registry| registry.render(format))
+            .unwrap_or_default()
+    }
+
+    /// Read the process and get the registry of its metrics, None when `/proc` cannot be read
+    pub(crate) fn refresh(&self) -> Option<&Registry> {
+        self.read().map(|()| self.registry.as_ref())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read(&self) -> Option<()> {
+        let stat = parse_stat(&std::fs::read_to_string("/proc/self/stat").ok()?)?;
+        let boot_time = parse_boot_time(&std::fs::read_to_string("/proc/stat").ok()?)?;
+        // Clock ticks per second and bytes per page, as used by /proc
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f64;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as f64;
+        if ticks <= 0.0 || page_size <= 0.0 {
+            return None;
+        }
+
+        let _ = self
+            .cpu_seconds
+            .set_total(&[], (stat.utime + stat.stime) as f64 / ticks);
+        let _ = self.resident_memory.set(&[], stat.rss as f64 * page_size);
+        let _ = self.virtual_memory.set(&[], stat.vsize as f64);
+        let _ = self.threads.set(&[], stat.threads as f64);
+        let _ = self
+            .start_time
+            .set(&[], boot_time as f64 + stat.start_time as f64 / ticks);
+        if let Ok(fds) = std::fs::read_dir("/proc/self/fd") {
+            let _ = self.open_fds.set(&[], fds.count() as f64);
+        }
+        let mut limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } == 0 {
+            let _ = self.max_fds.set(&[], limit.rlim_cur as f64);
+        }
+        Some(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read(&self) -> Option<()> {
+        None
+    }
+}
+
+/// Fields of `/proc/self/stat` reported as metrics
+#[derive(Debug, PartialEq)]
+struct Stat {
+    /// User CPU time in clock ticks
+    utime: u64,
+    /// System CPU time in clock ticks
+    stime: u64,
+    threads: u64,
+    /// Clock ticks after boot when the process started
+    start_time: u64,
+    /// Virtual memory in bytes
+    vsize: u64,
+    /// Resident memory in pages
+    rss: u64,
+}
+
+/// Parse `/proc/self/stat`. The command name may hold spaces and parentheses, so the fields are counted
+/// from the last closing parenthesis, after which the third field, the state, follows
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_stat(stat: &str) -> Option<Stat> {
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let field = |number: usize| fields.get(number - 3)?.parse::<u64>().ok();
+    Some(Stat {
+        utime: field(14)?,
+        stime: field(15)?,
+        threads: field(20)?,
+        start_time: field(22)?,
+        vsize: field(23)?,
+        rss: field(24)?,
+    })
+}
+
+/// Parse the boot time in seconds since the epoch from `/proc/stat`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_boot_time(stat: &str) -> Option<u64> {
+    stat.lines()
+        .find_map(|line| line.strip_prefix("btime "))
+        .and_then(|btime| btime.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = "4242 (my (odd) app) S 1 4242 4242 0 -1 4194560 1500 0 0 0 250 125 0 0 20 0 7 0 \
+                    98765 104857600 2560 18446744073709551615 1 1 0 0 0 0 0 0 0 0 0 0 17 3 0 0 0 0 0\n";
+        assert_eq!(
+            parse_stat(stat),
+            Some(Stat {
+                utime: 250,
+                stime: 125,
+                threads: 7,
+                start_time: 98765,
+                vsize: 104857600,
+                rss: 2560,
+            })
+        );
+        assert_eq!(parse_stat("4242 (app) S 1"), None);
+
+        assert_eq!(
+            parse_boot_time("cpu  1 2 3\nbtime 1700000000\nprocesses 12\n"),
+            Some(1700000000)
+        );
+        assert_eq!(parse_boot_time("cpu  1 2 3\n"), None);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_render() {
+        let rendered = ProcessMetrics::default().render(Format::Prometheus);
+        assert!(rendered.contains("# TYPE process_cpu_seconds_total counter\n"));
+        assert!(rendered.contains("\nprocess_threads "));
+        assert!(!rendered.contains("process_threads 0\n"));
+        assert!(!rendered.contains("process_resident_memory_bytes 0\n"));
+        assert!(!rendered.contains("process_open_fds 0\n"));
+    }
+}
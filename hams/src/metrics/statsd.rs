@@ -0,0 +1,203 @@
+//! StatsD encoding of the native metrics
+//!
+//! StatsD has gauges and counters but no labels, cumulative counters or histogram buckets. The encoder
+//! keeps the totals it last sent, so counters and the count and sum of histograms are sent as their
+//! increase since. DogStatsD sends the labels as tags; for plain StatsD the label values are appended to
+//! the name. Characters StatsD uses as separators are replaced with `_`.
+
+use std::collections::HashMap;
+
+use super::{MetricKind, Registry, Value};
+use crate::hams::config::StatsdFlavor;
+
+/// Encodes registries as StatsD lines, remembering the totals of the counters sent
+#[derive(Debug)]
+pub(crate) struct Encoder {
+    flavor: StatsdFlavor,
+    /// Prefix of each name including the dot, or empty
+    prefix: String,
+    /// Total last sent of each counter by its name and tags
+    sent: HashMap<(String, String), f64>,
+}
+
+impl Encoder {
+    pub(crate) fn new(flavor: StatsdFlavor, prefix: Option<&str>) -> Self {
+        Encoder {
+            flavor,
+            prefix: prefix
+                .map(|prefix| format!("{}.", sanitise(prefix, |_| false)))
+                .unwrap_or_default(),
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Append the lines of the metrics of the registry
+    pub(crate) fn encode(&mut self, registry: &Registry, lines: &mut Vec<String>) {
+        let Ok(families) = registry.families.lock() else {
+            return;
+        };
+        for family in families.values() {
+            let Ok(series) = family.series.lock() else {
+                continue;
+            };
+            for (values, series) in series.iter() {
+                let (name, tags) = self.name_and_tags(&family.name, &family.labels, values);
+                match (&series.value, family.kind) {
+                    (Value::Number(value), MetricKind::Gauge) => {
+                        self.gauge(&name, *value, &tags, lines)
+                    }
+                    (Value::Number(total), _) => self.count(name, *total, tags, lines),
+                    (Value::Histogram { sum, count, .. }, _) => {
+                        self.count(format!("{name}_count"), *count as f64, tags.clone(), lines);
+                        self.count(format!("{name}_sum"), *sum, tags, lines);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The name with the prefix and the tags of a series, which for plain StatsD are part of the name
+    fn name_and_tags(&self, name: &str, labels: &[String], values: &[String]) -> (String, String) {
+        let mut name = format!("{}{}", self.prefix, sanitise(name, |_| false));
+        let pairs = labels
+            .iter()
+            .zip(values)
+            .filter(|(_, value)| !value.is_empty());
+        match self.flavor {
+            StatsdFlavor::Statsd => {
+                for (_, value) in pairs {
+                    name.push('.');
+                    name.push_str(&sanitise(value, |c| c == '.'));
+                }
+                (name, String::new())
+            }
+            StatsdFlavor::Dogstatsd => {
+                let tags: Vec<String> = pairs
+                    .map(|(label, value)| format!("{label}:{}", sanitise(value, |_| false)))
+                    .collect();
+                if tags.is_empty() {
+                    (name, String::new())
+                } else {
+                    (name, format!("|#{}", tags.join(",")))
+                }
+            }
+        }
+    }
+
+    fn gauge(&self, name: &str, value: f64, tags: &str, lines: &mut Vec<String>) {
+        if !value.is_finite() {
+            return;
+        }
+        if value < 0.0 && self.flavor == StatsdFlavor::Statsd {
+            // A signed value changes a StatsD gauge rather than setting it, so it is reset first
+            lines.push(format!("{name}:0|g{tags}"));
+        }
+        lines.push(format!("{name}:{value}|g{tags}"));
+    }
+
+    /// Send the increase of a total since it was last sent, or all of it when it was reset or not sent
+    /// before. A total that has not increased is not sent again
+    fn count(&mut self, name: String, total: f64, tags: String, lines: &mut Vec<String>) {
+        if !total.is_finite() {
+            return;
+        }
+        let line = |increase: f64| format!("{name}:{increase}|c{tags}");
+        match self.sent.get(&(name.clone(), tags.clone())) {
+            Some(sent) if *sent == total => return,
+            Some(sent) if *sent < total => lines.push(line(total - sent)),
+            _ => lines.push(line(total)),
+        }
+        self.sent.insert((name, tags), total);
+    }
+}
+
+/// Replace the separators of StatsD and the characters matching `also` with `_`
+fn sanitise(text: &str, also: impl Fn(char) -> bool) -> String {
+    text.chars()
+        .map(|c| {
+            if matches!(c, ':' | '|' | '@' | '#' | ',') || c.is_whitespace() || also(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> Registry {
+        let registry = Registry::default();
+        registry
+            .counter("jobs_total", "Jobs", &["queue"])
+            .unwrap()
+            .inc_by(&["fast.lane"], 2.0)
+            .unwrap();
+        registry
+            .gauge("temperature_celsius", "Temperature", &[])
+            .unwrap()
+            .set(&[], -3.5)
+            .unwrap();
+        registry
+            .histogram("latency_seconds", "Latency", &[], Some(&[0.1]))
+            .unwrap()
+            .observe(&[], 0.25)
+            .unwrap();
+        registry
+    }
+
+    fn encode(encoder: &mut Encoder, registry: &Registry) -> Vec<String> {
+        let mut lines = Vec::new();
+        encoder.encode(registry, &mut lines);
+        lines
+    }
+
+    #[test]
+    fn test_dogstatsd() {
+        let registry = registry();
+        let mut encoder = Encoder::new(StatsdFlavor::Dogstatsd, Some("shop"));
+        assert_eq!(
+            encode(&mut encoder, &registry),
+            [
+                "shop.jobs_total:2|c|#queue:fast.lane",
+                "shop.latency_seconds_count:1|c",
+                "shop.latency_seconds_sum:0.25|c",
+                "shop.temperature_celsius:-3.5|g",
+            ]
+        );
+
+        // Only the increase of the counters is sent again
+        registry
+            .counter("jobs_total", "Jobs", &["queue"])
+            .unwrap()
+            .inc(&["fast.lane"])
+            .unwrap();
+        assert_eq!(
+            encode(&mut encoder, &registry),
+            [
+                "shop.jobs_total:1|c|#queue:fast.lane",
+                "shop.temperature_celsius:-3.5|g",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_statsd() {
+        let registry = registry();
+        let mut encoder = Encoder::new(StatsdFlavor::Statsd, None);
+        let lines = encode(&mut encoder, &registry);
+        assert_eq!(lines[0], "jobs_total.fast_lane:2|c");
+        assert_eq!(
+            lines[3..],
+            ["temperature_celsius:0|g", "temperature_celsius:-3.5|g"]
+        );
+    }
+
+    #[test]
+    fn test_sanitise() {
+        assert_eq!(sanitise("a:b|c@d#e,f g", |_| false), "a_b_c_d_e_f_g");
+        assert_eq!(sanitise("a.b", |c| c == '.'), "a_b");
+    }
+}
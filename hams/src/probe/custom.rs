@@ -0,0 +1,204 @@
+/// Custom probe runs a check written in the language of the host through a C callback.
+use super::HealthProbe;
+use libc::{c_int, c_void, time_t};
+use log::{error, info};
+use std::{
+    ffi::{c_char, CStr, CString},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Room for the message of a check, including its NUL terminator
+const MESSAGE_LEN: usize = 256;
+
+/// Check of a custom probe. Returns 1 if the probe passes, 0 if it fails and -1 on error. A NUL
+/// terminated message of at most `message_len` bytes, including the NUL, may be written to `message` to
+/// explain a failure or error. The message is reported with the result of the check.
+pub type CustomCheckCallback =
+    extern "C" fn(user_data: *mut c_void, message: *mut c_char, message_len: usize) -> c_int;
+
+/// Release the user data of a custom probe, NULL when it needs no releasing
+pub type CustomFreeCallback = Option<extern "C" fn(user_data: *mut c_void)>;
+
+/// The callbacks and user data of a custom probe, shared by its copies
+#[derive(Debug)]
+struct Callback {
+    check: CustomCheckCallback,
+    user_data: *mut c_void,
+    free: CustomFreeCallback,
+    /// The check is running, possibly after a check timed out waiting for it
+    running: AtomicBool,
+}
+
+// The host guarantees the user data can be used from any thread when it creates the probe
+unsafe impl Send for Callback {}
+unsafe impl Sync for Callback {}
+
+impl Drop for Callback {
+    fn drop(&mut self) {
+        if let Some(free) = self.free {
+            free(self.user_data);
+        }
+    }
+}
+
+/// A probe whose check is a callback of the host, so C, Kotlin or TypeScript code can decide whether
+/// eg a connection pool is healthy. The user data is freed when the last copy of the probe is dropped.
+///
+/// The message of the latest check is reported with its result. A check which still runs after the
+/// check timed out on it is not called again until it returns.
+#[derive(Debug)]
+pub struct Custom {
+    name: String,
+    callback: Arc<Callback>,
+    /// Message of the latest check of this copy
+    message: Mutex<Option<String>>,
+}
+
+/// Copies share the callback but each reports the message of its own checks
+impl Clone for Custom {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            callback: self.callback.clone(),
+            message: Mutex::new(None),
+        }
+    }
+}
+
+impl Custom {
+    /// Create a new Custom probe calling `check` with `user_data`, which `free` releases if given
+    pub fn new<S: Into<String>>(
+        name: S,
+        check: CustomCheckCallback,
+        user_data: *mut c_void,
+        free: CustomFreeCallback,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            callback: Arc::new(Callback {
+                check,
+                user_data,
+                free,
+                running: AtomicBool::new(false),
+            }),
+            message: Mutex::new(None),
+        }
+    }
+
+    fn set_message(&self, message: Option<String>) {
+        if let Ok(mut guard) = self.message.lock() {
+            *guard = message;
+        }
+    }
+}
+
+impl HealthProbe for Custom {
+    #[doc = "Name of the probe"]
+    fn name(&self) -> *mut c_char {
+        CString::new(self.name.clone()).unwrap().into_raw()
+    }
+
+    fn check(&self, _time: time_t) -> i32 {
+        if self.callback.running.swap(true, Ordering::AcqRel) {
+            self.set_message(Some("still running from an earlier check".to_string()));
+            return -1;
+        }
+        let mut message = [0 as c_char; MESSAGE_LEN];
+        let reply =
+            (self.callback.check)(self.callback.user_data, message.as_mut_ptr(), MESSAGE_LEN);
+        self.callback.running.store(false, Ordering::Release);
+        // Terminate the message in case the callback filled the buffer
+        message[MESSAGE_LEN - 1] = 0;
+        let message = unsafe { CStr::from_ptr(message.as_ptr()) }.to_string_lossy();
+
+        let reply = match reply {
+            1 => 1,
+            0 => {
+                if !message.is_empty() {
+                    info!("Probe {} failed: {message}", self.name);
+                }
+                0
+            }
+            _ => {
+                error!("Probe {} errored with {reply}: {message}", self.name);
+                -1
+            }
+        };
+        self.set_message((!message.is_empty()).then(|| message.into_owned()));
+        reply
+    }
+
+    fn describe(&self) -> *mut c_char {
+        CString::new(r#"{"type":"custom"}"#).unwrap().into_raw()
+    }
+
+    fn set_valid(&self, _valid: bool) -> i32 {
+        0
+    }
+
+    fn message(&self) -> *mut c_char {
+        match self.message.lock().ok().and_then(|message| message.clone()) {
+            Some(message) => CString::new(message).map_or(std::ptr::null_mut(), CString::into_raw),
+            None => std::ptr::null_mut(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicI32, Ordering};
+
+    use super::*;
+
+    /// Reply with the value of the state, explaining anything but a pass
+    extern "C" fn check(user_data: *mut c_void, message: *mut c_char, message_len: usize) -> c_int {
+        let reply = unsafe { &*(user_data as *const AtomicI32) }.load(Ordering::Relaxed);
+        if reply != 1 {
+            let text = CString::new("pool exhausted").unwrap();
+            let length = text.as_bytes_with_nul().len().min(message_len);
+            unsafe { std::ptr::copy_nonoverlapping(text.as_ptr(), message, length) };
+        }
+        reply
+    }
+
+    extern "C" fn free(user_data: *mut c_void) {
+        drop(unsafe { Box::from_raw(user_data as *mut AtomicI32) });
+    }
+
+    #[test]
+    fn test_custom() {
+        let state = Box::into_raw(Box::new(AtomicI32::new(1)));
+        let probe = Custom::new("pool", check, state as *mut c_void, Some(free));
+        let copy = probe.clone();
+
+        assert_eq!(probe.check(0), 1);
+        assert!(probe.message().is_null());
+        unsafe { &*state }.store(0, Ordering::Relaxed);
+        assert_eq!(copy.check(0), 0);
+        assert_eq!(
+            unsafe { CString::from_raw(copy.message()) }
+                .into_string()
+                .unwrap(),
+            "pool exhausted"
+        );
+        // Each copy reports the message of its own checks
+        assert!(probe.message().is_null());
+        unsafe { &*state }.store(7, Ordering::Relaxed);
+        assert_eq!(probe.check(0), -1);
+        assert_eq!(probe.set_valid(true), 0);
+        assert_eq!(
+            unsafe { CString::from_raw(probe.describe()) }
+                .into_string()
+                .unwrap(),
+            r#"{"type":"custom"}"#
+        );
+
+        // The state is freed with the last copy
+        drop(probe);
+        assert_eq!(copy.check(0), -1);
+        drop(copy);
+    }
+}
@@ -5,6 +5,10 @@ use thin_trait_object::thin_trait_object;
 /// A boxed HealthProbe for use over FFI
 #[thin_trait_object]
 /// Trait for health probes
+///
+/// The vtable of a boxed probe is part of the ABI, so its layout is versioned by
+/// HAMS_PROBE_ABI_VERSION. Version 2 added describe and set_valid and version 3 added message, so a
+/// probe built for an earlier version must be rebuilt against this header.
 pub trait HealthProbe: Sync + Send {
     /// Name of the probe. Created as a c_str and converted to a raw pointer
     /// to be used in FFI.
@@ -14,4 +18,16 @@ pub trait HealthProbe: Sync + Send {
     /// Returns 1 if the probe is healthy, 0 otherwise
     /// Returns -1 if an error occurred
     fn check(&self, time: time_t) -> c_int;
+    /// Type and configuration of the probe as a JSON object with a `type` field,
+    /// eg `{"type":"manual","valid":true}`.
+    /// Received owns the pointer and is responsible for freeing it.
+    fn describe(&self) -> *mut c_char;
+    /// Force the result of the probe
+    /// Returns 1 if the result was set, 0 if the probe cannot be set
+    fn set_valid(&self, valid: bool) -> c_int;
+    /// Explanation of the latest check, eg why it failed. NULL when there is none.
+    /// Received owns the pointer and is responsible for freeing it.
+    fn message(&self) -> *mut c_char {
+        std::ptr::null_mut()
+    }
 }
@@ -57,6 +57,19 @@ impl HealthProbe for Kick {
 
         (time < self.latest + duration_secs) as i32
     }
+
+    fn describe(&self) -> *mut c_char {
+        CString::new(format!(
+            r#"{{"type":"kick","margin_secs":{}}}"#,
+            self.margin.as_secs()
+        ))
+        .unwrap()
+        .into_raw()
+    }
+
+    fn set_valid(&self, _valid: bool) -> i32 {
+        0
+    }
 }
 
 #[cfg(test)]
@@ -79,6 +92,14 @@ mod tests {
         assert!(probe.check(time_now) == 1);
         //No need to sleep, we can just check the time
         assert!(probe.check(time_now + 2) == 0);
+
+        assert_eq!(probe.set_valid(true), 0);
+        assert_eq!(
+            unsafe { CString::from_raw(probe.describe()) }
+                .into_string()
+                .unwrap(),
+            r#"{"type":"kick","margin_secs":1}"#
+        );
     }
 
     // Test the boxed_probe method
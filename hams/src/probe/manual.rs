@@ -58,6 +58,18 @@ impl HealthProbe for Manual {
     fn check(&self, _time: time_t) -> i32 {
         self.enabled.lock().unwrap().valid as i32
     }
+
+    fn describe(&self) -> *mut c_char {
+        let valid = self.enabled.lock().unwrap().valid;
+        CString::new(format!(r#"{{"type":"manual","valid":{valid}}}"#))
+            .unwrap()
+            .into_raw()
+    }
+
+    fn set_valid(&self, valid: bool) -> i32 {
+        self.enabled.lock().unwrap().valid = valid;
+        1
+    }
 }
 
 // impl Into<FFIProbe> for Manual {
@@ -94,6 +106,15 @@ mod tests {
         probe.toggle();
         assert!(probe.check(time_now) == 1);
 
+        assert_eq!(probe.set_valid(false), 1);
+        assert!(probe.check(time_now) == 0);
+        assert_eq!(
+            unsafe { CString::from_raw(probe.describe()) }
+                .into_string()
+                .unwrap(),
+            r#"{"type":"manual","valid":false}"#
+        );
+
         drop(probe);
     }
 
@@ -7,22 +7,32 @@ use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 use std::time::SystemTime;
+use utoipa::ToSchema;
 
 pub(crate) mod ffitraits;
 
+/// This module contains the custom probe
+pub mod custom;
 /// This module contains the kick probe
 pub mod kick;
 /// This module contains the manual probe
 pub mod manual;
 
 /// Detail structure for replies from ready and alive for a single probe
-#[derive(Serialize, PartialEq, Clone)]
+#[derive(Serialize, PartialEq, Clone, ToSchema)]
 pub struct HealthProbeResult {
     /// Name of health Reply
     pub name: String,
     /// Return value of health Reply
     pub valid: bool,
+    /// Description of a fault injected into the probe, which replaced or delayed its real check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fault: Option<String>,
+    /// Explanation given by the probe, eg why it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
 }
 
 impl fmt::Debug for HealthProbeResult {
@@ -43,6 +53,21 @@ pub(crate) trait AsyncHealthProbe: Debug + Sync + Send {
     fn name(&self) -> Result<String, HamsError>;
 
     async fn check(&self, time: SystemTime) -> Result<bool, HamsError>;
+
+    /// Type and configuration of the probe as a JSON object with a `type` field
+    fn describe(&self) -> Result<serde_json::Value, HamsError> {
+        Ok(serde_json::json!({"type": "unknown"}))
+    }
+
+    /// Force the result of the probe. Returns false if the probe cannot be set
+    fn set_valid(&self, _valid: bool) -> Result<bool, HamsError> {
+        Ok(false)
+    }
+
+    /// Explanation of the latest check, eg why it failed
+    fn message(&self) -> Option<String> {
+        None
+    }
 }
 
 impl Hash for dyn AsyncHealthProbe {
@@ -61,10 +86,10 @@ impl Eq for dyn AsyncHealthProbe {}
 
 /// HealthProbe is a an AsyncHealthProbe that can be converted to a Box<dyn AsyncHealthProbe> so that it
 /// is compatible with the async health that is required for some HealthChecks (network based)
-/// This stuct includes a BoxedHealthProbe for the FFI probe
+/// This stuct includes a BoxedHealthProbe for the FFI probe, shared with the thread its check runs on
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct FFIProbe {
-    probe: BoxedHealthProbe<'static>,
+    probe: Arc<BoxedHealthProbe<'static>>,
 }
 
 impl<T> From<T> for FFIProbe
@@ -73,7 +98,7 @@ where
 {
     fn from(probe: T) -> Self {
         FFIProbe {
-            probe: BoxedHealthProbe::new(probe),
+            probe: Arc::new(BoxedHealthProbe::new(probe)),
         }
     }
 }
@@ -81,7 +106,13 @@ where
 #[async_trait]
 impl AsyncHealthProbe for FFIProbe {
     fn name(&self) -> Result<String, HamsError> {
-        Ok(unsafe { CString::from_raw(self.probe.name()) }.into_string()?)
+        let name = self.probe.name();
+        if name.is_null() {
+            return Err(HamsError::FFIError(
+                "Probe returned a NULL name".to_string(),
+            ));
+        }
+        Ok(unsafe { CString::from_raw(name) }.into_string()?)
     }
 
     async fn check(&self, time: SystemTime) -> Result<bool, HamsError> {
@@ -90,7 +121,16 @@ impl AsyncHealthProbe for FFIProbe {
             .as_secs()
             .try_into()?;
 
-        let check_reply = self.probe.check(epoch_secs);
+        // A foreign check may block, so it runs off the async threads when there is a runtime to run it
+        let check_reply = match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let probe = self.probe.clone();
+                runtime
+                    .spawn_blocking(move || probe.check(epoch_secs))
+                    .await?
+            }
+            Err(_) => self.probe.check(epoch_secs),
+        };
         match check_reply {
             1 => Ok(true),
             0 => Ok(false),
@@ -99,6 +139,35 @@ impl AsyncHealthProbe for FFIProbe {
             )),
         }
     }
+
+    fn describe(&self) -> Result<serde_json::Value, HamsError> {
+        let description = self.probe.describe();
+        if description.is_null() {
+            return Err(HamsError::FFIError(
+                "Probe returned a NULL description".to_string(),
+            ));
+        }
+        let description = unsafe { CString::from_raw(description) }.into_string()?;
+        Ok(serde_json::from_str(&description)?)
+    }
+
+    fn set_valid(&self, valid: bool) -> Result<bool, HamsError> {
+        match self.probe.set_valid(valid) {
+            1 => Ok(true),
+            0 => Ok(false),
+            error_value => Err(HamsError::Message(
+                "Error in set_valid probe got value: ".to_string() + &error_value.to_string(),
+            )),
+        }
+    }
+
+    fn message(&self) -> Option<String> {
+        let message = self.probe.message();
+        if message.is_null() {
+            return None;
+        }
+        unsafe { CString::from_raw(message) }.into_string().ok()
+    }
 }
 
 // impl<T> From<T> for Box<dyn AsyncHealthProbe>
@@ -287,6 +356,8 @@ mod tests {
         let hpr = HealthProbeResult {
             name: "test".to_owned(),
             valid: true,
+            fault: None,
+            message: None,
         };
         assert_eq!(hpr.name, "test");
         assert!(hpr.valid);
@@ -307,6 +378,42 @@ mod tests {
         fn check(&self, _time: time_t) -> c_int {
             self.check as c_int
         }
+
+        fn describe(&self) -> *mut c_char {
+            CString::new(r#"{"type":"test"}"#).unwrap().into_raw()
+        }
+
+        fn set_valid(&self, _valid: bool) -> c_int {
+            0
+        }
+    }
+
+    /// A foreign probe which fails to allocate its strings
+    struct NullProbe;
+
+    impl HealthProbe for NullProbe {
+        fn name(&self) -> *mut c_char {
+            std::ptr::null_mut()
+        }
+
+        fn check(&self, _time: time_t) -> c_int {
+            1
+        }
+
+        fn describe(&self) -> *mut c_char {
+            std::ptr::null_mut()
+        }
+
+        fn set_valid(&self, _valid: bool) -> c_int {
+            0
+        }
+    }
+
+    #[test]
+    fn test_null_strings() {
+        let probe = super::FFIProbe::from(NullProbe);
+        assert!(matches!(probe.name(), Err(HamsError::FFIError(_))));
+        assert!(matches!(probe.describe(), Err(HamsError::FFIError(_))));
     }
 
     #[test]
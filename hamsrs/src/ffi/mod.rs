@@ -30,6 +30,26 @@ pub struct KickProbe {
     _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
 }
 
+/// Opaque objects representing HaMS metrics.
+/// Low level API access to the CAPI
+#[repr(C)]
+pub struct Counter {
+    _data: [u8; 0],
+    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+}
+
+#[repr(C)]
+pub struct Gauge {
+    _data: [u8; 0],
+    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+}
+
+#[repr(C)]
+pub struct Histogram {
+    _data: [u8; 0],
+    _marker: core::marker::PhantomData<(*mut u8, core::marker::PhantomPinned)>,
+}
+
 /// Opaque object representing HaMS Probe objects.
 /// Low level API access to the CAPI
 // #[repr(C)]
@@ -43,8 +63,15 @@ pub type BProbe = BoxedHealthProbe<'static>;
 extern "C" {
     /// Configure logging for HaMS
     pub fn hams_logger_init(param: LogParam) -> i32;
+    /// Change the log level of HaMS at runtime
+    pub fn hams_set_log_level(level: *const libc::c_char, target: *const libc::c_char) -> i32;
 
     pub fn hams_new(name: *const libc::c_char, address: *const libc::c_char) -> *mut Hams;
+    pub fn hams_new_from_json(config: *const libc::c_char) -> *mut Hams;
+    pub fn hams_new_from_yaml(config: *const libc::c_char) -> *mut Hams;
+    /// Read the message of the last error of a HaMS call on this thread
+    pub fn hams_last_error_length() -> i32;
+    pub fn hams_last_error_message(buffer: *mut libc::c_char, length: i32) -> i32;
     pub fn hams_free(hams: *mut Hams) -> i32;
     pub fn hams_start(hams: *mut Hams) -> i32;
     pub fn hams_stop(hams: *mut Hams) -> i32;
@@ -56,6 +83,18 @@ extern "C" {
     ) -> i32;
     pub fn hams_deregister_shutdown(hams: *mut Hams) -> i32;
 
+    pub fn hams_set_version(hams: *mut Hams, version: *const libc::c_char) -> i32;
+    pub fn hams_set_build_info(
+        hams: *mut Hams,
+        git_sha: *const libc::c_char,
+        build_time: *const libc::c_char,
+    ) -> i32;
+    pub fn hams_set_label(
+        hams: *mut Hams,
+        key: *const libc::c_char,
+        value: *const libc::c_char,
+    ) -> i32;
+
     #[allow(improper_ctypes)]
     pub fn hams_alive_insert(hams: *mut Hams, probe: *mut BProbe) -> i32;
     pub fn hams_alive_remove(hams: *mut Hams, probe: *mut BProbe) -> i32;
@@ -68,6 +107,109 @@ extern "C" {
         state: *const c_void,
     ) -> i32;
     pub fn hams_deregister_prometheus(hams: *mut Hams) -> i32;
+    pub fn hams_register_collector(
+        hams: *mut Hams,
+        name: *const libc::c_char,
+        my_cb: extern "C" fn(state: *const c_void) -> *const libc::c_char,
+        my_cb_free: extern "C" fn(*mut libc::c_char),
+        state: *const c_void,
+    ) -> i32;
+    pub fn hams_deregister_collector(hams: *mut Hams, name: *const libc::c_char) -> i32;
+
+    pub fn hams_set_maintenance(
+        hams: *mut Hams,
+        reason: *const libc::c_char,
+        expiry_secs: u64,
+    ) -> i32;
+
+    pub fn hams_probe_list(hams: *mut Hams) -> *mut libc::c_char;
+    pub fn hams_probe_set_valid(
+        hams: *mut Hams,
+        check: *const libc::c_char,
+        probe: *const libc::c_char,
+        valid: bool,
+    ) -> i32;
+    pub fn hams_probe_check(
+        hams: *mut Hams,
+        check: *const libc::c_char,
+        probe: *const libc::c_char,
+    ) -> i32;
+    pub fn hams_string_free(s: *mut libc::c_char) -> i32;
+    pub fn hams_inject_fault(
+        hams: *mut Hams,
+        check: *const libc::c_char,
+        probe: *const libc::c_char,
+        fault: *const libc::c_char,
+    ) -> i32;
+
+    pub fn hams_counter_new(
+        hams: *mut Hams,
+        name: *const libc::c_char,
+        help: *const libc::c_char,
+        labels: *const *const libc::c_char,
+        label_count: usize,
+    ) -> *mut Counter;
+    pub fn hams_counter_inc(
+        counter: *mut Counter,
+        values: *const *const libc::c_char,
+        value_count: usize,
+        amount: f64,
+    ) -> i32;
+    pub fn hams_counter_inc_exemplar(
+        counter: *mut Counter,
+        values: *const *const libc::c_char,
+        value_count: usize,
+        amount: f64,
+        exemplar_names: *const *const libc::c_char,
+        exemplar_values: *const *const libc::c_char,
+        exemplar_count: usize,
+    ) -> i32;
+    pub fn hams_counter_free(counter: *mut Counter) -> i32;
+    pub fn hams_gauge_new(
+        hams: *mut Hams,
+        name: *const libc::c_char,
+        help: *const libc::c_char,
+        labels: *const *const libc::c_char,
+        label_count: usize,
+    ) -> *mut Gauge;
+    pub fn hams_gauge_set(
+        gauge: *mut Gauge,
+        values: *const *const libc::c_char,
+        value_count: usize,
+        value: f64,
+    ) -> i32;
+    pub fn hams_gauge_add(
+        gauge: *mut Gauge,
+        values: *const *const libc::c_char,
+        value_count: usize,
+        amount: f64,
+    ) -> i32;
+    pub fn hams_gauge_free(gauge: *mut Gauge) -> i32;
+    pub fn hams_histogram_new(
+        hams: *mut Hams,
+        name: *const libc::c_char,
+        help: *const libc::c_char,
+        labels: *const *const libc::c_char,
+        label_count: usize,
+        buckets: *const f64,
+        bucket_count: usize,
+    ) -> *mut Histogram;
+    pub fn hams_histogram_observe(
+        histogram: *mut Histogram,
+        values: *const *const libc::c_char,
+        value_count: usize,
+        observation: f64,
+    ) -> i32;
+    pub fn hams_histogram_observe_exemplar(
+        histogram: *mut Histogram,
+        values: *const *const libc::c_char,
+        value_count: usize,
+        observation: f64,
+        exemplar_names: *const *const libc::c_char,
+        exemplar_values: *const *const libc::c_char,
+        exemplar_count: usize,
+    ) -> i32;
+    pub fn hams_histogram_free(histogram: *mut Histogram) -> i32;
 
     pub fn hello_world();
     pub fn hello_callback(my_cb: extern "C" fn());
@@ -76,6 +218,7 @@ extern "C" {
         my_cb_free: extern "C" fn(*mut libc::c_char),
     );
     pub fn hams_version() -> *const libc::c_char;
+    pub fn hams_probe_abi_version() -> u32;
 
     pub fn probe_manual_new(name: *const libc::c_char, valid: bool) -> *mut ManualProbe;
     pub fn probe_manual_free(probe: *mut ManualProbe) -> i32;
@@ -1,9 +1,15 @@
 use derive_builder::Builder;
-use serde::Deserialize;
-use std::net::SocketAddr;
+use log::Level;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_with::{serde_as, DurationSeconds, DurationSecondsWithFrac};
+use std::{collections::BTreeMap, fmt, net::SocketAddr, path::PathBuf, time::Duration};
 
-#[derive(Deserialize, Builder, Debug, Clone)]
+/// Configuration of HaMS, with the same schema as the configuration documents of the C API.
+///
+/// The configuration is passed to HaMS as JSON on creation, which validates it
+#[derive(Deserialize, Serialize, Builder, Debug, Clone, PartialEq)]
 #[serde(default)]
+#[serde(deny_unknown_fields)]
 #[builder(default)]
 pub struct HamsConfig {
     /// Hostname to start the webservice on
@@ -11,6 +17,27 @@ pub struct HamsConfig {
     pub address: SocketAddr,
     /// Name for the service
     pub name: String,
+    /// Path the routes are served under, eg `/hams` serves `/hams/alive`
+    pub prefix: String,
+    /// Signals which stop HaMS and call the shutdown callback. Empty leaves signals to the application
+    pub signals: Vec<StopSignal>,
+    /// Response policy for the alive endpoint
+    pub alive: CheckConfig,
+    /// Response policy for the ready endpoint
+    pub ready: CheckConfig,
+    /// Logging of each request served
+    pub access_log: AccessLogConfig,
+    /// Serve over TLS instead of plain HTTP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsConfig>,
+    /// Authentication required by each group of routes
+    pub auth: AuthConfig,
+    /// Metrics served besides those of the application and the calling of its collectors
+    pub metrics: MetricsConfig,
+    /// Export metrics and check spans to an OpenTelemetry collector. Ignored with a warning when HaMS
+    /// was built without the otel feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otel: Option<OtelConfig>,
 }
 
 impl Default for HamsConfig {
@@ -18,10 +45,412 @@ impl Default for HamsConfig {
         Self {
             address: "0.0.0.0:8079".parse().unwrap(),
             name: "NO_NAME".to_string(),
+            prefix: DEFAULT_PREFIX.to_string(),
+            signals: vec![
+                StopSignal::Interrupt,
+                StopSignal::Terminate,
+                StopSignal::Quit,
+                StopSignal::Hangup,
+            ],
+            alive: CheckConfig::default(),
+            ready: CheckConfig::default(),
+            access_log: AccessLogConfig::default(),
+            tls: None,
+            auth: AuthConfig::default(),
+            metrics: MetricsConfig::default(),
+            otel: None,
         }
     }
 }
 
+/// Path the routes are served under unless configured otherwise
+pub const DEFAULT_PREFIX: &str = "/hams";
+
+/// Signal which stops HaMS
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopSignal {
+    /// SIGINT, eg ctrl-c
+    Interrupt,
+    /// SIGTERM, eg from kubelet
+    Terminate,
+    /// SIGQUIT
+    Quit,
+    /// SIGHUP
+    Hangup,
+}
+
+/// HTTP status returned by a check endpoint when the check fails unless configured otherwise
+pub const DEFAULT_FAIL_STATUS: u16 = 503;
+
+/// HTTP status returned by a check endpoint rejected by its concurrency limit unless configured otherwise
+pub const DEFAULT_LIMIT_STATUS: u16 = 429;
+
+/// Time a probe may take on a check unless configured otherwise
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Response policy of a check endpoint.
+///
+/// A passing check always replies 200 OK. A failing check replies with `fail_status` and, when
+/// `retry_after` is set, a `Retry-After` header in seconds.
+#[serde_as]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+#[serde(deny_unknown_fields)]
+pub struct CheckConfig {
+    /// HTTP status for a failing check. Must be a 4xx or 5xx status
+    #[serde(deserialize_with = "deserialize_error_status")]
+    pub fail_status: u16,
+    /// Value of the Retry-After header on a failing or limited check
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    pub retry_after: Option<Duration>,
+    /// Most evaluations of the check running at once. Unlimited when not set
+    pub max_concurrent: Option<usize>,
+    /// Reply when max_concurrent evaluations are already running
+    pub on_limit: LimitPolicy,
+    /// HTTP status for a request rejected by the limit. Must be a 4xx or 5xx status
+    #[serde(deserialize_with = "deserialize_error_status")]
+    pub limit_status: u16,
+    /// Time a probe may take before the check gives up on it
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub timeout: Duration,
+    /// Probes added to the check on creation, besides those the application inserts
+    pub probes: Vec<ProbeConfig>,
+}
+
+impl Default for CheckConfig {
+    fn default() -> Self {
+        Self {
+            fail_status: DEFAULT_FAIL_STATUS,
+            retry_after: None,
+            max_concurrent: None,
+            on_limit: LimitPolicy::Reject,
+            limit_status: DEFAULT_LIMIT_STATUS,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+            probes: Vec::new(),
+        }
+    }
+}
+
+/// A probe declared in the configuration
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ProbeConfig {
+    /// A probe which holds the validity it is set to, through [super::Hams::probe_set_valid] or the
+    /// admin routes
+    Manual {
+        /// Name of the probe, unique within the check
+        name: String,
+        /// Validity on creation
+        #[serde(default = "default_true")]
+        valid: bool,
+    },
+}
+
+/// Reply to a check request when the check is at its concurrency limit
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitPolicy {
+    /// Reply with `limit_status`
+    Reject,
+    /// Reply with the latest result of each probe, or reject when a probe has not been checked yet
+    Stale,
+}
+
+/// Time a collector may take on a scrape of the metrics unless configured otherwise
+pub const DEFAULT_COLLECTOR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Metrics served besides those of the application and the calling of its collectors on each scrape
+#[serde_as]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    /// Time a collector may take before the scrape gives up on it
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub timeout: Duration,
+    /// Serve the latest output of a failing collector instead of failing the scrape
+    pub serve_last_good: bool,
+    /// Serve the standard `process_*` metrics read from `/proc/self` on Linux
+    pub process: bool,
+    /// Push the metrics to a Prometheus Pushgateway while running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub push: Option<PushConfig>,
+    /// Emit the native metrics to a StatsD or DogStatsD agent while running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub statsd: Option<StatsdConfig>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_COLLECTOR_TIMEOUT,
+            serve_last_good: false,
+            process: false,
+            push: None,
+            statsd: None,
+        }
+    }
+}
+
+/// Time between pushes of the metrics unless configured otherwise
+pub const DEFAULT_PUSH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Pushing of the metrics to a Prometheus Pushgateway, for jobs that finish before they are scraped
+#[serde_as]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PushConfig {
+    /// Base URL of the Pushgateway, eg `http://pushgateway:9091`
+    pub url: String,
+    /// Job label of the grouping. The name of the HaMS when not set
+    #[serde(default)]
+    pub job: Option<String>,
+    /// Instance label of the grouping. The hostname when not set
+    #[serde(default)]
+    pub instance: Option<String>,
+    /// Time between pushes
+    #[serde(default = "default_push_interval")]
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub interval: Duration,
+    /// Delete the grouping from the Pushgateway on a clean exit
+    #[serde(default = "default_true")]
+    pub delete_on_exit: bool,
+    /// Time a push or delete may take, including connecting
+    #[serde(default = "default_push_timeout")]
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub timeout: Duration,
+}
+
+fn default_push_interval() -> Duration {
+    DEFAULT_PUSH_INTERVAL
+}
+
+/// Time a push may take unless configured otherwise
+pub const DEFAULT_PUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn default_push_timeout() -> Duration {
+    DEFAULT_PUSH_TIMEOUT
+}
+
+/// Time between emits to StatsD unless configured otherwise
+pub const DEFAULT_STATSD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Largest datagram sent to StatsD unless configured otherwise, which fits in an Ethernet frame
+pub const DEFAULT_STATSD_PACKET_SIZE: usize = 1432;
+
+/// Protocol of the StatsD agent, which decides how metric labels are sent
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsdFlavor {
+    /// Plain StatsD, which has no tags, so label values are appended to the metric name
+    Statsd,
+    /// DogStatsD, which sends labels as `label:value` tags
+    Dogstatsd,
+}
+
+/// Emitting of the native metrics to a StatsD or DogStatsD agent over UDP, for hosts without Prometheus
+#[serde_as]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct StatsdConfig {
+    /// UDP address of the agent, eg `127.0.0.1:8125`
+    pub address: String,
+    /// Protocol of the agent
+    #[serde(default = "default_statsd_flavor")]
+    pub flavor: StatsdFlavor,
+    /// Prefix of the metric names, joined with a dot
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Time between emits
+    #[serde(default = "default_statsd_interval")]
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub interval: Duration,
+    /// Largest datagram sent, metrics are split over as many datagrams as needed
+    #[serde(default = "default_statsd_packet_size")]
+    pub packet_size: usize,
+}
+
+fn default_statsd_flavor() -> StatsdFlavor {
+    StatsdFlavor::Dogstatsd
+}
+
+fn default_statsd_interval() -> Duration {
+    DEFAULT_STATSD_INTERVAL
+}
+
+fn default_statsd_packet_size() -> usize {
+    DEFAULT_STATSD_PACKET_SIZE
+}
+
+/// Time between exports to an OpenTelemetry collector unless configured otherwise
+pub const DEFAULT_EXPORT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Export of the native metrics and check spans to an OpenTelemetry collector over OTLP/HTTP
+#[serde_as]
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OtelConfig {
+    /// Base URL of the OTLP/HTTP receiver of the collector, eg `http://otel-collector:4318`
+    pub endpoint: String,
+    /// Time between exports
+    #[serde(default = "default_export_interval")]
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub interval: Duration,
+    /// Headers sent with each export, eg for authentication
+    #[serde(default)]
+    pub headers: BTreeMap<String, Secret>,
+    /// Time an export may take, including connecting
+    #[serde(default = "default_export_timeout")]
+    #[serde_as(as = "DurationSecondsWithFrac<f64>")]
+    pub timeout: Duration,
+}
+
+fn default_export_interval() -> Duration {
+    DEFAULT_EXPORT_INTERVAL
+}
+
+/// Time an export to an OpenTelemetry collector may take unless configured otherwise
+pub const DEFAULT_EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn default_export_timeout() -> Duration {
+    DEFAULT_EXPORT_TIMEOUT
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Access log policy of the webservice
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+#[serde(deny_unknown_fields)]
+pub struct AccessLogConfig {
+    /// Log each request served
+    pub enabled: bool,
+    /// Level for requests other than alive and ready
+    pub level: Level,
+    /// Log target for requests other than alive and ready
+    pub target: String,
+    /// Level for requests to alive and ready
+    pub probe_level: Level,
+    /// Log target for requests to alive and ready
+    pub probe_target: String,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            level: Level::Info,
+            target: "hams::access".to_string(),
+            probe_level: Level::Debug,
+            probe_target: "hams::access::probe".to_string(),
+        }
+    }
+}
+
+/// TLS configuration of the webservice
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file with the server certificate chain
+    pub cert: PathBuf,
+    /// PEM file with the server private key
+    pub key: PathBuf,
+    /// PEM file with the CAs trusted to sign client certificates
+    #[serde(default)]
+    pub client_ca: Option<PathBuf>,
+}
+
+/// A secret which HaMS reads on creation
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Secret {
+    /// The secret itself. Prefer file or env outside of tests
+    Value(String),
+    /// File holding the secret. Surrounding whitespace is ignored
+    File(PathBuf),
+    /// Environment variable holding the secret
+    Env(String),
+}
+
+/// Never print the value of a secret
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Secret::Value(_) => f.debug_tuple("Value").field(&"<redacted>").finish(),
+            Secret::File(path) => f.debug_tuple("File").field(path).finish(),
+            Secret::Env(name) => f.debug_tuple("Env").field(name).finish(),
+        }
+    }
+}
+
+/// Username and password accepted by HTTP basic auth
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BasicCredential {
+    /// Username of the caller
+    pub username: String,
+    /// Password of the caller
+    pub password: Secret,
+}
+
+/// Who may use a group of routes
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    /// Anyone who can reach the port
+    Open,
+    /// Only authenticated callers. Others get 401 Unauthorized
+    Authenticated,
+    /// Verbose checks only: unauthenticated callers get the reply without probe details
+    Redact,
+}
+
+/// Authentication of the webservice routes.
+///
+/// Shutdown and admin are closed without credentials unless set to `open`. Once any credential is
+/// configured the other groups default to requiring authentication
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Tokens accepted in `Authorization: Bearer <token>`
+    pub bearer_tokens: Vec<Secret>,
+    /// Credentials accepted in `Authorization: Basic`
+    pub basic: Vec<BasicCredential>,
+    /// Common names of client certificates accepted. Empty accepts any certificate signed by the client CA
+    pub client_names: Vec<String>,
+    /// Access to alive and ready. Open when not set
+    pub checks: Option<Access>,
+    /// Access to verbose alive and ready
+    pub verbose: Option<Access>,
+    /// Access to metrics
+    pub metrics: Option<Access>,
+    /// Access to shutdown. Closed without credentials unless set
+    pub shutdown: Option<Access>,
+    /// Access to info
+    pub info: Option<Access>,
+    /// Access to runtime administration such as the log level. Closed without credentials unless set
+    pub admin: Option<Access>,
+}
+
+/// Only accept client or server error codes, as HaMS does
+fn deserialize_error_status<'de, D>(deserializer: D) -> Result<u16, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let status = u16::deserialize(deserializer)?;
+    if (400..=599).contains(&status) {
+        Ok(status)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "status must be between 400 and 599, got {status}"
+        )))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,6 +467,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_document() {
+        let config: HamsConfig = serde_json::from_str(
+            r#"{"name": "shop", "address": "127.0.0.1:9000", "ready": {"retry_after": 5}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.name, "shop");
+        assert_eq!(config.ready.retry_after, Some(Duration::from_secs(5)));
+        assert_eq!(config.ready.fail_status, DEFAULT_FAIL_STATUS);
+
+        let document = serde_json::to_value(&config).unwrap();
+        assert_eq!(document["address"], "127.0.0.1:9000");
+        assert_eq!(document["ready"]["retry_after"], 5);
+        assert_eq!(document["ready"]["timeout"], 5.0);
+        assert!(document.get("tls").is_none());
+        assert_eq!(document["prefix"], "/hams");
+        assert_eq!(
+            document["signals"],
+            serde_json::json!(["interrupt", "terminate", "quit", "hangup"])
+        );
+
+        let config: HamsConfig = serde_json::from_str(
+            r#"{"prefix": "/ops", "signals": [], "alive": {"probes": [{"type": "manual", "name": "warmup"}]}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.prefix, "/ops");
+        assert!(config.signals.is_empty());
+        assert_eq!(
+            config.alive.probes,
+            vec![ProbeConfig::Manual {
+                name: "warmup".to_string(),
+                valid: true
+            }]
+        );
+    }
+
+    #[test]
+    fn test_document_errors() {
+        let error = serde_json::from_str::<HamsConfig>(r#"{"redy": {}}"#).unwrap_err();
+        assert!(error.to_string().contains("unknown field `redy`"));
+
+        let error = serde_json::from_str::<HamsConfig>(r#"{"metrics": {"push": {}}}"#).unwrap_err();
+        assert!(error.to_string().contains("missing field `url`"));
+
+        let error =
+            serde_json::from_str::<HamsConfig>(r#"{"ready": {"limit_status": 200}}"#).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("status must be between 400 and 599, got 200"));
+    }
+
+    /// Secrets are passed to HaMS but never printed
+    #[test]
+    fn test_secret() {
+        let config: HamsConfig = serde_json::from_str(
+            r#"{"auth": {"bearer_tokens": [{"value": "hunter2"}], "shutdown": "open"},
+                "otel": {"endpoint": "http://collector:4318", "headers": {"x-key": {"env": "KEY"}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.auth.shutdown, Some(Access::Open));
+        assert!(!format!("{config:?}").contains("hunter2"));
+
+        let document = serde_json::to_value(&config).unwrap();
+        assert_eq!(document["auth"]["bearer_tokens"][0]["value"], "hunter2");
+        assert_eq!(document["otel"]["headers"]["x-key"]["env"], "KEY");
+        assert_eq!(document["otel"]["interval"], 15.0);
+    }
+
     #[test]
     fn test_builder() {
         let config = HamsConfigBuilder::default()
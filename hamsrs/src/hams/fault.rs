@@ -0,0 +1,50 @@
+//! Faults injected into probes to rehearse outages
+
+use serde::Serialize;
+
+/// What a fault does to a probe while it is injected
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Fault {
+    /// The probe fails without being checked
+    Fail,
+    /// The probe passes without being checked
+    Pass,
+    /// The probe is checked after a delay
+    Latency {
+        /// Delay before the check in milliseconds
+        latency_ms: u64,
+    },
+    /// The probe fails as if its check returned an error
+    Error {
+        /// Error reported with the result
+        message: String,
+    },
+}
+
+/// Request passed to HaMS, which is the body of the admin fault route
+#[derive(Serialize)]
+pub(crate) struct FaultRequest<'a> {
+    pub(crate) fault: &'a Fault,
+    pub(crate) ttl_secs: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fault_request() {
+        let fault = Fault::Error {
+            message: "say \"timeout\"".to_string(),
+        };
+        let request = FaultRequest {
+            fault: &fault,
+            ttl_secs: 60,
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"fault":{"type":"error","message":"say \"timeout\""},"ttl_secs":60}"#
+        );
+    }
+}
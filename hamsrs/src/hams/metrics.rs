@@ -0,0 +1,323 @@
+//! Metrics registered with HaMS and reported on its metrics endpoint
+
+use log::info;
+
+use crate::{
+    ffi,
+    hamserror::{FFIEnum, HamsError},
+};
+
+/// C strings of label names or values, kept alive while their pointers are passed to HaMS
+struct CStrings {
+    strings: Vec<std::ffi::CString>,
+}
+
+impl CStrings {
+    fn new(values: &[&str]) -> Result<CStrings, HamsError> {
+        let strings = values
+            .iter()
+            .map(|value| std::ffi::CString::new(*value))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CStrings { strings })
+    }
+
+    fn pointers(&self) -> Vec<*const libc::c_char> {
+        self.strings.iter().map(|value| value.as_ptr()).collect()
+    }
+}
+
+/// Label names and values of an exemplar as C strings
+struct ExemplarLabels {
+    names: CStrings,
+    values: CStrings,
+}
+
+impl ExemplarLabels {
+    fn new(exemplar: &[(&str, &str)]) -> Result<ExemplarLabels, HamsError> {
+        let (names, values): (Vec<&str>, Vec<&str>) = exemplar.iter().copied().unzip();
+        Ok(ExemplarLabels {
+            names: CStrings::new(&names)?,
+            values: CStrings::new(&values)?,
+        })
+    }
+}
+
+/// Name and help of a metric as C strings
+fn c_name_help(
+    name: &str,
+    help: &str,
+) -> Result<(std::ffi::CString, std::ffi::CString), HamsError> {
+    Ok((std::ffi::CString::new(name)?, std::ffi::CString::new(help)?))
+}
+
+/// A counter that only goes up. Each combination of label values is a separate series
+#[derive(Debug)]
+pub struct Counter {
+    c: *mut ffi::Counter,
+}
+
+impl Counter {
+    pub(crate) fn new(
+        hams: *mut ffi::Hams,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> Result<Counter, HamsError> {
+        let (c_name, c_help) = c_name_help(name, help)?;
+        let c_labels = CStrings::new(labels)?;
+        let pointers = c_labels.pointers();
+
+        let c = unsafe {
+            ffi::hams_counter_new(
+                hams,
+                c_name.as_ptr(),
+                c_help.as_ptr(),
+                pointers.as_ptr(),
+                pointers.len(),
+            )
+        };
+        if c.is_null() {
+            return Err(HamsError::Message(format!(
+                "Failed to register counter {name}"
+            )));
+        }
+        Ok(Counter { c })
+    }
+
+    /// Add one to the series with the label values
+    pub fn inc(&self, values: &[&str]) -> Result<(), HamsError> {
+        self.inc_by(values, 1.0)
+    }
+
+    /// Add the amount, which cannot be negative, to the series with the label values
+    pub fn inc_by(&self, values: &[&str], amount: f64) -> Result<(), HamsError> {
+        let c_values = CStrings::new(values)?;
+        let pointers = c_values.pointers();
+
+        let retval =
+            unsafe { ffi::hams_counter_inc(self.c, pointers.as_ptr(), pointers.len(), amount) };
+        if retval != FFIEnum::Success as i32 {
+            return Err(HamsError::Message(
+                "Failed to increment counter".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Add the amount to the series with the label values and keep an exemplar of the increase with the
+    /// exemplar labels, eg `trace_id`. Exemplars are served with OpenMetrics
+    pub fn inc_by_with_exemplar(
+        &self,
+        values: &[&str],
+        amount: f64,
+        exemplar: &[(&str, &str)],
+    ) -> Result<(), HamsError> {
+        let c_values = CStrings::new(values)?;
+        let pointers = c_values.pointers();
+        let c_exemplar = ExemplarLabels::new(exemplar)?;
+        let names = c_exemplar.names.pointers();
+        let exemplar_values = c_exemplar.values.pointers();
+
+        let retval = unsafe {
+            ffi::hams_counter_inc_exemplar(
+                self.c,
+                pointers.as_ptr(),
+                pointers.len(),
+                amount,
+                names.as_ptr(),
+                exemplar_values.as_ptr(),
+                names.len(),
+            )
+        };
+        if retval != FFIEnum::Success as i32 {
+            return Err(HamsError::Message(
+                "Failed to increment counter".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Counter {
+    /// Release the counter handle. The metric stays registered
+    fn drop(&mut self) {
+        let retval = unsafe { ffi::hams_counter_free(self.c) };
+        if retval != FFIEnum::Success as i32 {
+            panic!("FAILED to free Counter");
+        }
+
+        info!("Counter freed")
+    }
+}
+
+/// A gauge that can go up and down. Each combination of label values is a separate series
+#[derive(Debug)]
+pub struct Gauge {
+    c: *mut ffi::Gauge,
+}
+
+impl Gauge {
+    pub(crate) fn new(
+        hams: *mut ffi::Hams,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> Result<Gauge, HamsError> {
+        let (c_name, c_help) = c_name_help(name, help)?;
+        let c_labels = CStrings::new(labels)?;
+        let pointers = c_labels.pointers();
+
+        let c = unsafe {
+            ffi::hams_gauge_new(
+                hams,
+                c_name.as_ptr(),
+                c_help.as_ptr(),
+                pointers.as_ptr(),
+                pointers.len(),
+            )
+        };
+        if c.is_null() {
+            return Err(HamsError::Message(format!(
+                "Failed to register gauge {name}"
+            )));
+        }
+        Ok(Gauge { c })
+    }
+
+    /// Set the series with the label values
+    pub fn set(&self, values: &[&str], value: f64) -> Result<(), HamsError> {
+        let c_values = CStrings::new(values)?;
+        let pointers = c_values.pointers();
+
+        let retval =
+            unsafe { ffi::hams_gauge_set(self.c, pointers.as_ptr(), pointers.len(), value) };
+        if retval != FFIEnum::Success as i32 {
+            return Err(HamsError::Message("Failed to set gauge".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Add the amount, which may be negative, to the series with the label values
+    pub fn add(&self, values: &[&str], amount: f64) -> Result<(), HamsError> {
+        let c_values = CStrings::new(values)?;
+        let pointers = c_values.pointers();
+
+        let retval =
+            unsafe { ffi::hams_gauge_add(self.c, pointers.as_ptr(), pointers.len(), amount) };
+        if retval != FFIEnum::Success as i32 {
+            return Err(HamsError::Message("Failed to add to gauge".to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Gauge {
+    /// Release the gauge handle. The metric stays registered
+    fn drop(&mut self) {
+        let retval = unsafe { ffi::hams_gauge_free(self.c) };
+        if retval != FFIEnum::Success as i32 {
+            panic!("FAILED to free Gauge");
+        }
+
+        info!("Gauge freed")
+    }
+}
+
+/// A histogram counting observations in buckets. Each combination of label values is a separate series
+#[derive(Debug)]
+pub struct Histogram {
+    c: *mut ffi::Histogram,
+}
+
+impl Histogram {
+    pub(crate) fn new(
+        hams: *mut ffi::Hams,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        buckets: Option<&[f64]>,
+    ) -> Result<Histogram, HamsError> {
+        let (c_name, c_help) = c_name_help(name, help)?;
+        let c_labels = CStrings::new(labels)?;
+        let pointers = c_labels.pointers();
+
+        let c = unsafe {
+            ffi::hams_histogram_new(
+                hams,
+                c_name.as_ptr(),
+                c_help.as_ptr(),
+                pointers.as_ptr(),
+                pointers.len(),
+                buckets.map_or(std::ptr::null(), |buckets| buckets.as_ptr()),
+                buckets.map_or(0, |buckets| buckets.len()),
+            )
+        };
+        if c.is_null() {
+            return Err(HamsError::Message(format!(
+                "Failed to register histogram {name}"
+            )));
+        }
+        Ok(Histogram { c })
+    }
+
+    /// Record an observation in the series with the label values
+    pub fn observe(&self, values: &[&str], observation: f64) -> Result<(), HamsError> {
+        let c_values = CStrings::new(values)?;
+        let pointers = c_values.pointers();
+
+        let retval = unsafe {
+            ffi::hams_histogram_observe(self.c, pointers.as_ptr(), pointers.len(), observation)
+        };
+        if retval != FFIEnum::Success as i32 {
+            return Err(HamsError::Message(
+                "Failed to observe histogram".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Record an observation in the series with the label values and keep it as the exemplar of its
+    /// bucket with the exemplar labels, eg `trace_id`. Exemplars are served with OpenMetrics
+    pub fn observe_with_exemplar(
+        &self,
+        values: &[&str],
+        observation: f64,
+        exemplar: &[(&str, &str)],
+    ) -> Result<(), HamsError> {
+        let c_values = CStrings::new(values)?;
+        let pointers = c_values.pointers();
+        let c_exemplar = ExemplarLabels::new(exemplar)?;
+        let names = c_exemplar.names.pointers();
+        let exemplar_values = c_exemplar.values.pointers();
+
+        let retval = unsafe {
+            ffi::hams_histogram_observe_exemplar(
+                self.c,
+                pointers.as_ptr(),
+                pointers.len(),
+                observation,
+                names.as_ptr(),
+                exemplar_values.as_ptr(),
+                names.len(),
+            )
+        };
+        if retval != FFIEnum::Success as i32 {
+            return Err(HamsError::Message(
+                "Failed to observe histogram".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Histogram {
+    /// Release the histogram handle. The metric stays registered
+    fn drop(&mut self) {
+        let retval = unsafe { ffi::hams_histogram_free(self.c) };
+        if retval != FFIEnum::Success as i32 {
+            panic!("FAILED to free Histogram");
+        }
+
+        info!("Histogram freed")
+    }
+}
@@ -1,13 +1,17 @@
 pub mod config;
+pub mod fault;
+pub mod metrics;
 
 use config::HamsConfig;
+use fault::{Fault, FaultRequest};
 use libc::c_void;
 use log::info;
+use metrics::{Counter, Gauge, Histogram};
 use tokio_util::sync::CancellationToken;
 
 use crate::{
     ffi::{self, ffitraits::BoxedHealthProbe},
-    hamserror::FFIEnum,
+    hamserror::{last_error_message, FFIEnum},
     probes::Probe,
 };
 
@@ -32,14 +36,14 @@ impl Hams {
         config: HamsConfig,
     ) -> Result<Hams, crate::hamserror::HamsError> {
         info!("Registering HaMS: {} @{}", &config.name, config.address);
-        let c_name = std::ffi::CString::new(config.name)?;
-        let c_address = std::ffi::CString::new(config.address.to_string())?;
+        let c_config = std::ffi::CString::new(serde_json::to_string(&config)?)?;
 
-        let c = unsafe { ffi::hams_new(c_name.as_ptr(), c_address.as_ptr()) };
+        let c = unsafe { ffi::hams_new_from_json(c_config.as_ptr()) };
         if c.is_null() {
-            return Err(crate::hamserror::HamsError::Message(
-                "Failed to create Hams object".to_string(),
-            ));
+            return Err(crate::hamserror::HamsError::Message(format!(
+                "Failed to create Hams object: {}",
+                last_error_message().unwrap_or_default()
+            )));
         }
 
         let ct_box = Box::new(ct.clone());
@@ -77,7 +81,7 @@ impl Hams {
     ///
     pub fn start(&self) -> Result<(), crate::hamserror::HamsError> {
         let retval = unsafe { ffi::hams_start(self.c) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to start HaMS".to_string(),
             ));
@@ -91,7 +95,8 @@ impl Hams {
     /// as well as the prometheus metrics
     pub fn stop(&self) -> Result<(), crate::hamserror::HamsError> {
         let retval = unsafe { ffi::hams_stop(self.c) };
-        if retval == 0 {
+        // Stopping a HaMS that is not running is not an error
+        if retval != FFIEnum::Success as i32 && retval != FFIEnum::NotRunning as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to stop HaMS".to_string(),
             ));
@@ -99,6 +104,71 @@ impl Hams {
         Ok(())
     }
 
+    /// Set the version of the application reported on version and info
+    pub fn set_version(&self, version: &str) -> Result<(), crate::hamserror::HamsError> {
+        let c_version = std::ffi::CString::new(version)?;
+
+        let retval = unsafe { ffi::hams_set_version(self.c, c_version.as_ptr()) };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(
+                "Failed to set version".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Set the git commit and build time of the application reported on info
+    pub fn set_build_info(
+        &self,
+        git_sha: Option<&str>,
+        build_time: Option<&str>,
+    ) -> Result<(), crate::hamserror::HamsError> {
+        let c_git_sha = git_sha.map(std::ffi::CString::new).transpose()?;
+        let c_build_time = build_time.map(std::ffi::CString::new).transpose()?;
+
+        let retval = unsafe {
+            ffi::hams_set_build_info(
+                self.c,
+                c_git_sha.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+                c_build_time
+                    .as_ref()
+                    .map_or(std::ptr::null(), |s| s.as_ptr()),
+            )
+        };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(
+                "Failed to set build info".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Set a label of the application reported on info. A None value removes the label
+    pub fn set_label(
+        &self,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<(), crate::hamserror::HamsError> {
+        let c_key = std::ffi::CString::new(key)?;
+        let c_value = value.map(std::ffi::CString::new).transpose()?;
+
+        let retval = unsafe {
+            ffi::hams_set_label(
+                self.c,
+                c_key.as_ptr(),
+                c_value.as_ref().map_or(std::ptr::null(), |s| s.as_ptr()),
+            )
+        };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(
+                "Failed to set label".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Register the default collector, replacing any registered before.
+    /// Libraries sharing a HaMS should use [Hams::register_collector] with their own name instead
     pub fn register_prometheus(
         &self,
         my_cb: extern "C" fn(state: *const c_void) -> *const libc::c_char,
@@ -106,7 +176,7 @@ impl Hams {
         state: *const c_void,
     ) -> Result<(), crate::hamserror::HamsError> {
         let retval = unsafe { ffi::hams_register_prometheus(self.c, my_cb, my_cb_free, state) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to register prometheus".to_string(),
             ));
@@ -118,7 +188,7 @@ impl Hams {
     /// This will stop the prometheus metrics from being served
     pub fn deregister_prometheus(&self) -> Result<(), crate::hamserror::HamsError> {
         let retval = unsafe { ffi::hams_deregister_prometheus(self.c) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to deregister prometheus".to_string(),
             ));
@@ -126,6 +196,41 @@ impl Hams {
         Ok(())
     }
 
+    /// Register a named collector called on every scrape of the metrics.
+    /// Collectors with other names are kept, a collector already registered with the name is an error
+    pub fn register_collector(
+        &self,
+        name: &str,
+        my_cb: extern "C" fn(state: *const c_void) -> *const libc::c_char,
+        my_cb_free: extern "C" fn(*mut libc::c_char),
+        state: *const c_void,
+    ) -> Result<(), crate::hamserror::HamsError> {
+        let c_name = std::ffi::CString::new(name)?;
+
+        let retval = unsafe {
+            ffi::hams_register_collector(self.c, c_name.as_ptr(), my_cb, my_cb_free, state)
+        };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(format!(
+                "Failed to register collector {name}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Deregister a named collector so its metrics are no longer served
+    pub fn deregister_collector(&self, name: &str) -> Result<(), crate::hamserror::HamsError> {
+        let c_name = std::ffi::CString::new(name)?;
+
+        let retval = unsafe { ffi::hams_deregister_collector(self.c, c_name.as_ptr()) };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(format!(
+                "Failed to deregister collector {name}"
+            )));
+        }
+        Ok(())
+    }
+
     /// Insert a probe into the alive checks
     ///
     /// This will insert a probe into the alive checks AND will pass ownership of the probe to the HaMS
@@ -135,7 +240,7 @@ impl Hams {
 
         let retval = unsafe { ffi::hams_alive_insert(self.c, probe_c) };
 
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to insert probe into alive checks".to_string(),
             ));
@@ -154,7 +259,7 @@ impl Hams {
             BoxedHealthProbe::into_raw(b_probe) as *mut ffi::ffitraits::BoxedHealthProbe<'static>;
 
         let retval = unsafe { ffi::hams_alive_remove(self.c, probe_c) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to remove probe from alive checks".to_string(),
             ));
@@ -172,7 +277,7 @@ impl Hams {
 
         let retval = unsafe { ffi::hams_ready_insert(self.c, probe_c) };
 
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to insert probe into ready checks".to_string(),
             ));
@@ -189,13 +294,172 @@ impl Hams {
 
         let retval = unsafe { ffi::hams_ready_remove(self.c, probe_c) };
 
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to remove probe from ready checks".to_string(),
             ));
         }
         Ok(())
     }
+
+    /// Take the service out of rotation. Ready fails with the reason until maintenance is cleared or
+    /// the expiry has passed. Alive is unaffected
+    pub fn set_maintenance(
+        &self,
+        reason: &str,
+        expiry: Option<std::time::Duration>,
+    ) -> Result<(), crate::hamserror::HamsError> {
+        let c_reason = std::ffi::CString::new(reason)?;
+        let expiry_secs = expiry.map_or(0, |expiry| expiry.as_secs().max(1));
+
+        let retval = unsafe { ffi::hams_set_maintenance(self.c, c_reason.as_ptr(), expiry_secs) };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(
+                "Failed to set maintenance".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// End maintenance and return ready to its probes
+    pub fn clear_maintenance(&self) -> Result<(), crate::hamserror::HamsError> {
+        let retval = unsafe { ffi::hams_set_maintenance(self.c, std::ptr::null(), 0) };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(
+                "Failed to clear maintenance".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// List the probes of every check as JSON with their type, configuration and last result
+    pub fn probe_list(&self) -> Result<String, crate::hamserror::HamsError> {
+        let c_list = unsafe { ffi::hams_probe_list(self.c) };
+        if c_list.is_null() {
+            return Err(crate::hamserror::HamsError::Message(
+                "Failed to list probes".to_string(),
+            ));
+        }
+        let list = unsafe { std::ffi::CStr::from_ptr(c_list) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { ffi::hams_string_free(c_list) };
+        Ok(list)
+    }
+
+    /// Enable or disable a manual probe of the alive or ready check by name
+    pub fn probe_set_valid(
+        &self,
+        check: &str,
+        probe: &str,
+        valid: bool,
+    ) -> Result<(), crate::hamserror::HamsError> {
+        let c_check = std::ffi::CString::new(check)?;
+        let c_probe = std::ffi::CString::new(probe)?;
+
+        let retval =
+            unsafe { ffi::hams_probe_set_valid(self.c, c_check.as_ptr(), c_probe.as_ptr(), valid) };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(format!(
+                "Failed to set probe {probe} of {check}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check a single probe of the alive or ready check by name now
+    pub fn probe_check(
+        &self,
+        check: &str,
+        probe: &str,
+    ) -> Result<bool, crate::hamserror::HamsError> {
+        let c_check = std::ffi::CString::new(check)?;
+        let c_probe = std::ffi::CString::new(probe)?;
+
+        match unsafe { ffi::hams_probe_check(self.c, c_check.as_ptr(), c_probe.as_ptr()) } {
+            1 => Ok(true),
+            0 => Ok(false),
+            _ => Err(crate::hamserror::HamsError::Message(format!(
+                "Failed to check probe {probe} of {check}"
+            ))),
+        }
+    }
+
+    /// Inject a fault into a probe of the alive or ready check by name. The probe reverts to its real
+    /// check once the ttl has passed, rounded up to whole seconds
+    pub fn inject_fault(
+        &self,
+        check: &str,
+        probe: &str,
+        fault: &Fault,
+        ttl: std::time::Duration,
+    ) -> Result<(), crate::hamserror::HamsError> {
+        let c_check = std::ffi::CString::new(check)?;
+        let c_probe = std::ffi::CString::new(probe)?;
+        let request = FaultRequest {
+            fault,
+            ttl_secs: ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0),
+        };
+        let c_fault = std::ffi::CString::new(serde_json::to_string(&request)?)?;
+
+        let retval = unsafe {
+            ffi::hams_inject_fault(self.c, c_check.as_ptr(), c_probe.as_ptr(), c_fault.as_ptr())
+        };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(format!(
+                "Failed to inject fault into probe {probe} of {check}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Revert a probe of the alive or ready check to its real check before its fault expires
+    pub fn clear_fault(&self, check: &str, probe: &str) -> Result<(), crate::hamserror::HamsError> {
+        let c_check = std::ffi::CString::new(check)?;
+        let c_probe = std::ffi::CString::new(probe)?;
+
+        let retval = unsafe {
+            ffi::hams_inject_fault(self.c, c_check.as_ptr(), c_probe.as_ptr(), std::ptr::null())
+        };
+        if retval != FFIEnum::Success as i32 {
+            return Err(crate::hamserror::HamsError::Message(format!(
+                "Failed to clear fault of probe {probe} of {check}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Register a counter reported on the HaMS metrics endpoint
+    pub fn counter(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> Result<Counter, crate::hamserror::HamsError> {
+        Counter::new(self.c, name, help, labels)
+    }
+
+    /// Register a gauge reported on the HaMS metrics endpoint
+    pub fn gauge(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+    ) -> Result<Gauge, crate::hamserror::HamsError> {
+        Gauge::new(self.c, name, help, labels)
+    }
+
+    /// Register a histogram reported on the HaMS metrics endpoint. HaMS uses its default buckets when none
+    /// are given
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[&str],
+        buckets: Option<&[f64]>,
+    ) -> Result<Histogram, crate::hamserror::HamsError> {
+        Histogram::new(self.c, name, help, labels, buckets)
+    }
 }
 
 /// This trait automatically handles the deallocation of the hams api when the Hams object
@@ -204,7 +468,7 @@ impl Drop for Hams {
     /// Releaes the HaMS ffi on drop
     fn drop(&mut self) {
         let retval = unsafe { ffi::hams_free(self.c) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             panic!("FAILED to free HaMS");
         }
 
@@ -230,6 +494,151 @@ mod tests {
         assert!(ct.is_cancelled());
     }
 
+    /// Create HaMS with options of the full configuration, which HaMS validates
+    #[test]
+    fn test_hams_options() {
+        let config: HamsConfig =
+            serde_json::from_str(r#"{"name": "shop", "ready": {"retry_after": 5}}"#).unwrap();
+        Hams::new(CancellationToken::new(), config).unwrap();
+
+        let config: HamsConfig = serde_json::from_str(r#"{"prefix": "/hams/"}"#).unwrap();
+        let error = Hams::new(CancellationToken::new(), config)
+            .err()
+            .expect("Invalid options should be rejected");
+        assert!(error.to_string().contains("prefix must be / followed by"));
+    }
+
+    /// Body of the reply to a GET request to HaMS once it is served
+    fn get(address: &str, path: &str) -> String {
+        use std::io::{Read, Write};
+
+        let mut stream = (0..50)
+            .find_map(|_| {
+                std::net::TcpStream::connect(address)
+                    .map_err(|_| std::thread::sleep(std::time::Duration::from_millis(100)))
+                    .ok()
+            })
+            .expect("HaMS should be listening");
+        write!(
+            stream,
+            "GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n"
+        )
+        .unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        reply
+            .split_once("\r\n\r\n")
+            .map(|(_, body)| body.to_string())
+            .unwrap_or_default()
+    }
+
+    /// A document setting every option is accepted by HaMS, and the configuration HaMS reports reads
+    /// back as the same configuration, so the two schemas agree
+    #[test]
+    fn test_config_round_trip() {
+        let certs = concat!(env!("CARGO_MANIFEST_DIR"), "/../hams/tests/certs");
+        let mut document = serde_json::json!({
+            "address": "127.0.0.1:18081",
+            "name": "shop",
+            "prefix": "/ops/health",
+            "signals": ["terminate"],
+            "alive": {
+                "fail_status": 500,
+                "retry_after": 5,
+                "max_concurrent": 2,
+                "on_limit": "stale",
+                "limit_status": 503,
+                "timeout": 1.5,
+                "probes": [{"type": "manual", "name": "warmup", "valid": false}]
+            },
+            "ready": {"probes": [{"type": "manual", "name": "database"}]},
+            "access_log": {
+                "enabled": false,
+                "level": "WARN",
+                "target": "shop::access",
+                "probe_level": "TRACE",
+                "probe_target": "shop::probe"
+            },
+            "tls": {
+                "cert": format!("{certs}/server.pem"),
+                "key": format!("{certs}/server.key"),
+                "client_ca": format!("{certs}/ca.pem")
+            },
+            "auth": {
+                "bearer_tokens": [{"env": "SHOP_TOKEN"}],
+                "basic": [{"username": "ops", "password": {"file": "/run/secrets/ops"}}],
+                "client_names": ["ops"],
+                "checks": "open",
+                "verbose": "redact",
+                "metrics": "authenticated",
+                "shutdown": "authenticated",
+                "info": "open",
+                "admin": "authenticated"
+            },
+            "metrics": {
+                "timeout": 2.5,
+                "serve_last_good": true,
+                "process": true,
+                "push": {
+                    "url": "http://127.0.0.1:9/",
+                    "job": "batch",
+                    "instance": "one",
+                    "interval": 60,
+                    "delete_on_exit": false,
+                    "timeout": 0.5
+                },
+                "statsd": {
+                    "address": "127.0.0.1:8125",
+                    "flavor": "statsd",
+                    "prefix": "shop",
+                    "interval": 60,
+                    "packet_size": 512
+                }
+            },
+            "otel": {
+                "endpoint": "http://127.0.0.1:4318",
+                "interval": 60,
+                "headers": {"x-key": {"env": "OTEL_KEY"}},
+                "timeout": 0.5
+            }
+        });
+        let config: HamsConfig = serde_json::from_value(document.clone()).unwrap();
+        Hams::new(CancellationToken::new(), config).unwrap();
+
+        // Served without TLS so the reported configuration can be read without a TLS client
+        document.as_object_mut().unwrap().remove("tls");
+        let config: HamsConfig = serde_json::from_value(document).unwrap();
+        let hams = Hams::new(CancellationToken::new(), config.clone()).unwrap();
+        hams.start().unwrap();
+        let info: serde_json::Value =
+            serde_json::from_str(&get("127.0.0.1:18081", "/ops/health/info")).unwrap();
+        hams.stop().unwrap();
+
+        let reported: HamsConfig = serde_json::from_value(info["config"].clone()).unwrap();
+        // Builds of HaMS without the otel feature ignore the export
+        assert!(reported.otel.is_none() || reported.otel == config.otel);
+        assert_eq!(
+            reported,
+            HamsConfig {
+                otel: reported.otel.clone(),
+                ..config
+            }
+        );
+    }
+
+    /// Set the application details reported by HaMS
+    #[test]
+    fn test_hams_app_info() {
+        let hams = Hams::new(CancellationToken::new(), HamsConfig::default()).unwrap();
+
+        hams.set_version("1.2.3").unwrap();
+        hams.set_build_info(Some("abc123"), None).unwrap();
+        hams.set_label("region", Some("eu")).unwrap();
+        hams.set_label("region", None).unwrap();
+        hams.set_version("bad\0version")
+            .expect_err("Nul in version should be rejected");
+    }
+
     /// Add and remove probes from HaMS
     #[test]
     fn add_probes_to_hams_alive() {
@@ -255,6 +664,129 @@ mod tests {
             .expect("Should be able to remove the probe");
     }
 
+    /// Set and clear maintenance
+    #[test]
+    fn test_hams_maintenance() {
+        let hams = Hams::new(CancellationToken::new(), HamsConfig::default()).unwrap();
+
+        hams.set_maintenance("upgrade", Some(std::time::Duration::from_secs(60)))
+            .unwrap();
+        hams.set_maintenance("upgrade", None).unwrap();
+        hams.clear_maintenance().unwrap();
+        hams.set_maintenance("bad\0reason", None)
+            .expect_err("Nul in reason should be rejected");
+    }
+
+    /// List, disable and check probes by name
+    #[test]
+    fn test_hams_probe_admin() {
+        let hams = Hams::new(CancellationToken::new(), HamsConfig::default()).unwrap();
+        let probe = crate::probes::ProbeManual::new("rotation", true).unwrap();
+        hams.ready_insert(probe.clone()).unwrap();
+
+        hams.probe_set_valid("ready", "rotation", false).unwrap();
+        assert!(!probe.check().unwrap());
+        assert!(!hams.probe_check("ready", "rotation").unwrap());
+        hams.probe_check("ready", "missing")
+            .expect_err("Missing probe should be an error");
+        hams.probe_set_valid("other", "rotation", true)
+            .expect_err("Missing check should be an error");
+
+        let list = hams.probe_list().unwrap();
+        assert!(list.contains(r#""name":"rotation","type":"manual""#));
+    }
+
+    /// Inject and clear faults on a probe through FFI
+    #[test]
+    fn test_hams_faults() {
+        let hams = Hams::new(CancellationToken::new(), HamsConfig::default()).unwrap();
+        let probe = crate::probes::ProbeManual::new("faulty", true).unwrap();
+        hams.alive_insert(probe.clone()).unwrap();
+
+        let ttl = std::time::Duration::from_secs(60);
+        hams.inject_fault("alive", "faulty", &Fault::Fail, ttl)
+            .unwrap();
+        assert!(!hams.probe_check("alive", "faulty").unwrap());
+        assert!(probe.check().unwrap());
+        assert!(hams.probe_list().unwrap().contains("injected fail until "));
+
+        hams.clear_fault("alive", "faulty").unwrap();
+        assert!(hams.probe_check("alive", "faulty").unwrap());
+
+        hams.inject_fault("alive", "missing", &Fault::Pass, ttl)
+            .expect_err("Missing probe should be an error");
+        hams.inject_fault("alive", "faulty", &Fault::Pass, std::time::Duration::ZERO)
+            .expect_err("Zero ttl should be an error");
+    }
+
+    extern "C" fn collector(_state: *const c_void) -> *const libc::c_char {
+        std::ffi::CString::new("library_jobs 1").unwrap().into_raw()
+    }
+
+    extern "C" fn collector_free(ptr: *mut libc::c_char) {
+        if !ptr.is_null() {
+            drop(unsafe { std::ffi::CString::from_raw(ptr) });
+        }
+    }
+
+    /// Register named collectors alongside the default collector
+    #[test]
+    fn test_hams_collectors() {
+        let hams = Hams::new(CancellationToken::new(), HamsConfig::default()).unwrap();
+
+        hams.register_prometheus(collector, collector_free, std::ptr::null())
+            .expect("Should register the default collector");
+        hams.register_collector("library", collector, collector_free, std::ptr::null())
+            .expect("Should register a named collector");
+        hams.register_collector("library", collector, collector_free, std::ptr::null())
+            .expect_err("Should not register the same name twice");
+
+        hams.deregister_collector("library")
+            .expect("Should deregister the named collector");
+        hams.deregister_collector("library")
+            .expect_err("Should not deregister a missing collector");
+        hams.deregister_prometheus()
+            .expect("Should deregister the default collector");
+    }
+
+    /// Register and update metrics through FFI
+    #[test]
+    fn test_hams_metrics() {
+        let hams = Hams::new(CancellationToken::new(), HamsConfig::default()).unwrap();
+
+        let counter = hams
+            .counter("jobs_total", "Jobs run", &["queue"])
+            .expect("Should register a counter");
+        counter.inc(&["fast"]).unwrap();
+        counter.inc_by(&["fast"], 2.0).unwrap();
+        counter
+            .inc_by(&["fast"], -1.0)
+            .expect_err("Counters cannot go down");
+        counter
+            .inc(&[])
+            .expect_err("Label values must match the labels");
+
+        let gauge = hams.gauge("queue_items", "Items queued", &[]).unwrap();
+        gauge.set(&[], 4.0).unwrap();
+        gauge.add(&[], -1.5).unwrap();
+
+        let histogram = hams
+            .histogram("latency_seconds", "Job latency", &[], Some(&[0.1, 1.0]))
+            .unwrap();
+        histogram.observe(&[], 0.5).unwrap();
+        histogram
+            .observe_with_exemplar(&[], 0.05, &[("trace_id", "4bf92f3577b34da6")])
+            .unwrap();
+        counter
+            .inc_by_with_exemplar(&["fast"], 1.0, &[("trace-id", "4bf92f3577b34da6")])
+            .expect_err("Exemplar label names must be valid");
+        hams.histogram("latency_seconds", "Job latency", &[], None)
+            .expect_err("Buckets must match the registered histogram");
+
+        hams.counter("hams_jobs_total", "Reserved", &[])
+            .expect_err("The hams_ prefix is reserved");
+    }
+
     /// Add and remove probes from HaMS ready and alive
     #[test]
     fn add_probes_to_hams_ready() {
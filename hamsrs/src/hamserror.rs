@@ -1,18 +1,58 @@
 use std::ffi::NulError;
 use thiserror::Error;
 
-use crate::hams::config::HamsConfigBuilderError;
+use crate::{ffi, hams::config::HamsConfigBuilderError};
 
+/// Return codes of the HaMS C API, mirroring hams.h
 #[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FFIEnum {
     /// No Error
     Success = 1,
     /// Null error
     NullError = 0,
-    /// Unknown error
+    /// Unknown error, including a panic
     UnknownError = -1,
     /// CString error
     CStringError = -2,
+    /// Service is already running
+    AlreadyRunning = -3,
+    /// Service is not running
+    NotRunning = -4,
+    /// An item with the same name is already registered
+    AlreadyExists = -5,
+    /// A named item does not exist
+    NotFound = -6,
+    /// An argument is not valid
+    InvalidArgument = -7,
+    /// A check or probe did not pass
+    CheckFailed = -8,
+    /// A callback failed
+    CallbackFailed = -9,
+    /// The service was cancelled
+    Cancelled = -10,
+    /// An IO or thread error
+    ThreadError = -11,
+    /// A lock was poisoned
+    Poisoned = -12,
+    /// The caller is not authenticated
+    Unauthorized = -13,
+}
+
+/// The message of the last error of a HaMS call on this thread, if any
+pub(crate) fn last_error_message() -> Option<String> {
+    let length = unsafe { ffi::hams_last_error_length() };
+    if length <= 0 {
+        return None;
+    }
+    let mut buffer = vec![0u8; length as usize];
+    let written =
+        unsafe { ffi::hams_last_error_message(buffer.as_mut_ptr() as *mut libc::c_char, length) };
+    if written <= 0 {
+        return None;
+    }
+    buffer.truncate(written as usize - 1);
+    Some(String::from_utf8_lossy(&buffer).into_owned())
 }
 
 // Error type for handling errors on FFI calls
@@ -35,6 +75,9 @@ pub enum HamsError {
     /// Try conversion from int
     #[error("Try conversion from int")]
     TryFromIntError(#[from] std::num::TryFromIntError),
+    /// Error when serialising JSON passed to HaMS
+    #[error("JSON error: {0}")]
+    JsonError(#[from] serde_json::Error),
 }
 
 // impl fmt::Display for HamsError {
@@ -1,7 +1,8 @@
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 
 use ffi_log2::LogParam;
-use hamserror::HamsError;
+use hamserror::{FFIEnum, HamsError};
+use log::LevelFilter;
 
 pub mod ffi;
 pub mod hams;
@@ -28,8 +29,120 @@ pub fn hams_version() -> String {
 
 /// Initialise logging
 pub fn hams_logger_init(param: LogParam) -> Result<(), HamsError> {
-    if unsafe { ffi::hams_logger_init(param) } == 0 {
+    if unsafe { ffi::hams_logger_init(param) } != FFIEnum::Success as i32 {
         return Err(HamsError::Message("Logging did not register".to_string()));
     }
     Ok(())
 }
+
+/// Change the log level of HaMS at runtime
+///
+/// Without a target the default level is changed, otherwise the level of the target and the targets
+/// nested below it. A target without a level removes its override.
+pub fn hams_set_log_level(
+    level: Option<LevelFilter>,
+    target: Option<&str>,
+) -> Result<(), HamsError> {
+    let level = level
+        .map(|level| CString::new(level.as_str()))
+        .transpose()?;
+    let target = target.map(CString::new).transpose()?;
+    let retval = unsafe {
+        ffi::hams_set_log_level(
+            level
+                .as_ref()
+                .map_or(std::ptr::null(), |level| level.as_ptr()),
+            target
+                .as_ref()
+                .map_or(std::ptr::null(), |target| target.as_ptr()),
+        )
+    };
+    if retval != FFIEnum::Success as i32 {
+        return Err(HamsError::Message("Log level was not changed".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_abi_version() {
+        assert_eq!(
+            unsafe { ffi::hams_probe_abi_version() },
+            ffi::ffitraits::PROBE_ABI_VERSION
+        );
+    }
+
+    /// Records logged through a host logger that only enables info and above
+    static CAPTURED: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    struct CaptureLogger;
+
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.level() <= log::Level::Info
+        }
+
+        fn log(&self, record: &log::Record) {
+            CAPTURED
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", record.target(), record.args()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Whether HaMS logged the access of a GET request to alive once it is served
+    fn alive_logged(address: &str) -> bool {
+        use std::io::{Read, Write};
+
+        let mut stream = (0..50)
+            .find_map(|_| {
+                std::net::TcpStream::connect(address)
+                    .map_err(|_| std::thread::sleep(std::time::Duration::from_millis(100)))
+                    .ok()
+            })
+            .expect("HaMS should be listening");
+        stream
+            .write_all(b"GET /hams/alive HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        stream.read_to_end(&mut Vec::new()).unwrap();
+        CAPTURED
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|line| line.starts_with("hams::access::probe method=GET path=/hams/alive"))
+    }
+
+    /// A level raised in HaMS at runtime is passed by the enabled check of the host
+    #[test]
+    fn test_raised_level_reaches_host() {
+        log::set_logger(&CaptureLogger).unwrap();
+        log::set_max_level(LevelFilter::Info);
+        hams_logger_init(ffi_log2::log_param()).unwrap();
+
+        let config = hams::config::HamsConfig {
+            address: "127.0.0.1:18079".parse().unwrap(),
+            ..Default::default()
+        };
+        let hams = Hams::new(tokio_util::sync::CancellationToken::new(), config).unwrap();
+        hams.start().unwrap();
+
+        assert!(!alive_logged("127.0.0.1:18079"));
+        hams_set_log_level(Some(LevelFilter::Debug), Some("hams::access")).unwrap();
+        assert!(alive_logged("127.0.0.1:18079"));
+
+        hams.stop().unwrap();
+        hams_set_log_level(None, Some("hams::access")).unwrap();
+    }
+
+    #[test]
+    fn test_set_log_level() {
+        assert!(hams_set_log_level(Some(LevelFilter::Debug), Some("hamsrs::test")).is_ok());
+        assert!(hams_set_log_level(None, Some("hamsrs::test")).is_ok());
+        assert!(hams_set_log_level(None, None).is_err());
+    }
+}
@@ -27,6 +27,14 @@ impl HealthProbe for ProbeCustom {
     fn check(&self, _time: time_t) -> i32 {
         self.valid.load(Ordering::Relaxed) as i32
     }
+
+    fn describe(&self) -> *mut c_char {
+        CString::new(r#"{"type":"custom"}"#).unwrap().into_raw()
+    }
+
+    fn set_valid(&self, _valid: bool) -> i32 {
+        0
+    }
 }
 
 impl Probe for ProbeCustom {
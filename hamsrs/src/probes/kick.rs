@@ -5,7 +5,10 @@ use log::{error, info};
 use super::Probe;
 
 use crate::ffi::ffitraits::BoxedHealthProbe;
-use crate::{ffi, hamserror::HamsError};
+use crate::{
+    ffi,
+    hamserror::{FFIEnum, HamsError},
+};
 
 #[derive(Debug)]
 pub struct ProbeKickInner {
@@ -16,7 +19,7 @@ impl Drop for ProbeKickInner {
     fn drop(&mut self) {
         let retval = unsafe { ffi::probe_kick_free(self.c) };
 
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             error!("Failed to free Probe object");
         }
 
@@ -44,7 +47,7 @@ impl ProbeKickInner {
     pub fn kick(&self) -> Result<(), HamsError> {
         let retval = unsafe { ffi::probe_kick_kick(self.c) };
 
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(HamsError::Message("Failed to kick Probe".to_string()));
         }
         Ok(())
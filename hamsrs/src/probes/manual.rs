@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use crate::{ffi::ffitraits::BoxedHealthProbe, hamserror::HamsError};
+use crate::{
+    ffi::ffitraits::BoxedHealthProbe,
+    hamserror::{FFIEnum, HamsError},
+};
 use log::info;
 
 use crate::ffi;
@@ -18,7 +21,7 @@ impl Drop for ProbeManualInner {
     /// Releaes the HaMS ffi on drop
     fn drop(&mut self) {
         let retval = unsafe { ffi::probe_manual_free(self.c) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             panic!("FAILED to free Probe");
         }
 
@@ -47,7 +50,7 @@ impl ProbeManualInner {
     /// Enable the probe
     pub fn enable(&self) -> Result<(), crate::hamserror::HamsError> {
         let retval = unsafe { ffi::probe_manual_enable(self.c, true) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to enable Probe".to_string(),
             ));
@@ -57,7 +60,7 @@ impl ProbeManualInner {
     /// Disable the probe
     pub fn disable(&self) -> Result<(), crate::hamserror::HamsError> {
         let retval = unsafe { ffi::probe_manual_disable(self.c) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to disable Probe".to_string(),
             ));
@@ -68,7 +71,7 @@ impl ProbeManualInner {
     /// Toggle the probe
     pub fn toggle(&self) -> Result<(), crate::hamserror::HamsError> {
         let retval = unsafe { ffi::probe_manual_toggle(self.c) };
-        if retval == 0 {
+        if retval != FFIEnum::Success as i32 {
             return Err(crate::hamserror::HamsError::Message(
                 "Failed to toggle Probe".to_string(),
             ));
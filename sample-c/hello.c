@@ -30,7 +30,8 @@ int main(void)
         c_log_enabled,
         c_log_log,
         c_log_flush,
-        ExternCLevelFilter_Info
+        ExternCLevelFilter_Info,
+        NULL
         };
 
 
@@ -45,13 +46,17 @@ int main(void)
     }
 
     int start_reply = hams_start(hams);
-    if (!start_reply) {
-         printf("FAILED to start");
+    if (start_reply != FFIEnum_Success) {
+        char message[256];
+        if (hams_last_error_message(message, sizeof(message)) <= 0) {
+            message[0] = '\0';
+        }
+        printf("FAILED to start (%d): %s", start_reply, message);
         return 2;
     }
 
     int free_reply = hams_free(hams);
-    if (!free_reply) {
+    if (free_reply != FFIEnum_Success) {
          printf("FAILED to free");
         return 3;
     }
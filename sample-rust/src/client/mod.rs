@@ -28,7 +28,7 @@ pub fn run_client_test() -> std::result::Result<(), error::MyError> {
             .await?;
         println!("{resp:#?}");
 
-        let resp = reqwest::get("http://localhost:8079/hams/alive_verbose")
+        let resp = reqwest::get("http://localhost:8079/hams/alive?verbose")
             .await?
             .text()
             // .json::<HashMap<String, String>>()
@@ -42,7 +42,7 @@ pub fn run_client_test() -> std::result::Result<(), error::MyError> {
             .await?;
         println!("{resp:#?}");
 
-        let resp = reqwest::get("http://localhost:8079/hams/ready_verbose")
+        let resp = reqwest::get("http://localhost:8079/hams/ready?verbose")
             .await?
             .text()
             // .json::<HashMap<String, String>>()
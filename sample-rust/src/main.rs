@@ -107,14 +107,17 @@ pub fn main() -> ExitCode {
             let hams = Hams::new(ct.clone(), config.hams).unwrap();
             println!("New HaMS CREATED");
 
+            hams.set_version(VERSION).expect("set version");
+
             let state_string = String::from("Hello from Rust PROMETHEUS");
 
-            hams.register_prometheus(
+            hams.register_collector(
+                NAME,
                 prometheus_response,
                 prometheus_response_free,
                 &state_string as *const String as *const c_void,
             )
-            .expect("register prometheus CBs");
+            .expect("register prometheus collector");
 
             hams.alive_insert(probe0.clone())
                 .expect("insert probe0 into alive");
@@ -135,7 +138,8 @@ pub fn main() -> ExitCode {
             hams.alive_remove(&probe0)
                 .expect("remove probe0 from alive");
 
-            hams.deregister_prometheus().expect("deregister prometheus");
+            hams.deregister_collector(NAME)
+                .expect("deregister prometheus collector");
 
             run_client_test().expect("run client test");
 
@@ -7,7 +7,7 @@ pub extern "C" fn prometheus_response(ptr: *const c_void) -> *const libc::c_char
 
     let state = unsafe { &*(ptr as *const String) };
 
-    let prometheus = format!("test {state}");
+    let prometheus = format!("test{{state=\"{state}\"}} 1");
     let c_str_prometheus = std::ffi::CString::new(prometheus).unwrap();
 
     c_str_prometheus.into_raw()
@@ -46,6 +46,7 @@ export const handler = (argv: Arguments<Options>): void => {
         log: ffi.Function('void', [ExternCRecordPtr]),
         flush: ffi.Function('void', []),
         level: ref.types.uint,
+        set_level: ref.refType(ref.types.void),
     });
 
 
@@ -125,7 +126,8 @@ export const handler = (argv: Arguments<Options>): void => {
         enabled: c_log_enabled,
         log: c_log_log,
         flush: c_log_flush,
-        level: 3
+        level: 3,
+        set_level: ref.NULL
     });
     console.log("Ready to register LOGGING");
     var log_reply = ffilib.hams_logger_init(myLog.ref());